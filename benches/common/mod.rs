@@ -0,0 +1,62 @@
+// Timing for the benchmarks, which use `harness = false` and only the
+// standard library, so they need no extra dependencies: `cargo bench` builds
+// them optimized, and each one prints the median time of its cases. Each
+// benchmark uses only some of these.
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+// Run `case` enough times to take about half a second, and return the median
+// time of a run
+pub fn median_time<T>(mut case: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    std::hint::black_box(case());
+    let once = start.elapsed().max(Duration::from_micros(1));
+    let runs = (Duration::from_millis(500).as_nanos() / once.as_nanos()).clamp(5, 1000) as usize;
+    let mut times: Vec<Duration> = (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            std::hint::black_box(case());
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[runs / 2]
+}
+
+pub fn report(name: &str, time: Duration) {
+    println!("{:<48} {:>12.3?}", name, time);
+}
+
+// `count` lines of `len` bytes, in no particular order, from a fixed seed so
+// every run sees the same text. Line `i` is the same as line `i % distinct`.
+pub fn lines(count: usize, distinct: usize, len: usize) -> Vec<u8> {
+    let mut text = Vec::with_capacity(count * (len + 1));
+    for i in 0..count {
+        push_line(&mut text, i % distinct, len);
+    }
+    text
+}
+
+// The lines numbered `range`, as `lines` would have them, all different
+pub fn distinct_lines(range: std::ops::Range<usize>, len: usize) -> Vec<u8> {
+    let mut text = Vec::with_capacity(range.len() * (len + 1));
+    for i in range {
+        push_line(&mut text, i, len);
+    }
+    text
+}
+
+fn push_line(text: &mut Vec<u8>, i: usize, len: usize) {
+    let mut x = (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    let start = text.len();
+    while text.len() - start < len {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        let hex = format!("{:016x}", x);
+        let take = hex.len().min(len - (text.len() - start));
+        text.extend_from_slice(&hex.as_bytes()[..take]);
+    }
+    text.push(b'\n');
+}
@@ -0,0 +1,35 @@
+// An intersection of 20 operands that's empty after the second. Hashing every
+// operand anyway, as `IntersectSet::operate` once did, against stopping as
+// soon as `operate_early_exit` says the set is settled.
+
+mod common;
+
+use minimal::{Config, IntersectSet, IntersectSetExt, LineSet, SetExpression, SliceSet};
+
+const OPERANDS: usize = 20;
+
+fn main() {
+    let config = Config::default();
+    for &lines in &[1_000, 100_000] {
+        let texts: Vec<Vec<u8>> = (0..OPERANDS).map(|k| common::distinct_lines(lines * k..lines * (k + 1), 24)).collect();
+        let every = common::median_time(|| {
+            let mut set = IntersectSet::init(&texts[0], &config);
+            for text in &texts[1..] {
+                let other = SliceSet::init_from_slice(text, &config);
+                set.retain(|line| other.contains(line));
+            }
+            set.len()
+        });
+        common::report(&format!("{} lines each: every operand", lines), every);
+        let early = common::median_time(|| {
+            let mut set = IntersectSet::init(&texts[0], &config);
+            for text in &texts[1..] {
+                if set.operate_early_exit(text, &config) {
+                    break;
+                }
+            }
+            set.len()
+        });
+        common::report(&format!("{} lines each: operate_early_exit", lines), early);
+    }
+}
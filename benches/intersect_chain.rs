@@ -0,0 +1,36 @@
+// An intersection of 30 operands that share most of their lines, so the
+// first operand's lines survive and are looked up again for every operand:
+// `IntersectSet` hashes each surviving line every time, and
+// `CachedIntersectSet` hashes it once.
+
+mod common;
+
+use minimal::{CachedIntersectSet, Config, IntersectSet, IntersectSetExt, SetExpression};
+
+const OPERANDS: usize = 30;
+
+fn main() {
+    let config = Config::default();
+    for &(lines, len) in &[(10_000, 24), (10_000, 200), (100_000, 24)] {
+        let first = common::distinct_lines(0..lines, len);
+        // Each later operand has every line of the first, and as many others
+        let operands: Vec<Vec<u8>> =
+            (1..OPERANDS).map(|k| [first.clone(), common::distinct_lines(lines * k..lines * (k + 1), len)].concat()).collect();
+        let plain = common::median_time(|| {
+            let mut set = IntersectSet::init(&first, &config);
+            for text in &operands {
+                set.operate(text, &config);
+            }
+            set.result_len()
+        });
+        common::report(&format!("{} lines of {} bytes: IntersectSet", lines, len), plain);
+        let cached = common::median_time(|| {
+            let mut set = CachedIntersectSet::init(&first, &config);
+            for text in &operands {
+                set.operate(text, &config);
+            }
+            set.result_len()
+        });
+        common::report(&format!("{} lines of {} bytes: CachedIntersectSet", lines, len), cached);
+    }
+}
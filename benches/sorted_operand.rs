@@ -0,0 +1,86 @@
+// `IntersectSet::operate` and `CachedIntersectSet::operate` search an operand
+// of at most `SORTED_OPERAND_LINES` lines as a sorted `Vec`, and hash a larger
+// one. This times both ways of looking the set's lines up in operands of each
+// size, for sets of a few sizes, to find where hashing starts to win: first
+// as an `IntersectSet` hashes, every line of the set and the operand, then as
+// a `CachedIntersectSet` does, with the set's hashes worked out once, when
+// it's read. Every way finds the same lines; only the time differs. The lines
+// are counted rather than retained, so that copying the set for each run
+// isn't timed too.
+
+mod common;
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    hash::{BuildHasherDefault, Hash, Hasher},
+};
+
+use minimal::{split_lines, IntersectSet, IntersectSetExt, SliceSet, TextSlice};
+
+// A line and its hash, hashed as just the hash, as `CachedIntersectSet` keeps
+// its members
+#[derive(PartialEq, Eq)]
+struct Hashed<'a> {
+    hash: u64,
+    line: &'a TextSlice,
+}
+
+impl<'a> Hashed<'a> {
+    fn new(line: &'a TextSlice) -> Self {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        Hashed { hash: hasher.finish(), line }
+    }
+}
+
+impl<'a> Hash for Hashed<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+#[derive(Default)]
+struct PassThrough(u64);
+
+impl Hasher for PassThrough {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, _: &[u8]) {
+        unreachable!("only hashes are hashed")
+    }
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}
+
+fn main() {
+    for &set_lines in &[16, 100, 1_000, 10_000] {
+        let first = common::distinct_lines(0..set_lines, 24);
+        let set = IntersectSet::init(&first, &Default::default());
+        let cached: Vec<Hashed> = set.iter().map(|line| Hashed::new(line)).collect();
+        for &operand_lines in &[2, 4, 8, 12, 16, 24, 32, 64, 128, 512] {
+            // Half the operand's lines are in the set
+            let operand =
+                [common::distinct_lines(0..operand_lines / 2, 24), common::distinct_lines(set_lines..set_lines + operand_lines / 2, 24)]
+                    .concat();
+            let sorted = common::median_time(|| {
+                let mut lines: Vec<&TextSlice> = split_lines(&operand, b'\n').collect();
+                lines.sort_unstable();
+                set.iter().filter(|x| lines.binary_search(x).is_ok()).count()
+            });
+            let hashed = common::median_time(|| {
+                let other: SliceSet = split_lines(&operand, b'\n').collect();
+                set.iter().filter(|x| other.contains(*x)).count()
+            });
+            let cached_hashed = common::median_time(|| {
+                let other: HashSet<Hashed, BuildHasherDefault<PassThrough>> = split_lines(&operand, b'\n').map(Hashed::new).collect();
+                cached.iter().filter(|member| other.contains(*member)).count()
+            });
+            let name = format!("set of {}, operand of {}", set_lines, operand_lines);
+            common::report(&format!("{}: sorted", name), sorted);
+            common::report(&format!("{}: hashed", name), hashed);
+            common::report(&format!("{}: cached hashes", name), cached_hashed);
+        }
+    }
+}
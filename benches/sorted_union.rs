@@ -0,0 +1,25 @@
+// Sorted union output two ways: a `UnionSet` whose lines are then sorted, and
+// a `SortedUnionSet`, which keeps them sorted as it goes.
+
+mod common;
+
+use minimal::{Config, SetExpression, SortedUnionSet, TextSlice, UnionSet, UnionSetExt};
+
+fn main() {
+    let config = Config::default();
+    for &(count, distinct) in &[(100_000, 100_000), (100_000, 10_000)] {
+        let text = common::lines(count, distinct, 24);
+        let union_then_sort = common::median_time(|| {
+            let set = UnionSet::init(text.clone(), &config);
+            let mut lines: Vec<&TextSlice> = set.result_lines().collect();
+            lines.sort_unstable();
+            lines.len()
+        });
+        common::report(&format!("{} lines, {} distinct: UnionSet + sort", count, distinct), union_then_sort);
+        let sorted = common::median_time(|| {
+            let set = SortedUnionSet::init(&text, &config);
+            set.result_lines().count()
+        });
+        common::report(&format!("{} lines, {} distinct: SortedUnionSet", count, distinct), sorted);
+    }
+}
@@ -0,0 +1,197 @@
+use indexmap::IndexSet;
+
+use crate::{buffer::LineBuffer, Config, LineSet, SetExpression, SliceSet, TextSlice, TextVec};
+
+// An `AnnotatedUnionSet` is a union that remembers which operand introduced
+// each line: `sources[i]` is the 0-based index of the first operand containing
+// the `i`th member of `set`. `finish` builds the output lines, each one the
+// source, a tab, and the line, so the set's lines aren't available until it's
+// called. The source is the operand's name from `config.file_names`, or its
+// index if it has none. Filters see just the line, without the annotation.
+//
+#[derive(Default)]
+pub struct AnnotatedUnionSet {
+    set: IndexSet<TextVec>,
+    sources: Vec<usize>,
+    operands: usize,
+    file_names: Vec<TextVec>,
+    annotated: LineBuffer,
+}
+
+impl AnnotatedUnionSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let mut set = AnnotatedUnionSet { file_names: config.file_names.clone(), ..Self::default() };
+        set.operate(text, config);
+        set
+    }
+
+    fn annotate(&mut self) {
+        self.annotated = LineBuffer::default();
+        for (line, &source) in self.set.iter().zip(&self.sources) {
+            let label = match self.file_names.get(source) {
+                Some(name) => name.clone(),
+                None => source.to_string().into_bytes(),
+            };
+            self.annotated.push(&[&label, b"\t", line]);
+        }
+    }
+}
+
+impl<'a> LineSet<'a> for AnnotatedUnionSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        if self.set.insert(line.to_vec()) {
+            self.sources.push(self.operands);
+        }
+    }
+}
+
+impl<'data> SetExpression<'data> for AnnotatedUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+        self.operands += 1;
+    }
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.annotate();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.annotated.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        let mut sources = self.sources.iter();
+        let mut kept_sources = Vec::new();
+        self.set.retain(|line| {
+            let source = sources.next();
+            let kept = keep(line);
+            if kept {
+                kept_sources.extend(source);
+            }
+            kept
+        });
+        self.sources = kept_sources;
+        // If `finish` has already run, its output needs the same filtering
+        if !self.annotated.is_empty() {
+            self.annotate();
+        }
+    }
+}
+
+// An `AnnotatedIntersectSet` is an intersection whose lines are each labelled
+// with every operand, since every operand has them: `finish` writes the names
+// from `config.file_names`, or the indexes of operands without one, joined by
+// commas, then a tab and the line. Like an `IntersectSet` it borrows its lines
+// from the first operand, and filters see them without the label.
+//
+pub struct AnnotatedIntersectSet<'data> {
+    set: IndexSet<&'data TextSlice>,
+    operands: usize,
+    file_names: Vec<TextVec>,
+    annotated: LineBuffer,
+}
+
+impl<'data> AnnotatedIntersectSet<'data> {
+    pub fn init(text: &'data TextSlice, config: &Config) -> Self {
+        let set = SliceSet::init_from_slice(text, config).into_iter().collect();
+        AnnotatedIntersectSet { set, operands: 1, file_names: config.file_names.clone(), annotated: LineBuffer::default() }
+    }
+
+    fn annotate(&mut self) {
+        let mut label = TextVec::new();
+        for source in 0..self.operands {
+            if source > 0 {
+                label.push(b',');
+            }
+            match self.file_names.get(source) {
+                Some(name) => label.extend_from_slice(name),
+                None => label.extend_from_slice(source.to_string().as_bytes()),
+            }
+        }
+        self.annotated = LineBuffer::default();
+        for line in &self.set {
+            self.annotated.push(&[&label, b"\t", line]);
+        }
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for AnnotatedIntersectSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        self.operands += 1;
+        if self.set.is_empty() {
+            return;
+        }
+        let other = SliceSet::init_from_slice(text, config);
+        self.set.retain(|line| other.contains(line));
+    }
+    fn is_settled(&self) -> bool {
+        self.set.is_empty()
+    }
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.annotate();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.annotated.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.set.retain(|line| keep(line));
+        // If `finish` has already run, its output needs the same filtering
+        if !self.annotated.is_empty() {
+            self.annotate();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn union_labels_each_line_with_the_first_operand_that_has_it() {
+        let config = Config::default();
+        let mut set = AnnotatedUnionSet::init(b"a\nb\n", &config);
+        set.operate(b"b\nc\n", &config);
+        set.operate(b"c\nd\na\n", &config);
+        set.finish().unwrap();
+        let expected: [&[u8]; 4] = [b"0\ta", b"0\tb", b"1\tc", b"2\td"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn union_filters_see_the_line_and_keep_its_label() {
+        let config = Config::default();
+        let mut set = AnnotatedUnionSet::init(b"a\nbb\n", &config);
+        set.operate(b"ccc\n", &config);
+        set.finish().unwrap();
+        set.retain_lines(|line| line.len() > 1);
+        let expected: [&[u8]; 2] = [b"0\tbb", b"1\tccc"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn union_labels_lines_with_file_names_where_there_are_any() {
+        let config = Config { file_names: vec![b"logfile1.txt".to_vec()], ..Config::default() };
+        let mut set = AnnotatedUnionSet::init(b"hello\n", &config);
+        set.operate(b"hello\nworld\n", &config);
+        set.finish().unwrap();
+        let expected: [&[u8]; 2] = [b"logfile1.txt\thello", b"1\tworld"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn intersection_labels_each_line_with_every_operand() {
+        let config = Config { file_names: vec![b"a.txt".to_vec(), b"b.txt".to_vec()], ..Config::default() };
+        let first = b"x\ny\nz\n".to_vec();
+        let mut set = AnnotatedIntersectSet::init(&first, &config);
+        set.operate(b"z\ny\n", &config);
+        set.operate(b"y\nz\nw\n", &config);
+        set.finish().unwrap();
+        let expected: [&[u8]; 2] = [b"a.txt,b.txt,2\ty", b"a.txt,b.txt,2\tz"];
+        assert_eq!(lines(&set), expected);
+        set.retain_lines(|line| line == b"z");
+        assert_eq!(lines(&set), [&b"a.txt,b.txt,2\tz"[..]]);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+use crate::{SetExpression, SetopError, TextSlice, UnionSet};
+
+// A set can be saved in a compact binary form and read back as a `UnionSet`.
+// The format is just the magic number `SLSO`, a little-endian `u32` count of
+// lines, and then each line as a little-endian `u32` length followed by its
+// bytes. Lines are stored without their delimiter, so nothing else in the
+// format depends on the options the set was computed with.
+//
+pub(crate) const MAGIC: &[u8; 4] = b"SLSO";
+
+fn length_field(n: usize) -> io::Result<[u8; 4]> {
+    u32::try_from(n)
+        .map(u32::to_le_bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too big for the binary set format"))
+}
+
+pub(crate) fn write_binary<'data>(set: &impl SetExpression<'data>, out: &mut impl Write) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&length_field(set.result_lines().count())?)?;
+    for line in set.result_lines() {
+        out.write_all(&length_field(line.len())?)?;
+        out.write_all(line)?;
+    }
+    Ok(())
+}
+
+// Split `n` bytes off the front of `data`
+fn take<'a>(data: &mut &'a TextSlice, n: usize) -> Result<&'a TextSlice, SetopError> {
+    if data.len() < n {
+        return Err(SetopError::Truncated);
+    }
+    let (head, tail) = data.split_at(n);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_length(data: &mut &TextSlice) -> Result<usize, SetopError> {
+    let field = take(data, 4)?;
+    Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]) as usize)
+}
+
+pub(crate) fn read_binary(mut data: &TextSlice) -> Result<UnionSet, SetopError> {
+    if take(&mut data, MAGIC.len()).map_err(|_| SetopError::BadMagic)? != MAGIC {
+        return Err(SetopError::BadMagic);
+    }
+    let count = take_length(&mut data)?;
+    let mut set = UnionSet::default();
+    for _ in 0..count {
+        let len = take_length(&mut data)?;
+        set.insert(take(&mut data, len)?.to_vec());
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, SetExpression, SetopError, UnionSet, UnionSetExt};
+
+    fn saved(set: &UnionSet) -> Vec<u8> {
+        let mut out = Vec::new();
+        set.write_to_binary(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn a_saved_set_reads_back_the_same_in_the_same_order() {
+        let set = UnionSet::init(b"b\n\na\x00\xff\nb\nc".to_vec(), &Config::default());
+        let read = UnionSet::read_from_binary(&saved(&set)).unwrap();
+        assert_eq!(read, set);
+        assert!(read.iter().eq(set.iter()));
+    }
+
+    #[test]
+    fn the_format_is_magic_count_and_length_prefixed_lines() {
+        let set = UnionSet::from_lines(["ab", "c"]);
+        assert_eq!(saved(&set), b"SLSO\x02\0\0\0\x02\0\0\0ab\x01\0\0\0c");
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let data = saved(&UnionSet::from_lines(["one", "two"]));
+        for len in 4..data.len() {
+            assert!(matches!(UnionSet::read_from_binary(&data[..len]), Err(SetopError::Truncated)), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn input_without_the_magic_number_is_an_error() {
+        assert!(matches!(UnionSet::read_from_binary(b"SLS"), Err(SetopError::BadMagic)));
+        assert!(matches!(UnionSet::read_from_binary(b"XXXX\0\0\0\0"), Err(SetopError::BadMagic)));
+    }
+}
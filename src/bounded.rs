@@ -0,0 +1,162 @@
+use std::{io, mem};
+
+use crate::{Config, LineSet, SetExpression, TextSlice, TextVec, UnionSet};
+
+// What we charge for each line a `BoundedUnionSet` holds, besides its bytes:
+// the `TextVec` itself, plus the hash and index `IndexSet` keeps for it, all
+// doubled since the set's tables can be up to half empty after they grow.
+// The allocator's own overhead isn't counted, so this is an estimate, but a
+// generous one for lines of any length.
+const ENTRY_OVERHEAD: usize = 2 * (mem::size_of::<TextVec>() + 2 * mem::size_of::<usize>());
+
+// A `BoundedUnionSet` is a `UnionSet` that stops taking lines once they'd take
+// more than `limit` bytes, so that input chosen to be large can't use up all
+// memory. It remembers which line of which operand took it past the limit,
+// and then it's settled and `finish` fails.
+//
+pub struct BoundedUnionSet {
+    lines: UnionSet,
+    limit: usize,
+    stored: usize,
+    operands: usize,
+    line_number: usize,
+    // The operand and line, both numbered from 1, that would have passed the
+    // limit
+    limit_reached_at: Option<(usize, usize)>,
+}
+
+impl BoundedUnionSet {
+    pub fn new(limit: usize) -> Self {
+        BoundedUnionSet { lines: UnionSet::default(), limit, stored: 0, operands: 0, line_number: 0, limit_reached_at: None }
+    }
+
+    pub fn init(text: &TextSlice, limit: usize, config: &Config) -> Self {
+        let mut set = Self::new(limit);
+        set.operate(text, config);
+        set
+    }
+
+    // The bytes we estimate the lines take, never more than the limit
+    pub fn stored_bytes(&self) -> usize {
+        self.stored
+    }
+
+    pub fn limit_reached_at(&self) -> Option<(usize, usize)> {
+        self.limit_reached_at
+    }
+
+    // Add `line`, already counted in `line_number`
+    fn insert_counted(&mut self, line: &TextSlice) {
+        if self.limit_reached_at.is_some() || self.lines.contains(line) {
+            return;
+        }
+        let size = line.len() + ENTRY_OVERHEAD;
+        if self.stored + size > self.limit {
+            self.limit_reached_at = Some((self.operands, self.line_number));
+            return;
+        }
+        self.stored += size;
+        self.lines.insert(line.to_vec());
+    }
+}
+
+impl<'a> LineSet<'a> for BoundedUnionSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.line_number += 1;
+        self.insert_counted(line);
+    }
+    // Lines in other shards, or skipped for their length, still count, so that
+    // the line number in the error is the line's number in the operand
+    fn insert_in_shard(&mut self, line: &'a TextSlice, config: &Config) {
+        self.line_number += 1;
+        if !config.skips_line(line) && config.shard.is_none_or(|shard| shard.contains(line)) {
+            self.insert_counted(line);
+        }
+    }
+}
+
+impl<'data> SetExpression<'data> for BoundedUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        if self.limit_reached_at.is_some() {
+            return;
+        }
+        self.operands += 1;
+        self.line_number = 0;
+        self.insert_all_lines(text, config);
+    }
+    fn is_settled(&self) -> bool {
+        self.limit_reached_at.is_some()
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        match self.limit_reached_at {
+            Some((operand, line)) => {
+                let message = format!("line {} of operand {} takes the union over its limit of {} bytes", line, operand, self.limit);
+                Err(io::Error::other(message))
+            }
+            None => Ok(()),
+        }
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.lines.iter().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.lines.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.lines.retain(|line| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OnLongLine, Shard};
+
+    // Room for `lines` lines of up to `bytes` bytes each
+    fn limit(lines: usize, bytes: usize) -> usize {
+        lines * (bytes + ENTRY_OVERHEAD)
+    }
+
+    #[test]
+    fn lines_within_the_limit_are_a_plain_union() {
+        let config = Config::default();
+        let mut set = BoundedUnionSet::init(b"a\nb\na\n", limit(3, 1), &config);
+        set.operate(b"c\nb\n", &config);
+        set.finish().unwrap();
+        let lines: Vec<&TextSlice> = set.result_lines().collect();
+        let expected: [&[u8]; 3] = [b"a", b"b", b"c"];
+        assert_eq!(lines, expected);
+        assert_eq!(set.stored_bytes(), limit(3, 1));
+    }
+
+    #[test]
+    fn the_error_names_the_operand_and_line_over_the_limit() {
+        let config = Config::default();
+        let mut set = BoundedUnionSet::init(b"a\nb\n", limit(3, 1), &config);
+        set.operate(b"a\nc\nb\nd\ne\n", &config);
+        assert!(set.is_settled());
+        assert_eq!(set.limit_reached_at(), Some((2, 4)));
+        set.operate(b"f\n", &config);
+        assert_eq!(set.limit_reached_at(), Some((2, 4)));
+        let err = set.finish().unwrap_err();
+        assert!(err.to_string().starts_with("line 4 of operand 2 "), "{}", err);
+    }
+
+    #[test]
+    fn lines_in_other_shards_still_count_toward_the_line_number() {
+        let text = b"a\nb\nc\nd\ne\nf\ng\nh\n";
+        let shard = Shard { index: 0, count: 2 };
+        let config = Config { shard: Some(shard), ..Config::default() };
+        let set = BoundedUnionSet::init(text, limit(1, 1), &config);
+        let in_shard: Vec<usize> = (1..=8).filter(|&n| shard.contains(&text[2 * n - 2..2 * n - 1])).collect();
+        assert!(in_shard.len() >= 2);
+        assert_eq!(set.limit_reached_at(), Some((1, in_shard[1])));
+    }
+
+    #[test]
+    fn skipped_long_lines_still_count_toward_the_line_number() {
+        let config = Config { max_line_length: Some(1), on_long_line: OnLongLine::Skip, ..Config::default() };
+        let set = BoundedUnionSet::init(b"long\na\nlonger\nb\n", limit(1, 1), &config);
+        assert_eq!(set.limit_reached_at(), Some((1, 4)));
+    }
+}
@@ -0,0 +1,38 @@
+use crate::TextSlice;
+
+// A `LineBuffer` holds result lines that a set builds rather than borrows —
+// merged, annotated, or otherwise rewritten — packed end to end in one buffer
+// instead of in a `TextVec` apiece.
+//
+#[derive(Default)]
+pub(crate) struct LineBuffer {
+    text: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl LineBuffer {
+    // Append a line made of `parts` joined together
+    pub(crate) fn push(&mut self, parts: &[&TextSlice]) {
+        for part in parts {
+            self.text.extend_from_slice(part);
+        }
+        self.ends.push(self.text.len());
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        let mut kept = LineBuffer::default();
+        for line in self.iter().filter(|line| keep(line)) {
+            kept.push(&[line]);
+        }
+        *self = kept;
+    }
+
+    pub(crate) fn iter<'me>(&'me self) -> impl Iterator<Item = &'me TextSlice> + 'me {
+        let starts = Some(0).into_iter().chain(self.ends.iter().cloned());
+        starts.zip(self.ends.iter()).map(move |(begin, &end)| &self.text[begin..end])
+    }
+}
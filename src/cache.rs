@@ -0,0 +1,142 @@
+use std::{
+    fs::{self, OpenOptions},
+    hash::Hasher,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str,
+};
+
+use rustc_hash::FxHasher;
+
+use crate::TextSlice;
+
+// A fast hash of `text`, to tell whether it has changed since a result was
+// cached. It isn't a cryptographic hash: it's good against accidents, not
+// against someone making two texts hash alike on purpose.
+pub fn content_hash(text: &TextSlice) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(text);
+    hasher.finish()
+}
+
+// A `SetCache` keeps results in a file that only ever grows: `store` appends
+// an entry, and `check` reads them all and answers with the last one for the
+// same path and hash. Each entry is a line with its hash in hex and the
+// lengths in bytes of its path and result, then the path and the result:
+//
+//     <hash> <path length> <result length>\n<path><result>
+//
+// An entry cut short, as by a write that was interrupted, is never found. The
+// entries appended after it are read as the rest of it, so they can be lost too.
+//
+pub struct SetCache {
+    path: PathBuf,
+}
+
+impl SetCache {
+    pub const FILE_NAME: &'static str = ".setop_cache";
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        SetCache { path: path.into() }
+    }
+
+    // The cache in `FILE_NAME` in the current directory
+    pub fn in_current_dir() -> Self {
+        SetCache::new(SetCache::FILE_NAME)
+    }
+
+    // The result last stored for `file_path` and `text_hash`, if any. A cache
+    // that doesn't exist or can't be read has nothing in it.
+    pub fn check(&self, file_path: &Path, text_hash: u64) -> Option<Vec<u8>> {
+        let entries = fs::read(&self.path).ok()?;
+        let key = file_path.to_string_lossy();
+        let mut found = None;
+        let mut rest = &entries[..];
+        while let Some((entry, after)) = next_entry(rest) {
+            if entry.hash == text_hash && entry.path == key.as_bytes() {
+                found = Some(entry.result);
+            }
+            rest = after;
+        }
+        found.map(|result| result.to_vec())
+    }
+
+    pub fn store(&self, file_path: &Path, text_hash: u64, result: &[u8]) -> io::Result<()> {
+        let key = file_path.to_string_lossy();
+        let mut entry = format!("{:016x} {} {}\n", text_hash, key.len(), result.len()).into_bytes();
+        entry.extend_from_slice(key.as_bytes());
+        entry.extend_from_slice(result);
+        // One write, so entries from two runs at once don't interleave
+        OpenOptions::new().create(true).append(true).open(&self.path)?.write_all(&entry)
+    }
+}
+
+struct Entry<'a> {
+    hash: u64,
+    path: &'a [u8],
+    result: &'a [u8],
+}
+
+// The entry at the start of `entries`, and what follows it
+fn next_entry(entries: &[u8]) -> Option<(Entry<'_>, &[u8])> {
+    let end = memchr::memchr(b'\n', entries)?;
+    let header = str::from_utf8(&entries[..end]).ok()?;
+    let mut fields = header.split(' ');
+    let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let path_len: usize = fields.next()?.parse().ok()?;
+    let result_len: usize = fields.next()?.parse().ok()?;
+    let body = &entries[end + 1..];
+    if body.len() < path_len.checked_add(result_len)? {
+        return None;
+    }
+    let (path, body) = body.split_at(path_len);
+    let (result, after) = body.split_at(result_len);
+    Some((Entry { hash, path, result }, after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn check_finds_what_store_stored_for_the_same_path_and_hash() {
+        let dir = TempDir::new().unwrap();
+        let cache = SetCache::new(dir.path().join(SetCache::FILE_NAME));
+        assert_eq!(cache.check(Path::new("a"), 1), None);
+        cache.store(Path::new("a"), 1, b"x\ny\n").unwrap();
+        cache.store(Path::new("b"), 1, b"z\n").unwrap();
+        assert_eq!(cache.check(Path::new("a"), 1), Some(b"x\ny\n".to_vec()));
+        assert_eq!(cache.check(Path::new("b"), 1), Some(b"z\n".to_vec()));
+        assert_eq!(cache.check(Path::new("a"), 2), None);
+    }
+
+    #[test]
+    fn the_last_entry_stored_wins() {
+        let dir = TempDir::new().unwrap();
+        let cache = SetCache::new(dir.path().join(SetCache::FILE_NAME));
+        cache.store(Path::new("a"), 1, b"old\n").unwrap();
+        cache.store(Path::new("a"), 1, b"new\n").unwrap();
+        assert_eq!(cache.check(Path::new("a"), 1), Some(b"new\n".to_vec()));
+    }
+
+    #[test]
+    fn an_entry_cut_short_is_never_found() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(SetCache::FILE_NAME);
+        let cache = SetCache::new(&path);
+        cache.store(Path::new("a"), 1, b"x\n").unwrap();
+        let mut entries = fs::read(&path).unwrap();
+        entries.extend_from_slice(b"0000000000000002 1 10\nbshort");
+        fs::write(&path, entries).unwrap();
+        assert_eq!(cache.check(Path::new("a"), 1), Some(b"x\n".to_vec()));
+        assert_eq!(cache.check(Path::new("b"), 2), None);
+    }
+
+    #[test]
+    fn content_hash_tells_texts_apart() {
+        assert_eq!(content_hash(b"a\nb\n"), content_hash(b"a\nb\n"));
+        assert_ne!(content_hash(b"a\nb\n"), content_hash(b"b\na\n"));
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{BuildHasherDefault, Hash, Hasher},
+};
+
+use indexmap::IndexSet;
+
+use crate::{Config, LineSet, SetExpression, TextSlice};
+
+// A `CachedIntersectSet` is an `IntersectSet` whose members carry their own
+// hash. An intersection with many operands looks up every surviving member
+// in each later operand's set; an `IntersectSet` hashes the member's bytes
+// again each time, while here each line is hashed once, when it's read, and
+// lookups hash just the cached value. Building each operand's set still
+// hashes all its lines, so `benches/intersect_chain.rs`, with 30 operands,
+// finds this 10-15% faster for long lines or many of them, and no faster for
+// 10,000 short ones.
+//
+// An `IntersectSet` searches an operand of only a few lines as a sorted `Vec`,
+// to save hashing the set's lines for it, but here they're hashed already:
+// `benches/sorted_operand.rs` finds looking up the cached hashes 1.7 times as
+// fast as the sorted `Vec` for an operand of 2 lines and a set of 16, and 7
+// times as fast for sets of 10,000, so every operand is hashed.
+//
+// Every operand's lines are hashed with the same fixed-key hasher, so the
+// cached hashes of two sets agree.
+//
+#[derive(Clone, Copy)]
+struct Hashed<'a> {
+    hash: u64,
+    line: &'a TextSlice,
+}
+
+impl<'a> Hashed<'a> {
+    fn new(line: &'a TextSlice) -> Self {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        Hashed { hash: hasher.finish(), line }
+    }
+}
+
+impl<'a> Hash for Hashed<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl<'a> PartialEq for Hashed<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.line == other.line
+    }
+}
+
+impl<'a> Eq for Hashed<'a> {}
+
+// A `Hashed` is hashed as its one `u64`, which this hasher passes through
+// unchanged
+#[derive(Default)]
+struct PassThrough(u64);
+
+impl Hasher for PassThrough {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(byte);
+        }
+    }
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}
+
+type HashedSet<'a> = IndexSet<Hashed<'a>, BuildHasherDefault<PassThrough>>;
+
+#[derive(Default)]
+struct HashedLines<'a>(HashedSet<'a>);
+
+impl<'a> LineSet<'a> for HashedLines<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.0.insert(Hashed::new(line));
+    }
+}
+
+pub struct CachedIntersectSet<'data>(HashedSet<'data>);
+
+impl<'data> CachedIntersectSet<'data> {
+    pub fn init(text: &'data TextSlice, config: &Config) -> Self {
+        CachedIntersectSet(HashedLines::init_from_slice(text, config).0)
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for CachedIntersectSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        if self.0.is_empty() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let before = self.0.len();
+        let other = HashedLines::init_from_slice(text, config).0;
+        self.0.retain(|member| other.contains(member));
+        #[cfg(feature = "tracing")]
+        tracing::debug!(before, after = self.0.len(), "intersected with operand");
+    }
+    fn is_settled(&self) -> bool {
+        self.0.is_empty()
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.iter().map(|member| member.line))
+    }
+    fn result_len(&self) -> usize {
+        self.0.len()
+    }
+    fn nth_line(&self, n: usize) -> Option<&TextSlice> {
+        self.0.get_index(n).map(|member| member.line)
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.retain(|member| keep(member.line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{intersect_n, TextVec};
+
+    #[test]
+    fn intersects_like_an_intersect_set() {
+        let config = Config::default();
+        let texts: Vec<TextVec> = vec![b"e\nd\nc\nb\na\n".to_vec(), b"a\nb\nc\nd\n".to_vec(), b"d\nx\nb\ne\n".to_vec()];
+        let mut set = CachedIntersectSet::init(&texts[0], &config);
+        for text in &texts[1..] {
+            set.operate(text, &config);
+        }
+        assert_eq!(set.to_bytes(), intersect_n(&texts, &config));
+        assert_eq!(set.to_bytes(), b"d\nb\n");
+        assert_eq!(set.nth_line(1), Some(&b"b"[..]));
+        set.operate(b"a\n", &config);
+        assert!(set.is_settled());
+    }
+
+    #[test]
+    fn members_with_the_same_hash_are_compared_by_their_bytes() {
+        let a = Hashed::new(b"a");
+        assert!(a == Hashed::new(b"a"));
+        assert!(a != Hashed { hash: a.hash, line: b"b" });
+    }
+}
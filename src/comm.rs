@@ -0,0 +1,117 @@
+use std::io;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{buffer::LineBuffer, Config, LineSet, SetExpression, SliceSet, TextSlice, TextVec};
+
+// A `CommSet` compares two operands the way `comm` does, in three columns:
+// lines only in the first, lines only in the second, and lines in both. Each
+// column is indented by a tab for every column before it that's shown, and
+// `config.comm_suppress[i]` leaves out column `i + 1`, like `comm -1 -2 -3`.
+// Unlike `comm` the operands needn't be sorted: the first operand's lines come
+// out in its order, in the first or third column, followed by the lines only
+// in the second. Any operands after the second count as part of it.
+//
+// `finish` builds the output lines, so the set's lines aren't available until
+// it's called. Filters see just the line, without its indentation.
+//
+pub struct CommSet<'data> {
+    // Each line of the first operand, and whether a later one has it too
+    first: IndexMap<&'data TextSlice, bool>,
+    second_only: IndexSet<TextVec>,
+    suppress: [bool; 3],
+    columns: LineBuffer,
+}
+
+impl<'data> CommSet<'data> {
+    pub fn init(text: &'data TextSlice, config: &Config) -> Self {
+        let first = SliceSet::init_from_slice(text, config);
+        CommSet {
+            first: first.into_iter().map(|line| (line, false)).collect(),
+            second_only: IndexSet::new(),
+            suppress: config.comm_suppress,
+            columns: LineBuffer::default(),
+        }
+    }
+
+    fn write_columns(&mut self) {
+        let indents = [
+            0,
+            usize::from(!self.suppress[0]),
+            usize::from(!self.suppress[0]) + usize::from(!self.suppress[1]),
+        ];
+        let tabs = b"\t\t";
+        self.columns = LineBuffer::default();
+        for (line, &shared) in &self.first {
+            let column = if shared { 2 } else { 0 };
+            if !self.suppress[column] {
+                self.columns.push(&[&tabs[..indents[column]], line]);
+            }
+        }
+        if !self.suppress[1] {
+            for line in &self.second_only {
+                self.columns.push(&[&tabs[..indents[1]], line]);
+            }
+        }
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for CommSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        for line in SliceSet::init_from_slice(text, config) {
+            match self.first.get_mut(line) {
+                Some(shared) => *shared = true,
+                None => {
+                    self.second_only.insert(line.to_vec());
+                }
+            }
+        }
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_columns();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.columns.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.first.retain(|line, _| keep(line));
+        self.second_only.retain(|line| keep(line));
+        // If `finish` has already run, its output needs the same filtering
+        if !self.columns.is_empty() {
+            self.write_columns();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comm(suppress: [bool; 3]) -> Vec<String> {
+        let config = Config { comm_suppress: suppress, ..Config::default() };
+        let first = b"only1\nboth\n".to_vec();
+        let mut set = CommSet::init(&first, &config);
+        set.operate(b"both\nonly2\n", &config);
+        set.finish().unwrap();
+        set.result_lines().map(|line| String::from_utf8(line.to_vec()).unwrap()).collect()
+    }
+
+    #[test]
+    fn every_combination_of_suppressed_columns() {
+        let expected: [(&str, &[&str]); 8] = [
+            ("", &["only1", "\t\tboth", "\tonly2"]),
+            ("1", &["\tboth", "only2"]),
+            ("2", &["only1", "\tboth"]),
+            ("3", &["only1", "\tonly2"]),
+            ("12", &["both"]),
+            ("13", &["only2"]),
+            ("23", &["only1"]),
+            ("123", &[]),
+        ];
+        for (columns, lines) in expected.iter() {
+            let suppress = [columns.contains('1'), columns.contains('2'), columns.contains('3')];
+            assert_eq!(comm(suppress), *lines, "--suppress {}", columns);
+        }
+    }
+}
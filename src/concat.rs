@@ -0,0 +1,63 @@
+use crate::{Config, LineSet, SetExpression, TextSlice, TextVec};
+
+// A `ConcatSet` isn't really a set: it keeps every line of every operand, in
+// order, repeats and all. Because it only splits and writes lines it shows
+// what the rest of the sets see — concatenating one file writes it back
+// unchanged, except that a last line without a delimiter gets one.
+//
+#[derive(Default)]
+pub struct ConcatSet(Vec<TextVec>);
+
+impl ConcatSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        Self::init_from_slice(text, config)
+    }
+}
+
+impl<'a> LineSet<'a> for ConcatSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.0.push(line.to_vec());
+    }
+}
+
+impl<'data> SetExpression<'data> for ConcatSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.iter().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.0.len()
+    }
+    fn nth_line(&self, n: usize) -> Option<&TextSlice> {
+        self.0.get(n).map(|v| v.as_slice())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.retain(|line| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concatenating_one_text_writes_it_back_unchanged() {
+        let config = Config::default();
+        let text = b"b\na\nb\n\n\xff\ta  \nlast\n";
+        assert_eq!(ConcatSet::init(text, &config).to_bytes(), text);
+        assert_eq!(ConcatSet::init(b"no newline", &config).to_bytes(), b"no newline\n");
+        assert_eq!(ConcatSet::init(b"", &config).to_bytes(), b"");
+    }
+
+    #[test]
+    fn every_line_of_every_operand_is_kept_in_order() {
+        let config = Config::default();
+        let mut set = ConcatSet::init(b"a\nb\na\n", &config);
+        set.operate(b"b\nc", &config);
+        assert_eq!(set.to_bytes(), b"a\nb\na\nb\nc\n");
+        assert_eq!(set.result_len(), 5);
+        assert_eq!(set.nth_line(3), Some(&b"b"[..]));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{Config, LineSet, SliceSet, TextVec};
+
+// The indices of a few of `texts` whose union is the union of them all, in
+// the order they were chosen. Finding the fewest is NP-hard, so we take the
+// usual greedy approach: pick the text with the most lines not yet covered,
+// the earliest on a tie, until every line is covered. That picks at most
+// H(n) ≈ ln n + 1 times as many texts as the fewest possible, where n is the
+// number of distinct lines, and when one text has every line it's the only
+// one picked. Texts with no lines are never picked.
+pub fn minimum_set_cover(texts: &[TextVec], config: &Config) -> Vec<usize> {
+    let sets: Vec<SliceSet> = texts.iter().map(|text| SliceSet::init_from_slice(text, config)).collect();
+    let mut uncovered: SliceSet = sets.iter().flatten().cloned().collect();
+    let mut chosen = Vec::new();
+    while !uncovered.is_empty() {
+        let best = sets
+            .iter()
+            .map(|set| set.iter().filter(|line| uncovered.contains(*line)).count())
+            .enumerate()
+            .fold((0, 0), |best, (n, count)| if count > best.1 { (n, count) } else { best });
+        chosen.push(best.0);
+        uncovered.retain(|line| !sets[best.0].contains(line));
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::union_n;
+
+    fn texts(texts: &[&str]) -> Vec<TextVec> {
+        texts.iter().map(|text| text.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn the_chosen_texts_cover_every_line() {
+        let config = Config::default();
+        let all = texts(&["1\n2\n", "2\n3\n4\n", "5\n", "1\n5\n", "4\n6\n", ""]);
+        let chosen = minimum_set_cover(&all, &config);
+        assert_eq!(chosen, [1, 3, 4]);
+        let picked: Vec<TextVec> = chosen.iter().map(|&n| all[n].clone()).collect();
+        let mut covered: Vec<_> = union_n(&picked, &config).split(|&b| b == b'\n').map(|line| line.to_vec()).collect();
+        let mut every: Vec<_> = union_n(&all, &config).split(|&b| b == b'\n').map(|line| line.to_vec()).collect();
+        covered.sort();
+        every.sort();
+        assert_eq!(covered, every);
+    }
+
+    #[test]
+    fn a_text_with_every_line_is_chosen_alone() {
+        let config = Config::default();
+        let all = texts(&["a\n", "b\nc\n", "c\nb\na\n", "a\nc\n"]);
+        assert_eq!(minimum_set_cover(&all, &config), [2]);
+    }
+
+    #[test]
+    fn empty_texts_are_never_chosen() {
+        let config = Config::default();
+        assert!(minimum_set_cover(&texts(&["", ""]), &config).is_empty());
+        assert!(minimum_set_cover(&[], &config).is_empty());
+    }
+}
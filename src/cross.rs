@@ -0,0 +1,87 @@
+use crate::{buffer::LineBuffer, Config, LineSet, SetExpression, SliceSet, TextSlice, TextVec};
+
+// Every pair of a line of `a` and a line of `b`, the line of `a` first, with
+// `separator` between them and the delimiter after. The pairs come in `a`'s
+// order, and for each line of `a` in `b`'s. Lines are taken as the sets take
+// them, once each however often they occur, so no pair is repeated.
+pub fn cross_product(a: &TextSlice, b: &TextSlice, separator: &[u8], config: &Config) -> Vec<u8> {
+    let right = SliceSet::init_from_slice(b, config);
+    let mut product = TextVec::new();
+    for left in SliceSet::init_from_slice(a, config) {
+        for right in &right {
+            product.extend_from_slice(left);
+            product.extend_from_slice(separator);
+            product.extend_from_slice(right);
+            product.push(config.delimiter);
+        }
+    }
+    product
+}
+
+// A `CrossSet` holds the `cross_product` of its operands, joined by
+// `config.cross_separator`: each operand after the first pairs every line so
+// far with each of its own, so with three operands the lines are triples.
+// The result is as long as the operands' numbers of distinct lines multiplied
+// together, so it grows fast.
+//
+pub struct CrossSet {
+    separator: TextVec,
+    product: LineBuffer,
+}
+
+impl CrossSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let mut product = LineBuffer::default();
+        for line in SliceSet::init_from_slice(text, config) {
+            product.push(&[line]);
+        }
+        CrossSet { separator: config.cross_separator.clone(), product }
+    }
+}
+
+impl<'data> SetExpression<'data> for CrossSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        let right = SliceSet::init_from_slice(text, config);
+        let mut product = LineBuffer::default();
+        for left in self.product.iter() {
+            for right in &right {
+                product.push(&[left, &self.separator, right]);
+            }
+        }
+        self.product = product;
+    }
+    fn is_settled(&self) -> bool {
+        self.product.is_empty()
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.product.iter())
+    }
+    fn retain_lines(&mut self, keep: impl FnMut(&TextSlice) -> bool) {
+        self.product.retain(keep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_product_pairs_every_distinct_line_in_order() {
+        let product = cross_product(b"a\nb\na\n", b"1\n2\n3\n", b",", &Config::default());
+        assert_eq!(product, b"a,1\na,2\na,3\nb,1\nb,2\nb,3\n");
+        assert_eq!(cross_product(b"a\n", b"", b",", &Config::default()), b"");
+    }
+
+    #[test]
+    fn each_operand_multiplies_the_lines() {
+        let config = Config { cross_separator: b"-".to_vec(), ..Config::default() };
+        let mut set = CrossSet::init(b"a\nb\n", &config);
+        set.operate(b"1\n2\n", &config);
+        set.operate(b"x\n", &config);
+        let lines: Vec<_> = set.result_lines().collect();
+        assert_eq!(lines, [&b"a-1-x"[..], b"a-2-x", b"b-1-x", b"b-2-x"]);
+        assert!(!set.is_settled());
+        set.operate(b"", &config);
+        assert!(set.is_settled());
+    }
+}
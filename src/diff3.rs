@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+
+use indexmap::IndexSet;
+
+use crate::{buffer::LineBuffer, finish_and_write, Config, LineSet, SetExpression, SliceSet, TextSlice, TextVec};
+
+// Write the three-way comparison of `a` and `b` against their common `base`
+// that a `Diff3Set` of the three makes
+pub fn diff3_output(base: &TextSlice, a: &TextSlice, b: &TextSlice, out: &mut impl Write, config: &Config) -> io::Result<()> {
+    let mut set = Diff3Set::init(base, config);
+    set.operate(a, config);
+    set.operate(b, config);
+    finish_and_write(&mut set, out, config)?;
+    Ok(())
+}
+
+// A `Diff3Set` compares two operands, A and B, with a third they both came
+// from, the base, which is the first operand; A is the second, and any
+// operands after the third count as part of B. Each operand is taken as its
+// distinct lines in first-seen order, like every other set here. By which
+// operands have it a line falls into one of seven groups, and the lines in
+// all three — those that come in the same order in each, anyway — are where
+// the three are in step. Between each of those and the next, A's lines, the
+// base's and B's are a chunk that's merged the way `diff3 -m` merges one:
+//
+// - unchanged, the same in all three: written as it is
+// - changed in A only, by adding lines or removing the base's: A's lines
+// - changed in B only: B's lines
+// - changed the same way in both: their lines, once
+// - changed differently in each: a conflict, marked
+//
+//     <<<<<<< A
+//     A's lines
+//     ||||||| base
+//     the base's lines
+//     =======
+//     B's lines
+//     >>>>>>> B
+//
+// So a line removed from the base by either side is gone unless the other
+// side changed the lines around it too, and a line added by either side is
+// kept.
+//
+// `finish` builds the output lines, so the set's lines aren't available until
+// it's called. Filters see the lines of the operands, and never the markers.
+//
+pub struct Diff3Set<'data> {
+    base: SliceSet<'data>,
+    a: IndexSet<TextVec>,
+    b: IndexSet<TextVec>,
+    operands: usize,
+    merged: LineBuffer,
+}
+
+impl<'data> Diff3Set<'data> {
+    pub fn init(text: &'data TextSlice, config: &Config) -> Self {
+        Diff3Set {
+            base: SliceSet::init_from_slice(text, config),
+            a: IndexSet::new(),
+            b: IndexSet::new(),
+            operands: 1,
+            merged: LineBuffer::default(),
+        }
+    }
+
+    fn write_lines(&mut self) {
+        let base: Vec<&TextSlice> = self.base.iter().cloned().collect();
+        let a: Vec<&TextSlice> = self.a.iter().map(|line| line.as_slice()).collect();
+        let b: Vec<&TextSlice> = self.b.iter().map(|line| line.as_slice()).collect();
+        let mut merged = LineBuffer::default();
+        // Where the chunk after the last line in step starts, in each operand
+        let (mut base_from, mut a_from, mut b_from) = (0, 0, 0);
+        for (i, line) in base.iter().enumerate() {
+            // A line in all three is only in step if it comes after the last
+            // one that was in A and B too
+            let (j, k) = match (self.a.get_full(*line), self.b.get_full(*line)) {
+                (Some((j, _)), Some((k, _))) if j >= a_from && k >= b_from => (j, k),
+                _ => continue,
+            };
+            merge_chunk(&mut merged, &base[base_from..i], &a[a_from..j], &b[b_from..k]);
+            merged.push(&[line]);
+            base_from = i + 1;
+            a_from = j + 1;
+            b_from = k + 1;
+        }
+        merge_chunk(&mut merged, &base[base_from..], &a[a_from..], &b[b_from..]);
+        self.merged = merged;
+    }
+}
+
+// Add the lines that merging one chunk of A and B with the base gives
+fn merge_chunk(merged: &mut LineBuffer, base: &[&TextSlice], a: &[&TextSlice], b: &[&TextSlice]) {
+    let mut push_all = |lines: &[&TextSlice]| {
+        for line in lines {
+            merged.push(&[line]);
+        }
+    };
+    if a == b || b == base {
+        push_all(a);
+    } else if a == base {
+        push_all(b);
+    } else {
+        push_all(&[b"<<<<<<< A"]);
+        push_all(a);
+        push_all(&[b"||||||| base"]);
+        push_all(base);
+        push_all(&[b"======="]);
+        push_all(b);
+        push_all(&[b">>>>>>> B"]);
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for Diff3Set<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        let side = if self.operands == 1 { &mut self.a } else { &mut self.b };
+        for line in SliceSet::init_from_slice(text, config) {
+            side.insert(line.to_vec());
+        }
+        self.operands += 1;
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_lines();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.merged.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.base.retain(|line| keep(line));
+        self.a.retain(|line| keep(line));
+        self.b.retain(|line| keep(line));
+        // If `finish` has already run, its output needs the same filtering
+        if !self.merged.is_empty() {
+            self.write_lines();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(base: &TextSlice, a: &TextSlice, b: &TextSlice) -> String {
+        let mut out = Vec::new();
+        diff3_output(base, a, b, &mut out, &Config::default()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn lines_unchanged_in_both_are_kept() {
+        assert_eq!(merged(b"1\n2\n3\n", b"1\n2\n3\n", b"1\n2\n3\n"), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn a_change_in_a_only_is_taken() {
+        // A adds `x` and removes `3`
+        assert_eq!(merged(b"1\n2\n3\n4\n", b"1\nx\n2\n4\n", b"1\n2\n3\n4\n"), "1\nx\n2\n4\n");
+    }
+
+    #[test]
+    fn a_change_in_b_only_is_taken() {
+        // B replaces `2` with `y` and adds `z` at the end
+        assert_eq!(merged(b"1\n2\n3\n", b"1\n2\n3\n", b"1\ny\n3\nz\n"), "1\ny\n3\nz\n");
+    }
+
+    #[test]
+    fn changes_to_different_lines_are_both_taken() {
+        assert_eq!(merged(b"1\n2\n3\n4\n5\n", b"1\nx\n3\n4\n5\n", b"1\n2\n3\n5\ny\n"), "1\nx\n3\n5\ny\n");
+    }
+
+    #[test]
+    fn the_same_change_in_both_is_taken_once() {
+        // Both remove `2`, and both add `x` after `3`
+        assert_eq!(merged(b"1\n2\n3\n", b"1\n3\nx\n", b"1\n3\nx\n"), "1\n3\nx\n");
+    }
+
+    #[test]
+    fn different_changes_to_the_same_line_conflict() {
+        let expected = "1\n<<<<<<< A\nx\n||||||| base\n2\n=======\ny\n>>>>>>> B\n3\n";
+        assert_eq!(merged(b"1\n2\n3\n", b"1\nx\n3\n", b"1\ny\n3\n"), expected);
+        // Removing a line conflicts with changing it
+        let expected = "1\n<<<<<<< A\n||||||| base\n2\n=======\ny\n>>>>>>> B\n3\n";
+        assert_eq!(merged(b"1\n2\n3\n", b"1\n3\n", b"1\ny\n3\n"), expected);
+    }
+
+    #[test]
+    fn a_line_out_of_step_is_part_of_a_chunk() {
+        // A moves `2` before `1`. Once `1` is in step, `2` comes too late in A
+        // to be, so A has added a `2` before `1` and removed the one after it,
+        // where B has added `b`
+        let expected = "2\n1\n<<<<<<< A\n||||||| base\n2\n=======\nb\n2\n>>>>>>> B\n";
+        assert_eq!(merged(b"1\n2\n", b"2\n1\n", b"1\nb\n2\n"), expected);
+    }
+
+    #[test]
+    fn filters_never_see_the_markers() {
+        let config = Config::default();
+        let mut set = Diff3Set::init(b"1\n22\n3\n", &config);
+        set.operate(b"1\nxx\n3\n", &config);
+        set.operate(b"1\nyy\n3\n", &config);
+        set.finish().unwrap();
+        set.retain_lines(|line| line.len() == 2);
+        assert_eq!(set.to_bytes(), b"<<<<<<< A\nxx\n||||||| base\n22\n=======\nyy\n>>>>>>> B\n");
+    }
+}
@@ -0,0 +1,336 @@
+use std::io::{self, BufRead, Read};
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{Config, LineSet, SetExpression, SliceSet, TextSlice, TextVec};
+
+// A `NotInAllSet` holds the lines of the first operand that are missing from
+// at least one of the others — the first operand minus the intersection of
+// the rest. Like an `IntersectSet` it borrows its lines from the first
+// operand, and counts how many of the later operands contain each one.
+//
+// With `config.invert_match` it keeps the other lines instead: those in every
+// later operand. That's their intersection, always in the first operand's
+// order.
+//
+#[derive(Default)]
+pub struct NotInAllSet<'data> {
+    counts: IndexMap<&'data TextSlice, usize>,
+    operands: usize,
+    invert: bool,
+}
+
+impl<'data> NotInAllSet<'data> {
+    pub fn init(text: &'data TextSlice, config: &Config) -> Self {
+        let lines = SliceSet::init_from_slice(text, config);
+        NotInAllSet {
+            counts: lines.into_iter().map(|line| (line, 0)).collect(),
+            operands: 0,
+            invert: config.invert_match,
+        }
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for NotInAllSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        let other = SliceSet::init_from_slice(text, config);
+        for (line, count) in self.counts.iter_mut() {
+            if other.contains(line) {
+                *count += 1;
+            }
+        }
+        self.operands += 1;
+    }
+    // A line in every later operand is in their intersection
+    fn finish(&mut self) -> io::Result<()> {
+        let (operands, invert) = (self.operands, self.invert);
+        self.counts.retain(|_, count| (*count < operands) != invert);
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.counts.keys().cloned())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.counts.retain(|line, _| keep(line));
+    }
+}
+
+// An `ExclusiveUnionSet` holds the lines that are in exactly one operand: the
+// lines unique to one file, not shared with any other. For two operands
+// that's their symmetric difference; with more, a line in two of them is left
+// out even though it's missing from the rest. Lines can come from any operand,
+// so they're copied, and each is counted once per operand it's in.
+//
+#[derive(Default)]
+pub struct ExclusiveUnionSet {
+    counts: IndexMap<TextVec, usize>,
+}
+
+impl ExclusiveUnionSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let mut set = Self::default();
+        set.operate(text, config);
+        set
+    }
+}
+
+impl<'data> SetExpression<'data> for ExclusiveUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        for line in SliceSet::init_from_slice(text, config) {
+            match self.counts.get_mut(line) {
+                Some(count) => *count += 1,
+                None => {
+                    self.counts.insert(line.to_vec(), 1);
+                }
+            }
+        }
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.counts.retain(|_, count| *count == 1);
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.counts.keys().map(|v| v.as_slice()))
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.counts.retain(|line, _| keep(line));
+    }
+}
+
+// A `ComplementSet` holds the lines of the first operand, the universe, that
+// are in none of the others: the universe minus the union of the rest.
+// Each later operand only ever removes lines, so once the set is empty the
+// rest needn't be read. Nor does a later operand need to be in memory at all:
+// `operate_reader` takes it a line at a time.
+//
+#[derive(Default)]
+pub struct ComplementSet(IndexSet<TextVec>);
+
+impl ComplementSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let mut universe = IndexSet::new();
+        for line in SliceSet::init_from_slice(text, config) {
+            universe.insert(line.to_vec());
+        }
+        ComplementSet(universe)
+    }
+
+    // Remove the lines that `reader` has, as `operate` does for the lines of
+    // a text, reading one line at a time and stopping once every line is
+    // gone. Lines are split and normalized as `operate` splits them, but
+    // nothing else is done to them: a caller that changes case or hashes
+    // lines has to read the whole text and use `operate`. Lines are marked
+    // as they're found and dropped together at the end, since dropping one
+    // from the middle of the set shifts the rest down.
+    pub fn operate_reader(&mut self, mut reader: impl BufRead, config: &Config) -> io::Result<()> {
+        let mut found = vec![false; self.0.len()];
+        let mut left = self.0.len();
+        let mut line = TextVec::new();
+        let mut read = || -> io::Result<()> {
+            while left > 0 {
+                line.clear();
+                let len = match config.record_size {
+                    Some(size) => (&mut reader).take(size as u64).read_to_end(&mut line)?,
+                    None => reader.read_until(config.delimiter, &mut line)?,
+                };
+                if len == 0 {
+                    break;
+                }
+                if config.record_size.is_none() && line.last() == Some(&config.delimiter) {
+                    line.pop();
+                }
+                if let Some((i, _)) = self.0.get_full(config.normalize_line(&line)) {
+                    if !found[i] {
+                        found[i] = true;
+                        left -= 1;
+                    }
+                }
+            }
+            Ok(())
+        };
+        // Lines found before a read error are still dropped
+        let result = read();
+        let mut found = found.into_iter();
+        self.0.retain(|_| !found.next().unwrap_or(false));
+        result
+    }
+}
+
+impl<'data> SetExpression<'data> for ComplementSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        let other = SliceSet::init_from_slice(text, config);
+        self.0.retain(|line| !other.contains(line.as_slice()));
+    }
+    fn is_settled(&self) -> bool {
+        self.0.is_empty()
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.iter().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.0.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.retain(|line| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+    use crate::{diff_n, intersect_n, union_n};
+
+    // A reader that fails, to put after the lines a test wants read
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+    }
+
+    // `text` and then a read error
+    fn then_broken(text: &'static [u8]) -> impl BufRead {
+        BufReader::new(Cursor::new(text).chain(Broken))
+    }
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn not_in_all_keeps_first_operand_lines_missing_from_any_other() {
+        let config = Config::default();
+        let first = b"1\n2\n3\n4\n".to_vec();
+        let mut set = NotInAllSet::init(&first, &config);
+        set.operate(b"1\n2\n", &config);
+        set.operate(b"2\n3\n", &config);
+        set.finish().unwrap();
+        let expected: [&[u8]; 3] = [b"1", b"3", b"4"];
+        assert_eq!(lines(&set), expected);
+        // The plain difference drops any line in any other operand
+        let texts = [first.clone(), b"1\n2\n".to_vec(), b"2\n3\n".to_vec()];
+        assert_eq!(diff_n(&texts, &config), b"4\n");
+    }
+
+    #[test]
+    fn exclusive_union_drops_lines_in_more_than_one_operand() {
+        let config = Config::default();
+        let mut set = ExclusiveUnionSet::init(b"a\nb\nc\na\n", &config);
+        set.operate(b"b\nd\n", &config);
+        set.operate(b"c\nb\ne\n", &config);
+        set.finish().unwrap();
+        // `c` is in two of the three files, and so isn't unique to one
+        assert_eq!(lines(&set), [&b"a"[..], b"d", b"e"]);
+    }
+
+    #[test]
+    fn exclusive_union_of_two_is_their_symmetric_difference() {
+        let config = Config::default();
+        let mut set = ExclusiveUnionSet::init(b"a\nb\n", &config);
+        set.operate(b"b\nc\n", &config);
+        set.finish().unwrap();
+        assert_eq!(lines(&set), [&b"a"[..], b"c"]);
+    }
+
+    #[test]
+    fn complement_is_the_universe_minus_the_union_of_the_rest() {
+        let config = Config::default();
+        // Operands drawn from a small alphabet, so they overlap in every way
+        let operand = |seed: usize, len: usize| -> TextVec {
+            (0..len).flat_map(|i| vec![b'a' + ((seed * 7 + i * i * 3) % 9) as u8, b'\n']).collect()
+        };
+        for seed in 0..20 {
+            let texts = [operand(seed, 8), operand(seed + 1, seed % 5), operand(seed + 2, 3)];
+            let mut set = ComplementSet::init(&texts[0], &config);
+            for text in &texts[1..] {
+                set.operate(text, &config);
+            }
+            let rest = union_n(&texts[1..], &config);
+            assert_eq!(set.to_bytes(), diff_n(&[texts[0].clone(), rest], &config), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn not_in_all_inverted_keeps_first_operand_lines_in_every_other() {
+        let config = Config { invert_match: true, ..Config::default() };
+        let first = b"1\n2\n3\n4\n".to_vec();
+        let mut set = NotInAllSet::init(&first, &config);
+        set.operate(b"3\n1\n2\n", &config);
+        set.operate(b"2\n3\n", &config);
+        set.finish().unwrap();
+        assert_eq!(lines(&set), [&b"2"[..], b"3"]);
+    }
+
+    #[test]
+    fn not_in_all_inverted_is_an_intersection_in_the_first_operands_order() {
+        let config = Config { invert_match: true, ..Config::default() };
+        let texts = [b"4\n3\n2\n1\n".to_vec(), b"1\n2\n3\n".to_vec(), b"3\n1\n2\n".to_vec()];
+        let mut set = NotInAllSet::init(&texts[0], &config);
+        set.operate(&texts[1], &config);
+        set.operate(&texts[2], &config);
+        set.finish().unwrap();
+        assert_eq!(set.to_bytes(), b"3\n2\n1\n");
+        // The same lines as the intersection, which takes the order of
+        // whichever operand comes first
+        let config = Config::default();
+        assert_eq!(intersect_n(&texts, &config), b"3\n2\n1\n");
+        assert_eq!(intersect_n(&[texts[1].clone(), texts[0].clone(), texts[2].clone()], &config), b"1\n2\n3\n");
+    }
+
+    #[test]
+    fn reading_an_operand_removes_what_operate_does() {
+        let operand = |seed: usize, len: usize| -> TextVec {
+            (0..len).flat_map(|i| vec![b'a' + ((seed * 5 + i * i) % 7) as u8, b' ', b'\n']).collect()
+        };
+        let trimming = Config { ignore_trailing_whitespace: true, ..Config::default() };
+        for config in [Config::default(), trimming] {
+            for seed in 0..20 {
+                let (universe, other) = (operand(seed, 6), operand(seed + 3, seed % 6));
+                let mut operated = ComplementSet::init(&universe, &config);
+                operated.operate(&other, &config);
+                let mut read = ComplementSet::init(&universe, &config);
+                read.operate_reader(&other[..], &config).unwrap();
+                assert_eq!(read.to_bytes(), operated.to_bytes(), "seed {}", seed);
+            }
+        }
+        // The last line needn't end with the delimiter
+        let mut set = ComplementSet::init(b"a\nb\nc\n", &Config::default());
+        set.operate_reader(&b"c\na"[..], &Config::default()).unwrap();
+        assert_eq!(set.to_bytes(), b"b\n");
+    }
+
+    #[test]
+    fn reading_stops_once_every_line_is_gone() {
+        let config = Config::default();
+        let mut set = ComplementSet::init(b"a\nb\n", &config);
+        set.operate_reader(then_broken(b"b\nc\na\n"), &config).unwrap();
+        assert!(set.is_settled());
+        let mut set = ComplementSet::default();
+        set.operate_reader(then_broken(b""), &config).unwrap();
+    }
+
+    #[test]
+    fn reading_records_takes_them_a_record_at_a_time() {
+        // Records can hold the delimiter: these are `a\n`, `bb` and `cc`, less
+        // `cc` and `a\n`
+        let config = Config { record_size: Some(2), ..Config::default() };
+        let mut operated = ComplementSet::init(b"a\nbbcc", &config);
+        operated.operate(b"cca\n", &config);
+        let mut read = ComplementSet::init(b"a\nbbcc", &config);
+        read.operate_reader(&b"cca\n"[..], &config).unwrap();
+        assert_eq!(lines(&read), lines(&operated));
+        assert_eq!(lines(&read), [b"bb"]);
+    }
+
+    #[test]
+    fn lines_found_before_a_read_error_are_still_dropped() {
+        let config = Config::default();
+        let mut set = ComplementSet::init(b"a\nb\nc\n", &config);
+        let err = set.operate_reader(then_broken(b"c\na\n"), &config).unwrap_err();
+        assert_eq!(err.to_string(), "broken");
+        assert_eq!(set.to_bytes(), b"b\n");
+    }
+}
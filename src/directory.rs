@@ -0,0 +1,125 @@
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{check_line_lengths, write_calculation, Config, OpName, SetopError, TextVec};
+
+// Every file under `dir`, however deep, sorted by path so the operands come
+// in the same order on every run. With `extension`, given as `.txt` or
+// `txt`, only files with that extension are listed. Symbolic links are
+// followed, so a link to a directory that contains it loops until the path
+// is too long.
+pub fn directory_files(dir: &Path, extension: Option<&str>) -> io::Result<Vec<PathBuf>> {
+    let extension = extension.map(|ext| ext.trim_start_matches('.'));
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if extension.is_none_or(|ext| path.extension().is_some_and(|found| found == ext)) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Perform `op` on the files `directory_files` lists, in that order, and
+// write the result to `out`. Each file's text is transformed as the options
+// in `config` say, and a line longer than `config.max_line_length` is dealt
+// with as `config.on_long_line` says; but unlike the command line this doesn't check
+// the record size.
+pub fn run_directory(
+    op: OpName,
+    dir: &Path,
+    extension_filter: Option<&str>,
+    config: &Config,
+    out: &mut impl Write,
+) -> Result<(), SetopError> {
+    let io_error = |path: &Path, err: io::Error| SetopError::Io(format!("{}: {}", path.display(), err));
+    let paths = directory_files(dir, extension_filter).map_err(|err| io_error(dir, err))?;
+    if paths.is_empty() {
+        return Err(SetopError::NoFiles(dir.to_path_buf()));
+    }
+    let mut texts: Vec<TextVec> = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let text = fs::read(path).map_err(|err| io_error(path, err))?;
+        check_line_lengths(path, &text, 0, config)?;
+        let transformed = match config.transform_text(&text) {
+            Cow::Owned(transformed) => Some(transformed),
+            Cow::Borrowed(_) => None,
+        };
+        texts.push(transformed.unwrap_or(text));
+    }
+    write_calculation(op, texts.iter(), out, config).map_err(|err| SetopError::Io(err.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::OnLongLine;
+
+    // A directory with a subdirectory, holding files named so that their
+    // sorted order isn't the order they're written in
+    fn tree() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("b.txt"), "2\n3\n4\n").unwrap();
+        fs::write(dir.path().join("a.txt"), "3\n2\n1\n").unwrap();
+        fs::write(dir.path().join("sub/c.txt"), "4\n3\n2\n").unwrap();
+        fs::write(dir.path().join("notes.md"), "5\n").unwrap();
+        dir
+    }
+
+    fn run(op: OpName, dir: &Path, extension: Option<&str>) -> Result<String, SetopError> {
+        let mut out = Vec::new();
+        run_directory(op, dir, extension, &Config::default(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn files_are_listed_recursively_in_path_order() {
+        let dir = tree();
+        let names = ["a.txt", "b.txt", "notes.md", "sub/c.txt"];
+        let expected: Vec<PathBuf> = names.iter().map(|name| dir.path().join(name)).collect();
+        assert_eq!(directory_files(dir.path(), None).unwrap(), expected);
+        assert_eq!(directory_files(dir.path(), Some(".md")).unwrap(), [dir.path().join("notes.md")]);
+        assert_eq!(directory_files(dir.path(), Some("txt")).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn operates_on_the_files_in_order() {
+        let dir = tree();
+        assert_eq!(run(OpName::Union, dir.path(), Some(".txt")).unwrap(), "3\n2\n1\n4\n");
+        assert_eq!(run(OpName::Intersect, dir.path(), Some(".txt")).unwrap(), "3\n2\n");
+        assert_eq!(run(OpName::Intersect, dir.path(), None).unwrap(), "");
+    }
+
+    #[test]
+    fn a_directory_without_matching_files_is_an_error() {
+        let dir = tree();
+        assert!(matches!(run(OpName::Union, dir.path(), Some(".csv")), Err(SetopError::NoFiles(path)) if path == dir.path()));
+    }
+
+    #[test]
+    fn long_lines_are_an_error_unless_skipped() {
+        let dir = tree();
+        fs::write(dir.path().join("sub/c.txt"), "4\n33\n").unwrap();
+        let limited = Config { max_line_length: Some(1), ..Config::default() };
+        let result = run_directory(OpName::Union, dir.path(), Some(".txt"), &limited, &mut Vec::new());
+        assert_eq!(result, Err(SetopError::LineTooLong { path: dir.path().join("sub/c.txt"), line: 2, length: 2 }));
+        let skipping = Config { on_long_line: OnLongLine::Skip, ..limited };
+        let mut out = Vec::new();
+        run_directory(OpName::Union, dir.path(), Some(".txt"), &skipping, &mut out).unwrap();
+        assert_eq!(out, b"3\n2\n1\n4\n");
+    }
+}
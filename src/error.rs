@@ -0,0 +1,47 @@
+use std::{error::Error, fmt, path::PathBuf};
+
+use crate::OpName;
+
+#[derive(Debug, PartialEq)]
+pub enum SetopError {
+    // Binary set data that doesn't start with the `SLSO` magic number
+    BadMagic,
+    // Binary set data that ends partway through a count, length, or line
+    Truncated,
+    // A result line, numbered from 1, that isn't valid UTF-8
+    Utf8Error { line: usize },
+    // An operation name that `OpName::from_str` doesn't know
+    UnknownOperation(String),
+    // A directory with no files to operate on
+    NoFiles(PathBuf),
+    // A line, numbered from 1, longer than `Config::max_line_length`, and its
+    // length in bytes
+    LineTooLong { path: PathBuf, line: usize, length: usize },
+    // Text that `SetExpr::from_str` can't parse, and what's wrong with it
+    BadExpression(String),
+    // A failure to read an operand or write the result, as a message that
+    // names the file where there is one
+    Io(String),
+}
+
+impl fmt::Display for SetopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SetopError::BadMagic => write!(f, "not a saved set (bad magic number)"),
+            SetopError::Truncated => write!(f, "saved set is truncated"),
+            SetopError::Utf8Error { line } => write!(f, "result line {} is not UTF-8", line),
+            SetopError::UnknownOperation(name) => {
+                let names: Vec<_> = OpName::ALL.iter().map(|op| op.to_string()).collect();
+                write!(f, "unknown operation {} (expected one of {})", name, names.join(", "))
+            }
+            SetopError::NoFiles(dir) => write!(f, "{}: no files to operate on", dir.display()),
+            SetopError::LineTooLong { path, line, length } => {
+                write!(f, "{}: line {} is too long ({} bytes)", path.display(), line, length)
+            }
+            SetopError::BadExpression(message) => write!(f, "bad expression: {}", message),
+            SetopError::Io(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for SetopError {}
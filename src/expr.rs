@@ -0,0 +1,235 @@
+use std::{iter::Peekable, str::FromStr, vec::IntoIter};
+
+use crate::{Config, SetopError, TextVec, UnionSet, UnionSetExt};
+
+// A `SetExpr` is a calculation over named operands, parsed from text like
+// `(a.txt + b.txt) x c.txt`: `+` is union and `x` is intersection, `x` binds
+// more tightly than `+`, so `a + b x c` is `a + (b x c)`, and both group from
+// the left. Parentheses group as usual. Operators are words of their own,
+// separated from the names around them by spaces or parentheses, so `a+b` is
+// a single name; a name can't contain spaces or parentheses, or be just `+`
+// or `x`.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub enum SetExpr {
+    Operand(String),
+    Union(Box<SetExpr>, Box<SetExpr>),
+    Intersect(Box<SetExpr>, Box<SetExpr>),
+}
+
+impl SetExpr {
+    // Every operand's name, in the order they appear, once for each time
+    pub fn operands(&self) -> Vec<&str> {
+        match self {
+            SetExpr::Operand(name) => vec![name.as_str()],
+            SetExpr::Union(left, right) | SetExpr::Intersect(left, right) => {
+                let mut names = left.operands();
+                names.extend(right.operands());
+                names
+            }
+        }
+    }
+
+    // Calculate the result, with `read` giving the text of each operand as
+    // it's needed. An operand that appears twice is read twice. Each side of
+    // an operator is its own `UnionSet`, so lines come out in the order they
+    // were first seen, reading from the left. Once the left side of an
+    // intersection is empty its right side isn't read.
+    pub fn evaluate<E>(&self, read: &mut impl FnMut(&str) -> Result<TextVec, E>, config: &Config) -> Result<UnionSet, E> {
+        match self {
+            SetExpr::Operand(name) => Ok(UnionSet::init(read(name)?, config)),
+            SetExpr::Union(left, right) => {
+                let mut set = left.evaluate(read, config)?;
+                set.merge(right.evaluate(read, config)?);
+                Ok(set)
+            }
+            SetExpr::Intersect(left, right) => {
+                let mut set = left.evaluate(read, config)?;
+                if set.is_empty() {
+                    return Ok(set);
+                }
+                let other = right.evaluate(read, config)?;
+                set.retain(|line| other.contains(line));
+                Ok(set)
+            }
+        }
+    }
+}
+
+impl FromStr for SetExpr {
+    type Err = SetopError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(text).into_iter().peekable();
+        let expr = parse_union(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(expr),
+            Some(Token::Close) => Err(bad("a ) with no ( before it")),
+            Some(token) => Err(bad(&format!("{} where an operator should be", token.describe()))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Plus,
+    Times,
+    Name(String),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+            Token::Plus => "+".to_string(),
+            Token::Times => "x".to_string(),
+            Token::Name(name) => name.clone(),
+        }
+    }
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let end_word = |word: &mut String, tokens: &mut Vec<Token>| {
+        match word.as_str() {
+            "" => return,
+            "+" => tokens.push(Token::Plus),
+            "x" => tokens.push(Token::Times),
+            _ => tokens.push(Token::Name(word.clone())),
+        }
+        word.clear();
+    };
+    for c in text.chars() {
+        match c {
+            '(' | ')' => {
+                end_word(&mut word, &mut tokens);
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            _ if c.is_whitespace() => end_word(&mut word, &mut tokens),
+            _ => word.push(c),
+        }
+    }
+    end_word(&mut word, &mut tokens);
+    tokens
+}
+
+fn bad(message: &str) -> SetopError {
+    SetopError::BadExpression(message.to_string())
+}
+
+// union := intersection ('+' intersection)*
+fn parse_union(tokens: &mut Tokens) -> Result<SetExpr, SetopError> {
+    let mut expr = parse_intersection(tokens)?;
+    while tokens.next_if_eq(&Token::Plus).is_some() {
+        expr = SetExpr::Union(Box::new(expr), Box::new(parse_intersection(tokens)?));
+    }
+    Ok(expr)
+}
+
+// intersection := operand ('x' operand)*
+fn parse_intersection(tokens: &mut Tokens) -> Result<SetExpr, SetopError> {
+    let mut expr = parse_operand(tokens)?;
+    while tokens.next_if_eq(&Token::Times).is_some() {
+        expr = SetExpr::Intersect(Box::new(expr), Box::new(parse_operand(tokens)?));
+    }
+    Ok(expr)
+}
+
+// operand := name | '(' union ')'
+fn parse_operand(tokens: &mut Tokens) -> Result<SetExpr, SetopError> {
+    match tokens.next() {
+        Some(Token::Name(name)) => Ok(SetExpr::Operand(name)),
+        Some(Token::Open) => {
+            let expr = parse_union(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(expr),
+                Some(token) => Err(bad(&format!("{} where an operator or ) should be", token.describe()))),
+                None => Err(bad("a ( with no ) after it")),
+            }
+        }
+        Some(token) => Err(bad(&format!("{} where a file name or ( should be", token.describe()))),
+        None => Err(bad("it ends where a file name or ( should be")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    fn operand(name: &str) -> Box<SetExpr> {
+        Box::new(SetExpr::Operand(name.to_string()))
+    }
+
+    fn parse(text: &str) -> SetExpr {
+        text.parse().unwrap()
+    }
+
+    // The result of `text` over operands named `a` to `d`, and the names in
+    // the order they were read
+    fn evaluate(text: &str) -> (String, Vec<String>) {
+        let texts: HashMap<&str, &str> = [("a", "1\n2\n3\n"), ("b", "3\n4\n"), ("c", "2\n4\n5\n"), ("d", "")].iter().copied().collect();
+        let mut read_names = Vec::new();
+        let mut read = |name: &str| -> Result<TextVec, ()> {
+            read_names.push(name.to_string());
+            Ok(texts[name].as_bytes().to_vec())
+        };
+        let set = parse(text).evaluate(&mut read, &Config::default()).unwrap();
+        let lines: Vec<_> = set.iter().map(|line| String::from_utf8_lossy(line).into_owned()).collect();
+        (lines.join(" "), read_names)
+    }
+
+    #[test]
+    fn intersection_binds_more_tightly_and_both_group_from_the_left() {
+        use SetExpr::*;
+        assert_eq!(parse("a + b x c"), Union(operand("a"), Box::new(Intersect(operand("b"), operand("c")))));
+        assert_eq!(parse("a x b + c"), Union(Box::new(Intersect(operand("a"), operand("b"))), operand("c")));
+        assert_eq!(parse("a + b + c"), Union(Box::new(Union(operand("a"), operand("b"))), operand("c")));
+        assert_eq!(parse("(a.txt+b.txt)x c.txt"), Intersect(operand("a.txt+b.txt"), operand("c.txt")));
+    }
+
+    #[test]
+    fn nested_parentheses_group() {
+        use SetExpr::*;
+        let expected = Intersect(Box::new(Union(operand("a"), Box::new(Intersect(operand("b"), operand("c"))))), operand("d"));
+        assert_eq!(parse("((a + (b x c))) x d"), expected);
+        assert_eq!(parse(" ( a+b ) x (c) ").operands(), ["a+b", "c"]);
+    }
+
+    #[test]
+    fn evaluates_nested_expressions_in_first_seen_order() {
+        assert_eq!(evaluate("a + b x c").0, "1 2 3 4");
+        assert_eq!(evaluate("(a + b) x c").0, "2 4");
+        assert_eq!(evaluate("c x (b + a)").0, "2 4");
+        assert_eq!(evaluate("(a x c) + (b x c)").0, "2 4");
+        assert_eq!(evaluate("((a x b) + (c x b)) x a").0, "3");
+    }
+
+    #[test]
+    fn an_empty_left_side_of_an_intersection_skips_reading_the_right() {
+        assert_eq!(evaluate("d x (a + b)"), (String::new(), vec!["d".to_string()]));
+        assert_eq!(evaluate("a + a").1, ["a", "a"]);
+    }
+
+    #[test]
+    fn bad_expressions_say_what_is_wrong() {
+        let error = |text: &str| match text.parse::<SetExpr>() {
+            Err(SetopError::BadExpression(message)) => message,
+            other => panic!("{:?} parsed as {:?}", text, other),
+        };
+        assert_eq!(error("(a + b"), "a ( with no ) after it");
+        assert_eq!(error("a + b)"), "a ) with no ( before it");
+        assert_eq!(error("a b"), "b where an operator should be");
+        assert_eq!(error("a + x c"), "x where a file name or ( should be");
+        assert_eq!(error("a +"), "it ends where a file name or ( should be");
+        assert_eq!(error("(a b)"), "b where an operator or ) should be");
+        assert_eq!(error(""), "it ends where a file name or ( should be");
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    cell::OnceCell,
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem,
+    path::PathBuf,
+};
+
+use tempfile::TempDir;
+
+use crate::{buffer::LineBuffer, output, Config, LineSet, OutputFormat, SetExpression, TextSlice, TextVec, UnionSet};
+
+// An `ExternalSortSet` computes a union without holding every distinct input
+// line in a hash at once. Lines go into an ordinary `UnionSet` until the lines
+// it holds add up to more than `chunk_size` bytes; then the set is sorted,
+// written to a file in `temp_dir` as a run, and emptied. `finish` merges the
+// sorted runs into one more file, keeping each line once.
+//
+// So the result stays on disk too, and `write_to` streams it from there when
+// it writes raw, unsorted lines. The inputs and the result can be much larger
+// than memory as long as the caller hands the inputs to `operate` a piece at
+// a time. Anything else that needs the lines themselves — `result_lines`, and
+// the other formats — reads the whole result into memory first. A result that
+// fits in one chunk is kept in memory from the start. The result comes out
+// sorted bytewise, not in first-seen order.
+//
+pub struct ExternalSortSet {
+    chunk_size: usize,
+    temp_dir: TempDir,
+    chunks: Vec<PathBuf>,
+    pending: UnionSet,
+    pending_bytes: usize,
+    // `insert_line` and `retain_lines` can't return an error, so a failed
+    // write is kept for `finish` or `write_to`
+    error: Option<io::Error>,
+    merged: Merged,
+}
+
+enum Merged {
+    InMemory(LineBuffer),
+    OnDisk { path: PathBuf, len: usize, loaded: OnceCell<io::Result<LineBuffer>> },
+}
+
+impl ExternalSortSet {
+    pub const DEFAULT_CHUNK_SIZE: usize = 64 << 20;
+
+    pub fn new(chunk_size: usize) -> io::Result<Self> {
+        Ok(ExternalSortSet {
+            chunk_size,
+            temp_dir: tempfile::tempdir()?,
+            chunks: Vec::new(),
+            pending: UnionSet::default(),
+            pending_bytes: 0,
+            error: None,
+            merged: Merged::InMemory(LineBuffer::default()),
+        })
+    }
+
+    // Sort the pending lines and write them out as the next run
+    fn spill(&mut self) -> io::Result<()> {
+        self.pending.sort();
+        let path = self.temp_dir.path().join(format!("run{}", self.chunks.len()));
+        let mut out = BufWriter::new(File::create(&path)?);
+        for line in &self.pending {
+            write_record(&mut out, line)?;
+        }
+        out.flush()?;
+        self.chunks.push(path);
+        self.pending.clear();
+        self.pending_bytes = 0;
+        Ok(())
+    }
+
+    // A k-way merge of the runs into the result file: the heap holds the
+    // smallest line not yet taken from each run, tagged with the run it came
+    // from, so memory holds one line per run. The runs go once they're merged.
+    fn merge(&mut self) -> io::Result<()> {
+        let mut runs = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for (k, path) in self.chunks.iter().enumerate() {
+            let mut run = BufReader::new(File::open(path)?);
+            if let Some(line) = read_record(&mut run)? {
+                heap.push(Reverse((line, k)));
+            }
+            runs.push(run);
+        }
+        let path = self.temp_dir.path().join("result");
+        let mut out = BufWriter::new(File::create(&path)?);
+        let mut len = 0;
+        let mut previous: Option<TextVec> = None;
+        while let Some(Reverse((line, k))) = heap.pop() {
+            if let Some(next) = read_record(&mut runs[k])? {
+                heap.push(Reverse((next, k)));
+            }
+            if previous.as_ref() != Some(&line) {
+                write_record(&mut out, &line)?;
+                len += 1;
+                previous = Some(line);
+            }
+        }
+        out.flush()?;
+        for run in mem::take(&mut self.chunks) {
+            fs::remove_file(run)?;
+        }
+        self.merged = Merged::OnDisk { path, len, loaded: OnceCell::new() };
+        Ok(())
+    }
+
+    // Copy the result file, keeping only the lines `keep` accepts
+    fn retain_on_disk(path: &PathBuf, mut keep: impl FnMut(&TextSlice) -> bool) -> io::Result<usize> {
+        let kept_path = path.with_extension("kept");
+        let mut out = BufWriter::new(File::create(&kept_path)?);
+        let mut len = 0;
+        for line in Records(BufReader::new(File::open(path)?)) {
+            let line = line?;
+            if keep(&line) {
+                write_record(&mut out, &line)?;
+                len += 1;
+            }
+        }
+        out.flush()?;
+        fs::rename(kept_path, path)?;
+        Ok(len)
+    }
+
+    // The error kept from `insert_line` or `retain_lines`, as one that can be
+    // returned while it's still kept
+    fn kept_error(&self) -> Option<io::Error> {
+        self.error.as_ref().map(|err| io::Error::new(err.kind(), err.to_string()))
+    }
+}
+
+// Runs are stored as length-prefixed records, so lines may contain any bytes
+fn write_record(out: &mut impl Write, line: &TextSlice) -> io::Result<()> {
+    out.write_all(&(line.len() as u64).to_le_bytes())?;
+    out.write_all(line)
+}
+
+fn read_record(run: &mut impl Read) -> io::Result<Option<TextVec>> {
+    let mut len = [0; 8];
+    match run.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut line = vec![0; u64::from_le_bytes(len) as usize];
+    run.read_exact(&mut line)?;
+    Ok(Some(line))
+}
+
+// The records of a run or of the result file, one at a time
+struct Records<R>(R);
+
+impl<R: Read> Iterator for Records<R> {
+    type Item = io::Result<TextVec>;
+    fn next(&mut self) -> Option<Self::Item> {
+        read_record(&mut self.0).transpose()
+    }
+}
+
+fn read_result(path: &PathBuf) -> io::Result<LineBuffer> {
+    let mut lines = LineBuffer::default();
+    for line in Records(BufReader::new(File::open(path)?)) {
+        lines.push(&[&line?]);
+    }
+    Ok(lines)
+}
+
+impl<'a> LineSet<'a> for ExternalSortSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        if self.error.is_some() {
+            return;
+        }
+        if self.pending.insert(line.to_vec()) {
+            self.pending_bytes += line.len();
+        }
+        if self.pending_bytes > self.chunk_size {
+            if let Err(err) = self.spill() {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+impl<'data> SetExpression<'data> for ExternalSortSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.chunks.is_empty() {
+            // Everything fit in one chunk, so there's nothing to merge
+            let mut pending = mem::take(&mut self.pending);
+            pending.sort();
+            let mut merged = LineBuffer::default();
+            for line in &pending {
+                merged.push(&[line]);
+            }
+            self.merged = Merged::InMemory(merged);
+            return Ok(());
+        }
+        if !self.pending.is_empty() {
+            self.spill()?;
+        }
+        self.merge()
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        match &self.merged {
+            Merged::InMemory(lines) => Box::new(lines.iter()),
+            // A result that can't be read back has no lines here; `write_to`
+            // reports the error
+            Merged::OnDisk { path, loaded, .. } => match loaded.get_or_init(|| read_result(path)) {
+                Ok(lines) => Box::new(lines.iter()),
+                Err(_) => Box::new(std::iter::empty()),
+            },
+        }
+    }
+    fn retain_lines(&mut self, keep: impl FnMut(&TextSlice) -> bool) {
+        match &mut self.merged {
+            Merged::InMemory(lines) => lines.retain(keep),
+            Merged::OnDisk { path, len, loaded } => {
+                *loaded = OnceCell::new();
+                match ExternalSortSet::retain_on_disk(path, keep) {
+                    Ok(kept) => *len = kept,
+                    Err(err) => self.error = Some(err),
+                }
+            }
+        }
+    }
+    fn result_len(&self) -> usize {
+        match &self.merged {
+            Merged::InMemory(lines) => lines.iter().count(),
+            Merged::OnDisk { len, .. } => *len,
+        }
+    }
+    // Raw lines in the order they're kept in can come straight from the
+    // result file; anything else needs them all at once
+    fn write_to(&self, out: &mut impl Write, config: &Config) -> io::Result<()> {
+        if let Some(err) = self.kept_error() {
+            return Err(err);
+        }
+        let (path, loaded) = match &self.merged {
+            Merged::OnDisk { path, loaded, .. } => (path, loaded),
+            Merged::InMemory(_) => return output::write_lines(self, out, config),
+        };
+        if config.format != OutputFormat::Raw || config.numeric_sort.is_some() || config.sample.is_some() {
+            if let Err(err) = loaded.get_or_init(|| read_result(path)) {
+                return Err(io::Error::new(err.kind(), err.to_string()));
+            }
+            return output::write_lines(self, out, config);
+        }
+        let mut error = None;
+        let lines = Records(BufReader::new(File::open(path)?)).map_while(|line| line.map_err(|err| error = Some(err)).ok());
+        let max_lines = config.max_lines.unwrap_or(usize::MAX);
+        output::write_raw(lines.skip(config.skip_lines).take(max_lines), out, config)?;
+        error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finish_and_write;
+
+    fn union_of(texts: &[&[u8]], chunk_size: usize, config: &Config) -> TextVec {
+        let mut set = ExternalSortSet::new(chunk_size).unwrap();
+        for text in texts {
+            set.operate(text, config);
+        }
+        let mut out = TextVec::new();
+        finish_and_write(&mut set, &mut out, config).unwrap();
+        out
+    }
+
+    #[test]
+    fn ten_megabytes_with_duplicates_come_out_once_each_and_sorted() {
+        // 100,000 distinct lines of 20 bytes, each six times over
+        let line = |n: usize| format!("{:08} {:>10}\n", n * 7919 % 100_000, "x");
+        let text: String = (0..600_000).map(|n| line(n % 100_000)).collect();
+        assert!(text.len() >= 10 << 20);
+        let out = union_of(&[text.as_bytes()], 1 << 20, &Config::default());
+        let mut expected: Vec<String> = (0..100_000).map(line).collect();
+        expected.sort();
+        assert_eq!(String::from_utf8(out).unwrap(), expected.concat());
+    }
+
+    #[test]
+    fn lines_repeated_across_runs_and_operands_are_kept_once() {
+        let out = union_of(&[b"c\na\nb\n", b"b\nd\na\n", b"a\ne\n"], 2, &Config::default());
+        assert_eq!(out, b"a\nb\nc\nd\ne\n");
+    }
+
+    #[test]
+    fn a_result_that_fits_in_one_chunk_stays_in_memory() {
+        let mut set = ExternalSortSet::new(1 << 20).unwrap();
+        set.operate(b"b\na\nb\n", &Config::default());
+        set.finish().unwrap();
+        assert!(matches!(set.merged, Merged::InMemory(_)));
+        assert_eq!(set.result_lines().collect::<Vec<_>>(), [b"a", b"b"]);
+    }
+
+    #[test]
+    fn a_merged_result_is_kept_on_disk_and_filtered_there() {
+        let mut set = ExternalSortSet::new(2).unwrap();
+        set.operate(b"ccc\na\nbb\ndddd\n", &Config::default());
+        set.finish().unwrap();
+        assert!(matches!(set.merged, Merged::OnDisk { .. }));
+        set.retain_lines(|line| line.len() % 2 == 0);
+        assert_eq!(set.result_len(), 2);
+        assert_eq!(set.result_lines().collect::<Vec<_>>(), [&b"bb"[..], b"dddd"]);
+    }
+
+    #[test]
+    fn streamed_output_applies_skip_max_lines_and_the_delimiter() {
+        let config = Config { skip_lines: 1, max_lines: Some(2), delimiter: b',', ..Config::default() };
+        assert_eq!(union_of(&[b"d,c,b,a,e"], 2, &config), b"b,c,");
+        let config = Config { no_final_delimiter: true, ..Config::default() };
+        assert_eq!(union_of(&[b"b\na\n"], 1, &config), b"a\nb");
+    }
+
+    #[test]
+    fn other_formats_read_the_merged_result_back() {
+        let config = Config { format: OutputFormat::Json, ..Config::default() };
+        assert_eq!(union_of(&[b"b\na\nb\n"], 1, &config), b"[\"a\",\"b\"]\n");
+    }
+}
@@ -0,0 +1,64 @@
+use std::borrow::Cow;
+
+use crate::{TextSlice, TextVec};
+
+// Which fields of each line the keyed operations compare, numbered from 1, and
+// the byte that ends each field
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldConfig {
+    pub field_indices: Vec<usize>,
+    pub field_delimiter: u8,
+}
+
+// The fields of `line` that `fields` picks, in the order it lists them, joined
+// by the delimiter. A field past the end of the line is empty, as is field 0.
+pub fn select_fields<'a>(line: &'a TextSlice, fields: &FieldConfig) -> Cow<'a, TextSlice> {
+    let mut spans = Vec::new();
+    let mut begin = 0;
+    for end in memchr::memchr_iter(fields.field_delimiter, line) {
+        spans.push(begin..end);
+        begin = end + 1;
+    }
+    spans.push(begin..line.len());
+    let field = |n: usize| n.checked_sub(1).and_then(|i| spans.get(i)).map_or(&[][..], |span| &line[span.clone()]);
+    if let [n] = fields.field_indices[..] {
+        return Cow::Borrowed(field(n));
+    }
+    let mut selected = TextVec::new();
+    for (i, &n) in fields.field_indices.iter().enumerate() {
+        if i > 0 {
+            selected.push(fields.field_delimiter);
+        }
+        selected.extend_from_slice(field(n));
+    }
+    Cow::Owned(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(field_indices: &[usize], field_delimiter: u8) -> FieldConfig {
+        FieldConfig { field_indices: field_indices.to_vec(), field_delimiter }
+    }
+
+    #[test]
+    fn one_field_is_borrowed_from_the_line() {
+        let selected = select_fields(b"chr1\t100\t200", &fields(&[2], b'\t'));
+        assert!(matches!(selected, Cow::Borrowed(b"100")));
+    }
+
+    #[test]
+    fn several_fields_are_joined_by_the_delimiter_in_the_order_given() {
+        assert_eq!(&*select_fields(b"a b c d", &fields(&[3, 1], b' ')), b"c a");
+        assert_eq!(&*select_fields(b"root:x:0:0", &fields(&[1, 3, 4], b':')), b"root:0:0");
+    }
+
+    #[test]
+    fn missing_fields_are_empty() {
+        assert_eq!(&*select_fields(b"a\tb", &fields(&[5], b'\t')), b"");
+        assert_eq!(&*select_fields(b"a\tb", &fields(&[0], b'\t')), b"");
+        assert_eq!(&*select_fields(b"a\tb", &fields(&[2, 3], b'\t')), b"b\t");
+        assert_eq!(&*select_fields(b"a\t\tc", &fields(&[2], b'\t')), b"");
+    }
+}
@@ -0,0 +1,114 @@
+use std::io;
+
+use indexmap::IndexMap;
+
+use crate::{buffer::LineBuffer, Config, LineSet, SetExpression, TextSlice, TextVec, UnionSet};
+
+// A `GroupByFieldSet` is a union for each value of one field: every line goes
+// into the union for its field number `key_field`, counting from 1, where
+// fields end at `config.field_delimiter`. A line with fewer fields goes into
+// the group with an empty key. `finish` writes each group's key on a line of
+// its own, followed by the group's lines each indented by a tab, with the
+// groups and their lines in the order they were first seen. Filters see the
+// lines without the tab, and a group left with no lines is dropped.
+//
+pub struct GroupByFieldSet {
+    key_field: usize,
+    delimiter: u8,
+    groups: IndexMap<TextVec, UnionSet>,
+    grouped: LineBuffer,
+}
+
+impl GroupByFieldSet {
+    pub fn init(text: &TextSlice, key_field: usize, config: &Config) -> Self {
+        let mut set = GroupByFieldSet {
+            key_field,
+            delimiter: config.field_delimiter,
+            groups: IndexMap::new(),
+            grouped: LineBuffer::default(),
+        };
+        set.operate(text, config);
+        set
+    }
+
+    fn write_groups(&mut self) {
+        self.grouped = LineBuffer::default();
+        for (key, lines) in &self.groups {
+            self.grouped.push(&[key]);
+            for line in lines {
+                self.grouped.push(&[b"\t", line]);
+            }
+        }
+    }
+}
+
+impl<'a> LineSet<'a> for GroupByFieldSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        let key = line.split(|&b| b == self.delimiter).nth(self.key_field.saturating_sub(1)).unwrap_or(&[]);
+        if !self.groups.contains_key(key) {
+            self.groups.insert(key.to_vec(), UnionSet::default());
+        }
+        if let Some(group) = self.groups.get_mut(key) {
+            if !group.contains(line) {
+                group.insert(line.to_vec());
+            }
+        }
+    }
+}
+
+impl<'data> SetExpression<'data> for GroupByFieldSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_groups();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.grouped.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        for lines in self.groups.values_mut() {
+            lines.retain(|line| keep(line));
+        }
+        self.groups.retain(|_, lines| !lines.is_empty());
+        // If `finish` has already run, its output needs the same filtering
+        if !self.grouped.is_empty() {
+            self.write_groups();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_group_is_a_union_of_its_lines() {
+        let config = Config::default();
+        let mut set = GroupByFieldSet::init(b"u1\tlogin\nu2\tview\nu1\tlogin\nu1\tview\n", 1, &config);
+        set.operate(b"u2\tview\nu3\tlogin\nu1\tlogout\n", &config);
+        set.finish().unwrap();
+        assert_eq!(
+            set.to_bytes(),
+            &b"u1\n\tu1\tlogin\n\tu1\tview\n\tu1\tlogout\nu2\n\tu2\tview\nu3\n\tu3\tlogin\n"[..]
+        );
+    }
+
+    #[test]
+    fn lines_without_the_field_group_under_an_empty_key() {
+        let config = Config { field_delimiter: b',', ..Config::default() };
+        let mut set = GroupByFieldSet::init(b"a,x\nb,y\nlone\nc,x\n", 2, &config);
+        set.finish().unwrap();
+        assert_eq!(set.to_bytes(), &b"x\n\ta,x\n\tc,x\ny\n\tb,y\n\n\tlone\n"[..]);
+    }
+
+    #[test]
+    fn filters_see_lines_without_the_tab_and_drop_emptied_groups() {
+        let config = Config::default();
+        let mut set = GroupByFieldSet::init(b"k1\ta\nk2\tbb\nk1\tccc\n", 1, &config);
+        set.finish().unwrap();
+        set.retain_lines(|line| line.len() > 5);
+        assert_eq!(set.to_bytes(), &b"k1\n\tk1\tccc\n"[..]);
+    }
+}
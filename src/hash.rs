@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+
+use crate::{Config, LineSet, TextSlice, TextVec};
+
+// Hashing replaces each line of an operand with the hex SHA-256 digest of the
+// line, so sets can be compared without either side's text being shown: the
+// result says only which digests the operands share. The digest is of the
+// line as a set would store it — without its delimiter, and normalized — and
+// the digests keep the lines' order, each ending in the delimiter. Every line
+// is hashed, whatever shard it's in: the sets shard the digests.
+//
+struct Digests {
+    text: TextVec,
+    delimiter: u8,
+}
+
+impl<'a> LineSet<'a> for Digests {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        for byte in Sha256::digest(line) {
+            self.text.push(HEX[usize::from(byte >> 4)]);
+            self.text.push(HEX[usize::from(byte & 0xf)]);
+        }
+        self.text.push(self.delimiter);
+    }
+}
+
+pub(crate) fn hash_lines(text: &TextSlice, config: &Config) -> TextVec {
+    let mut digests = Digests { text: TextVec::new(), delimiter: config.delimiter };
+    digests.insert_all_lines(text, &Config { shard: None, ..config.clone() });
+    digests.text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Shard;
+
+    #[test]
+    fn each_line_becomes_its_hex_digest() {
+        let hashed = hash_lines(b"abc\n\nabc", &Config::default());
+        let abc = b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n";
+        let empty = b"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n";
+        assert_eq!(hashed, [&abc[..], empty, abc].concat());
+    }
+
+    #[test]
+    fn every_line_is_hashed_whatever_the_shard() {
+        let text: TextVec = (0..40).flat_map(|n| format!("line {}\n", n).into_bytes()).collect();
+        let sharded = Config { shard: Some(Shard { index: 1, count: 3 }), ..Config::default() };
+        assert_eq!(hash_lines(&text, &sharded), hash_lines(&text, &Config::default()));
+    }
+}
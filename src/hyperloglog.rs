@@ -0,0 +1,97 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use crate::{Config, LineSet, TextSlice};
+
+// A `HyperLogLog` sketch estimates how many distinct lines it has seen without
+// remembering the lines themselves: each line's hash picks one of 64 registers
+// and the register keeps the longest run of leading zeros seen in the rest of
+// the hash. Memory is fixed at 64 bytes no matter how big the input is, and
+// the standard error is about 1.04/√64, or 13%.
+//
+const REGISTER_BITS: u32 = 6;
+const REGISTER_COUNT: usize = 1 << REGISTER_BITS;
+
+pub struct HyperLogLog {
+    registers: [u8; REGISTER_COUNT],
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        HyperLogLog { registers: [0; REGISTER_COUNT] }
+    }
+}
+
+impl HyperLogLog {
+    pub fn estimate(&self) -> f64 {
+        let m = REGISTER_COUNT as f64;
+        // The bias correction constant for 64 registers
+        let alpha = 0.709;
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-i32::from(r))).sum();
+        let raw = alpha * m * m / sum;
+        // For small cardinalities many registers are still empty, and linear
+        // counting of the empty registers is more accurate than the raw estimate
+        let empty = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && empty > 0 {
+            m * (m / empty as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+// The low bits of the hash choose the register, and the rank is the position
+// of the first 1 bit in what's left.
+impl<'a> LineSet<'a> for HyperLogLog {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        let mut hasher = DefaultHasher::new();
+        line.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash & (REGISTER_COUNT as u64 - 1)) as usize;
+        let rank = ((hash >> REGISTER_BITS).leading_zeros() - REGISTER_BITS + 1) as u8;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+}
+
+// Estimate the size of the union of `texts` in constant memory — for inputs
+// too big to build a `UnionSet` from.
+pub fn estimate_union_cardinality(texts: &[&TextSlice], config: &Config) -> f64 {
+    let mut sketch = HyperLogLog::default();
+    for text in texts {
+        sketch.insert_all_lines(text, config);
+    }
+    sketch.estimate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_lines(range: std::ops::Range<usize>) -> Vec<u8> {
+        range.map(|n| format!("line {}\n", n)).collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn estimate_is_within_15_percent_of_the_exact_union() {
+        let a = numbered_lines(0..30_000);
+        let b = numbered_lines(20_000..50_000);
+        let estimate = estimate_union_cardinality(&[&a, &b], &Config::default());
+        assert!((estimate - 50_000.0).abs() < 50_000.0 * 0.15, "estimate {}", estimate);
+    }
+
+    #[test]
+    fn small_unions_are_counted_closely() {
+        let a = numbered_lines(0..10);
+        let estimate = estimate_union_cardinality(&[&a, &a], &Config::default());
+        assert!((estimate - 10.0).abs() < 1.5, "estimate {}", estimate);
+    }
+
+    #[test]
+    fn nothing_estimates_to_zero() {
+        assert_eq!(estimate_union_cardinality(&[], &Config::default()), 0.0);
+    }
+}
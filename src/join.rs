@@ -0,0 +1,109 @@
+use indexmap::IndexMap;
+
+use crate::{Config, LineSet, SetExpression, TextSlice, TextVec};
+
+// A `JoinSet` is an intersection keyed on the first field of each line, where
+// fields end at `config.field_delimiter`, that also gathers the other fields:
+// each result line is the key followed by the rest of the line from every
+// operand in turn, like a SQL inner join. A key missing from any operand is
+// dropped, as in any intersection. Within one operand only the first line
+// with a key counts, and a line with no delimiter is a key with an empty rest,
+// so every result line has one more field per operand.
+//
+pub struct JoinSet<'data> {
+    delimiter: u8,
+    rows: IndexMap<&'data TextSlice, TextVec>,
+}
+
+// The rest of the first line with each key, in one operand
+struct Fields<'a> {
+    delimiter: u8,
+    rests: IndexMap<&'a TextSlice, &'a TextSlice>,
+}
+
+impl<'a> LineSet<'a> for Fields<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        let (key, rest) = match memchr::memchr(self.delimiter, line) {
+            Some(split) => (&line[..split], &line[split + 1..]),
+            None => (line, &line[line.len()..]),
+        };
+        self.rests.entry(key).or_insert(rest);
+    }
+}
+
+impl<'a> Fields<'a> {
+    fn of(text: &'a TextSlice, config: &Config) -> Self {
+        let mut fields = Fields { delimiter: config.field_delimiter, rests: IndexMap::new() };
+        fields.insert_all_lines(text, config);
+        fields
+    }
+}
+
+impl<'data> JoinSet<'data> {
+    pub fn init(text: &'data TextSlice, config: &Config) -> Self {
+        let delimiter = config.field_delimiter;
+        let rows = Fields::of(text, config)
+            .rests
+            .into_iter()
+            .map(|(key, rest)| (key, [key, &[delimiter], rest].concat()))
+            .collect();
+        JoinSet { delimiter, rows }
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for JoinSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        let other = Fields::of(text, config);
+        let delimiter = self.delimiter;
+        self.rows.retain(|key, row| match other.rests.get(key) {
+            Some(rest) => {
+                row.push(delimiter);
+                row.extend_from_slice(rest);
+                true
+            }
+            None => false,
+        });
+    }
+    fn is_settled(&self) -> bool {
+        self.rows.is_empty()
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.rows.values().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.rows.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.rows.retain(|_, row| keep(row));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn joins_the_rest_of_each_line_on_keys_in_every_operand() {
+        let config = Config::default();
+        let first = b"1\tapple\tred\n2\tpear\n3\tfig\n".to_vec();
+        let mut set = JoinSet::init(&first, &config);
+        set.operate(b"3\t30\n1\t10\n1\tignored\n", &config);
+        set.operate(b"1\tx\n3\n", &config);
+        assert_eq!(rows(&set), [&b"1\tapple\tred\t10\tx"[..], b"3\tfig\t30\t"]);
+    }
+
+    #[test]
+    fn a_key_missing_from_one_operand_is_dropped() {
+        let config = Config { field_delimiter: b',', ..Config::default() };
+        let first = b"a,1\nb,2\n".to_vec();
+        let mut set = JoinSet::init(&first, &config);
+        set.operate(b"b,3\n", &config);
+        assert_eq!(rows(&set), [&b"b,2,3"[..]]);
+        set.operate(b"a,4\n", &config);
+        assert!(set.is_settled());
+    }
+}
@@ -0,0 +1,188 @@
+use std::borrow::Cow;
+
+use indexmap::IndexMap;
+
+use crate::{fields::select_fields, Config, FieldConfig, LineSet, SetExpression, TextSlice, TextVec};
+
+// Keyed sets work like `UnionSet` and `IntersectSet`, but two lines count as
+// the same member when their keys match. The result holds the first whole
+// line seen with each key.
+//
+#[derive(Clone)]
+enum Key {
+    // The first this many bytes, as with `uniq -w`. A line shorter than that
+    // is its own key.
+    Prefix(usize),
+    // The fields, ending at this byte, in sorted order, so lines with the same
+    // fields in any order match. A field that's there twice must be there
+    // twice in the other line too.
+    SortedFields(u8),
+    // Just the fields `select_fields` picks
+    Fields(FieldConfig),
+}
+
+fn line_key<'a>(line: &'a TextSlice, key: &Key) -> Cow<'a, TextSlice> {
+    match *key {
+        Key::Prefix(width) => Cow::Borrowed(&line[..line.len().min(width)]),
+        Key::SortedFields(delimiter) => {
+            let mut fields: Vec<_> = line.split(|&b| b == delimiter).collect();
+            if fields.windows(2).all(|pair| pair[0] <= pair[1]) {
+                return Cow::Borrowed(line);
+            }
+            fields.sort_unstable();
+            Cow::Owned(fields.join(&delimiter))
+        }
+        Key::Fields(ref fields) => select_fields(line, fields),
+    }
+}
+
+// Each key maps to the first line that had it
+struct KeyedLines<'a> {
+    key: Key,
+    lines: IndexMap<Cow<'a, TextSlice>, &'a TextSlice>,
+}
+
+impl<'a> KeyedLines<'a> {
+    fn new(key: Key) -> Self {
+        KeyedLines { key, lines: IndexMap::new() }
+    }
+}
+
+impl<'a> LineSet<'a> for KeyedLines<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.lines.entry(line_key(line, &self.key)).or_insert(line);
+    }
+}
+
+// Later operands' lines can't be borrowed, so a union owns both its keys and
+// its lines
+pub struct KeyedUnionSet {
+    key: Key,
+    lines: IndexMap<TextVec, TextVec>,
+}
+
+impl KeyedUnionSet {
+    pub fn init(text: &TextSlice, key_width: usize, config: &Config) -> Self {
+        Self::init_with_key(text, Key::Prefix(key_width), config)
+    }
+
+    // Lines match when they have the same fields, ending at
+    // `config.field_delimiter`, in any order
+    pub fn init_ignoring_field_order(text: &TextSlice, config: &Config) -> Self {
+        Self::init_with_key(text, Key::SortedFields(config.field_delimiter), config)
+    }
+
+    // Lines match when the fields `fields` picks match
+    pub fn init_by_fields(text: &TextSlice, fields: &FieldConfig, config: &Config) -> Self {
+        Self::init_with_key(text, Key::Fields(fields.clone()), config)
+    }
+
+    fn init_with_key(text: &TextSlice, key: Key, config: &Config) -> Self {
+        let mut set = KeyedUnionSet { key, lines: IndexMap::new() };
+        set.operate(text, config);
+        set
+    }
+}
+
+impl<'data> SetExpression<'data> for KeyedUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        let mut other = KeyedLines::new(self.key.clone());
+        other.insert_all_lines(text, config);
+        for (key, line) in other.lines {
+            self.lines.entry(key.into_owned()).or_insert_with(|| line.to_vec());
+        }
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.lines.values().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.lines.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.lines.retain(|_, line| keep(line));
+    }
+}
+
+// As with `IntersectSet`, the keys and lines are borrowed from the first
+// operand
+pub struct KeyedIntersectSet<'data>(KeyedLines<'data>);
+
+impl<'data> KeyedIntersectSet<'data> {
+    pub fn init(text: &'data TextSlice, key_width: usize, config: &Config) -> Self {
+        Self::init_with_key(text, Key::Prefix(key_width), config)
+    }
+
+    pub fn init_ignoring_field_order(text: &'data TextSlice, config: &Config) -> Self {
+        Self::init_with_key(text, Key::SortedFields(config.field_delimiter), config)
+    }
+
+    pub fn init_by_fields(text: &'data TextSlice, fields: &FieldConfig, config: &Config) -> Self {
+        Self::init_with_key(text, Key::Fields(fields.clone()), config)
+    }
+
+    fn init_with_key(text: &'data TextSlice, key: Key, config: &Config) -> Self {
+        let mut lines = KeyedLines::new(key);
+        lines.insert_all_lines(text, config);
+        KeyedIntersectSet(lines)
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for KeyedIntersectSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        let mut other = KeyedLines::new(self.0.key.clone());
+        other.insert_all_lines(text, config);
+        self.0.lines.retain(|key, _| other.lines.contains_key(key.as_ref()));
+    }
+    fn is_settled(&self) -> bool {
+        self.0.lines.is_empty()
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.lines.values().cloned())
+    }
+    fn result_len(&self) -> usize {
+        self.0.lines.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.lines.retain(|_, line| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn union_keeps_the_first_whole_line_with_each_prefix() {
+        let config = Config::default();
+        let mut set = KeyedUnionSet::init(b"abc-first\nab\n", 3, &config);
+        set.operate(b"abc-second\nab\nxyz\n", &config);
+        let expected: [&[u8]; 3] = [b"abc-first", b"ab", b"xyz"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn intersection_matches_lines_by_prefix_and_writes_the_first_operands() {
+        let config = Config::default();
+        let first = b"2024-01-01 long line one\n2024-02-02 other\nabc\n".to_vec();
+        let mut set = KeyedIntersectSet::init(&first, 10, &config);
+        set.operate(b"2024-01-01 a different ending\nabc\nabcdef\n", &config);
+        let expected: [&[u8]; 2] = [b"2024-01-01 long line one", b"abc"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn ignoring_field_order_matches_reordered_columns() {
+        let config = Config { field_delimiter: b',', ..Config::default() };
+        let first = b"id,name,city\n1,ann,oslo\n2,bo,rome\n".to_vec();
+        let mut set = KeyedIntersectSet::init_ignoring_field_order(&first, &config);
+        set.operate(b"name,city,id\noslo,1,ann\nrome,2,bob\n", &config);
+        assert_eq!(lines(&set), [&b"id,name,city"[..], b"1,ann,oslo"]);
+        let mut set = KeyedUnionSet::init_ignoring_field_order(b"a,b\nb,b,a\n", &config);
+        set.operate(b"b,a\na,b,b\n", &config);
+        assert_eq!(lines(&set), [&b"a,b"[..], b"b,b,a"]);
+    }
+}
@@ -0,0 +1,44 @@
+use indexmap::set;
+
+use crate::{SliceSet, TextSlice};
+
+// A `LazyIntersectIterator` yields the lines of `first` that are also in
+// `other`, in `first`'s order, as they're asked for. Unlike an `IntersectSet`
+// it never builds a set of the survivors, so a caller that handles the lines
+// one at a time needs nothing beyond the two sets themselves.
+//
+pub struct LazyIntersectIterator<'a> {
+    lines: set::Iter<'a, &'a TextSlice>,
+    other: &'a SliceSet<'a>,
+}
+
+impl<'a> LazyIntersectIterator<'a> {
+    pub fn new(first: &'a SliceSet<'a>, other: &'a SliceSet<'a>) -> Self {
+        LazyIntersectIterator { lines: first.iter(), other }
+    }
+}
+
+impl<'a> Iterator for LazyIntersectIterator<'a> {
+    type Item = &'a TextSlice;
+    fn next(&mut self) -> Option<&'a TextSlice> {
+        let other = self.other;
+        self.lines.by_ref().cloned().find(|line| other.contains(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, LineSet};
+
+    #[test]
+    fn yields_the_common_lines_in_first_operand_order() {
+        let config = Config::default();
+        let first = SliceSet::init_from_slice(b"d\nb\na\nb\nc\n", &config);
+        let other = SliceSet::init_from_slice(b"a\nc\nd\ne\n", &config);
+        let lines: Vec<&TextSlice> = LazyIntersectIterator::new(&first, &other).collect();
+        assert_eq!(lines, [&b"d"[..], b"a", b"c"]);
+        let empty = SliceSet::default();
+        assert_eq!(LazyIntersectIterator::new(&first, &empty).next(), None);
+    }
+}
@@ -0,0 +1,1496 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{self, Write},
+    path::Path,
+    slice::Iter,
+    str::{self, FromStr},
+};
+
+use indexmap::{IndexMap, IndexSet};
+use memchr::{Memchr, Memchr3};
+
+mod annotated;
+mod binary;
+mod bounded;
+mod buffer;
+mod cache;
+mod cached;
+mod comm;
+mod concat;
+mod cover;
+mod cross;
+mod diff3;
+mod difference;
+mod directory;
+mod error;
+mod expr;
+mod external;
+mod fields;
+mod group;
+mod hash;
+mod hyperloglog;
+mod join;
+mod keyed;
+mod lazy;
+mod map;
+mod merge;
+mod multiset;
+mod output;
+mod pipeline;
+mod positions;
+mod rolling;
+mod sorted;
+mod stats;
+mod streaming;
+mod threshold;
+mod utf16;
+mod weighted;
+mod wordfreq;
+mod words;
+mod writer;
+
+#[cfg(test)]
+mod tests;
+pub use crate::annotated::{AnnotatedIntersectSet, AnnotatedUnionSet};
+pub use crate::bounded::BoundedUnionSet;
+pub use crate::cache::{content_hash, SetCache};
+pub use crate::cached::CachedIntersectSet;
+pub use crate::comm::CommSet;
+pub use crate::concat::ConcatSet;
+pub use crate::cover::minimum_set_cover;
+pub use crate::cross::{cross_product, CrossSet};
+pub use crate::diff3::{diff3_output, Diff3Set};
+pub use crate::difference::{ComplementSet, ExclusiveUnionSet, NotInAllSet};
+pub use crate::directory::{directory_files, run_directory};
+pub use crate::error::SetopError;
+pub use crate::expr::SetExpr;
+pub use crate::external::ExternalSortSet;
+pub use crate::fields::{select_fields, FieldConfig};
+pub use crate::group::GroupByFieldSet;
+pub use crate::hyperloglog::{estimate_union_cardinality, HyperLogLog};
+pub use crate::join::JoinSet;
+pub use crate::keyed::{KeyedIntersectSet, KeyedUnionSet};
+pub use crate::lazy::LazyIntersectIterator;
+pub use crate::map::{MapSet, MergeStrategy};
+pub use crate::merge::{merge_sorted_difference, merge_sorted_intersect, merge_sorted_union};
+pub use crate::multiset::{MultisetIntersectSet, MultisetUnionSet};
+pub use crate::pipeline::Pipeline;
+pub use crate::positions::{intersect_with_positions, write_positions};
+pub use crate::rolling::{rolling_union, write_rolling_union};
+pub use crate::sorted::SortedUnionSet;
+pub use crate::stats::{write_calculation_with_stats, OpStats};
+pub use crate::streaming::StreamingUnion;
+pub use crate::threshold::ThresholdSet;
+pub use crate::utf16::decode_utf16_bom;
+pub use crate::weighted::WeightedUnionSet;
+pub use crate::wordfreq::{WordFreqMap, WordFreqSet};
+pub use crate::words::{WordIntersectSet, WordUnionSet};
+pub use crate::writer::UnionSetWriter;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpName {
+    Union,
+    Intersect,
+    MultisetUnion,
+    MultisetIntersect,
+    WordUnion,
+    WordIntersect,
+    AnnotatedUnion,
+    NotInAll,
+    KeyedUnion,
+    KeyedIntersect,
+    Count,
+    WeightedUnion,
+    SortedUnion,
+    Join,
+    Exclusive,
+    Complement,
+    Comm,
+    Concat,
+    GroupedUnion,
+    AtLeast,
+    AnnotatedIntersect,
+    Cross,
+    MapUnion,
+    MapIntersect,
+    WordFreqUnion,
+    WordFreqIntersect,
+    Diff3,
+}
+
+impl OpName {
+    // Every operation under the name `Display` gives it, which `from_str`
+    // also accepts
+    pub const ALL: [OpName; 27] = [
+        OpName::Union,
+        OpName::Intersect,
+        OpName::MultisetUnion,
+        OpName::MultisetIntersect,
+        OpName::WordUnion,
+        OpName::WordIntersect,
+        OpName::AnnotatedUnion,
+        OpName::NotInAll,
+        OpName::KeyedUnion,
+        OpName::KeyedIntersect,
+        OpName::Count,
+        OpName::WeightedUnion,
+        OpName::SortedUnion,
+        OpName::Join,
+        OpName::Exclusive,
+        OpName::Complement,
+        OpName::Comm,
+        OpName::Concat,
+        OpName::GroupedUnion,
+        OpName::AtLeast,
+        OpName::AnnotatedIntersect,
+        OpName::Cross,
+        OpName::MapUnion,
+        OpName::MapIntersect,
+        OpName::WordFreqUnion,
+        OpName::WordFreqIntersect,
+        OpName::Diff3,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            OpName::Union => "union",
+            OpName::Intersect => "intersect",
+            OpName::MultisetUnion => "multiset-union",
+            OpName::MultisetIntersect => "multiset-intersect",
+            OpName::WordUnion => "word-union",
+            OpName::WordIntersect => "word-intersect",
+            OpName::AnnotatedUnion => "annotated-union",
+            OpName::NotInAll => "not-in-all",
+            OpName::KeyedUnion => "keyed-union",
+            OpName::KeyedIntersect => "keyed-intersect",
+            OpName::Count => "count",
+            OpName::WeightedUnion => "weighted-union",
+            OpName::SortedUnion => "sorted-union",
+            OpName::Join => "join",
+            OpName::Exclusive => "exclusive-union",
+            OpName::Complement => "complement",
+            OpName::Comm => "comm",
+            OpName::Concat => "concat",
+            OpName::GroupedUnion => "grouped-union",
+            OpName::AtLeast => "at-least",
+            OpName::AnnotatedIntersect => "annotated-intersect",
+            OpName::Cross => "cross",
+            OpName::MapUnion => "map-union",
+            OpName::MapIntersect => "map-intersect",
+            OpName::WordFreqUnion => "word-frequency-union",
+            OpName::WordFreqIntersect => "word-frequency-intersect",
+            OpName::Diff3 => "diff3",
+        }
+    }
+}
+
+impl fmt::Display for OpName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Union and intersect also go by short names and symbols
+impl FromStr for OpName {
+    type Err = SetopError;
+    fn from_str(s: &str) -> Result<Self, SetopError> {
+        match s {
+            "u" | "+" => Ok(OpName::Union),
+            "i" | "x" => Ok(OpName::Intersect),
+            _ => OpName::ALL.iter().cloned().find(|op| op.name() == s).ok_or_else(|| SetopError::UnknownOperation(s.to_string())),
+        }
+    }
+}
+
+pub type TextVec = Vec<u8>;
+pub type TextSlice = [u8];
+
+// Options that change how operands are read and compared. The command line
+// fills one in, and library entry points take it by reference.
+#[derive(Clone, Debug)]
+pub struct Config {
+    // The byte that ends each line
+    pub delimiter: u8,
+    // Drop spaces, tabs and carriage returns from the end of each line before
+    // comparing it, and output the trimmed line
+    pub ignore_trailing_whitespace: bool,
+    // Drop just one carriage return from the end of each line, as in a file
+    // with CRLF line endings, and nothing else. Lines ended by NUL aren't
+    // CRLF lines, so with a NUL delimiter this does nothing.
+    pub trim_cr: bool,
+    // Drop result lines shorter or longer than these lengths, in bytes
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    // How `write_to` writes the result
+    pub format: OutputFormat,
+    // Split text into records of exactly this many bytes instead of into
+    // delimited lines, and write them back out with no delimiter
+    pub record_size: Option<usize>,
+    // Leave out this many result lines before writing any, then write at
+    // most `max_lines`
+    pub skip_lines: usize,
+    pub max_lines: Option<usize>,
+    // Write a random sample of the result lines rather than all of them, in
+    // the order they'd have come in, before sorting, `skip_lines` and
+    // `max_lines`. `sample_seed` seeds the choice, so the same seed picks the
+    // same lines. Without the `sampling` feature there's no sampling.
+    pub sample: Option<SampleSize>,
+    pub sample_seed: u64,
+    // Change the case of each operand's text before it's split into lines,
+    // so both comparison and output use the changed text
+    pub case_transform: Option<CaseTransform>,
+    // Use Unicode case rules for `case_transform` when the text is UTF-8,
+    // rather than changing only ASCII letters
+    pub unicode_case: bool,
+    // Don't write the result; callers only learn whether it's empty
+    pub quiet: bool,
+    // For the keyed operations, how many bytes at the start of each line are
+    // compared
+    pub key_width: Option<usize>,
+    // For the keyed operations, compare the fields of each line, ending at
+    // `field_delimiter`, in sorted order instead, so that lines with the same
+    // fields in a different order match
+    pub ignore_field_order: bool,
+    // For the keyed operations, compare just these fields of each line
+    // instead
+    pub fields: Option<FieldConfig>,
+    // Lines longer than this many bytes are dealt with as `on_long_line` says
+    pub max_line_length: Option<usize>,
+    pub on_long_line: OnLongLine,
+    // For the weighted union, the byte between each line's count and the rest
+    // of the line, and whether a line without a count is an error rather than
+    // a count of 1
+    pub weight_delimiter: u8,
+    pub require_weights: bool,
+    // For the join, `ignore_field_order` and the grouped union, the byte that
+    // ends each field of a line
+    pub field_delimiter: u8,
+    // For `comm`, which of its three columns to leave out
+    pub comm_suppress: [bool; 3],
+    // Replace each operand's lines with their SHA-256 digests, in hex, as
+    // part of `transform_text`
+    pub hash_lines: bool,
+    // For `not-in-all`, keep the first operand's lines that are in all the
+    // others rather than those that aren't
+    pub invert_match: bool,
+    // Write the result sorted by the number each line starts with
+    pub numeric_sort: Option<NonNumeric>,
+    // How many lines at the start of each operand are a header rather than
+    // data. Sets don't look at this: whoever reads the operands drops their
+    // headers with `split_header`.
+    pub header_lines: usize,
+    // For union, fail rather than let the result's lines take more than about
+    // this many bytes
+    pub max_memory: Option<usize>,
+    // For the grouped union, the number of the field, from 1, whose value
+    // picks each line's group
+    pub group_by_field: Option<usize>,
+    // A line for `finish_and_write` to write, raw, in place of an empty
+    // result, so that readers can tell an empty result from no output at all
+    pub empty_marker: Option<TextVec>,
+    // Write the delimiter between raw result lines but not after the last,
+    // for readers that want a separated list rather than ended lines
+    pub no_final_delimiter: bool,
+    // For `at-least`, the total weight of the operands a line must be in, and
+    // each operand's weight in turn; any operands past the end of the list
+    // weigh 1
+    pub threshold: Option<u64>,
+    pub operand_weights: Vec<u64>,
+    // For the annotated operations, the names to label each operand's lines
+    // with in turn, in place of its index
+    pub file_names: Vec<TextVec>,
+    // For `cross`, what goes between the lines of each pair
+    pub cross_separator: TextVec,
+    // For the map operations, the byte between each line's key and value,
+    // and how the values of lines with the same key are combined
+    pub map_delimiter: u8,
+    pub map_merge: MergeStrategy,
+    // Take only the lines in this shard of every operand
+    pub shard: Option<Shard>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            delimiter: b'\n',
+            ignore_trailing_whitespace: false,
+            trim_cr: false,
+            min_length: None,
+            max_length: None,
+            format: OutputFormat::Raw,
+            record_size: None,
+            skip_lines: 0,
+            max_lines: None,
+            sample: None,
+            sample_seed: 0,
+            case_transform: None,
+            unicode_case: false,
+            quiet: false,
+            key_width: None,
+            ignore_field_order: false,
+            fields: None,
+            max_line_length: None,
+            on_long_line: OnLongLine::Error,
+            weight_delimiter: b'\t',
+            require_weights: false,
+            field_delimiter: b'\t',
+            comm_suppress: [false; 3],
+            hash_lines: false,
+            invert_match: false,
+            numeric_sort: None,
+            header_lines: 0,
+            max_memory: None,
+            group_by_field: None,
+            empty_marker: None,
+            no_final_delimiter: false,
+            threshold: None,
+            operand_weights: Vec::new(),
+            file_names: Vec::new(),
+            cross_separator: b"\t".to_vec(),
+            map_delimiter: b':',
+            map_merge: MergeStrategy::FirstWins,
+            shard: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // Each line's bytes followed by the delimiter
+    Raw,
+    // A JSON array of strings. JSON strings can't hold arbitrary bytes, so a
+    // result line that isn't UTF-8 is an `InvalidData` error.
+    Json,
+    // JSON Lines: each line as a JSON string on a line of its own. Bytes that
+    // aren't UTF-8 are written as `\u00XX` escapes rather than rejected.
+    Ndjson,
+    // Not the lines but a few numbers about them, each on a line of its own
+    // as a name, a tab and the value: `lines`, `bytes` (not counting
+    // delimiters), and `min_length`, `max_length` and `mean_length`, which are
+    // 0 when there are no lines
+    Summary,
+}
+
+// How many result lines a sample has: so many, or this fraction of them all,
+// rounded to the nearest line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleSize {
+    Lines(usize),
+    Fraction(f64),
+}
+
+// Shard `index` of `count` has the lines whose hash leaves `index` when
+// divided by `count`. Every operand is sharded alike, so a line is in the
+// same shard wherever it is, and the sets see all of a line or none of it:
+// the results of an operation over each of the `count` shards, put together,
+// have the lines of the result for the whole operands, just not in the same
+// order. The hash is the same on every run, and on every machine with the
+// same byte order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    pub fn contains(&self, line: &TextSlice) -> bool {
+        content_hash(line) % self.count == self.index
+    }
+}
+
+// Where `numeric_sort` puts lines that don't start with a number
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NonNumeric {
+    First,
+    Last,
+}
+
+// What happens to a line longer than `Config::max_line_length`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnLongLine {
+    // It's an error, which `check_line_lengths` finds as operands are read
+    Error,
+    // It's left out, as if the operand didn't have it
+    Skip,
+    // It's cut to `max_line_length` bytes
+    Truncate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaseTransform {
+    Lower,
+    Upper,
+}
+
+impl Config {
+    // A case transform changes the text itself, so it can't be done line by
+    // line as sets borrow their lines: it has to be applied to each whole
+    // operand, with `transform_text`, before the operand is given to a set.
+    // (The command line does that as it reads each file.) With no transform
+    // the text is returned as is; with one, the result is a new copy of the
+    // operand, and sets like `IntersectSet` borrow from the copy.
+    //
+    // Only ASCII letters are changed, byte by byte, so the text is never
+    // decoded and bytes from 0x80 up — including every byte of a multibyte
+    // UTF-8 character — pass through untouched. With `unicode_case` set, text
+    // that's UTF-8 is transformed with Unicode case rules instead.
+    //
+    // With `hash_lines` set, the lines of the case-changed text are then
+    // replaced by their digests.
+    pub fn transform_text<'a>(&self, text: &'a TextSlice) -> Cow<'a, TextSlice> {
+        let text = self.change_case(text);
+        if self.hash_lines {
+            return Cow::Owned(hash::hash_lines(&text, self));
+        }
+        text
+    }
+
+    fn change_case<'a>(&self, text: &'a TextSlice) -> Cow<'a, TextSlice> {
+        let case = match self.case_transform {
+            None => return Cow::Borrowed(text),
+            Some(case) => case,
+        };
+        if self.unicode_case {
+            if let Ok(text) = str::from_utf8(text) {
+                return match case {
+                    CaseTransform::Lower => Cow::Owned(text.to_lowercase().into_bytes()),
+                    CaseTransform::Upper => Cow::Owned(text.to_uppercase().into_bytes()),
+                };
+            }
+        }
+        match case {
+            CaseTransform::Lower => Cow::Owned(text.to_ascii_lowercase()),
+            CaseTransform::Upper => Cow::Owned(text.to_ascii_uppercase()),
+        }
+    }
+
+    // Set members are lines without their delimiter, adjusted as the options
+    // require. Every adjustment so far just shortens the line, so the result
+    // is still a subslice of the operand's text.
+    pub fn normalize_line<'a>(&self, line: &'a TextSlice) -> &'a TextSlice {
+        let mut line = line;
+        if self.ignore_trailing_whitespace {
+            while let Some((last, rest)) = line.split_last() {
+                if !b" \t\r".contains(last) {
+                    break;
+                }
+                line = rest;
+            }
+        }
+        if self.trim_cr && self.delimiter != b'\0' {
+            line = line.strip_suffix(b"\r").unwrap_or(line);
+        }
+        if let (Some(max), OnLongLine::Truncate) = (self.max_line_length, self.on_long_line) {
+            line = &line[..line.len().min(max)];
+        }
+        line
+    }
+
+    // Whether `on_long_line` leaves out `line`, once normalized
+    fn skips_line(&self, line: &TextSlice) -> bool {
+        self.on_long_line == OnLongLine::Skip && self.max_line_length.is_some_and(|max| line.len() > max)
+    }
+}
+
+// The `'data` lifetime is that of the operands' text. Most sets copy or
+// discard what they need from an operand before `operate` returns, and so
+// implement `SetExpression<'data>` for every `'data`; a set that keeps
+// borrowed lines, like `BorrowingUnionSet<'data>`, needs every operand to
+// outlive it.
+pub trait SetExpression<'data>
+where
+    Self: Sized,
+    // We can't say Sized + IntoLineIterator: rustc complains that there's
+    // no implementation for type Foo, just for type &'a Foo
+{
+    fn operate(&mut self, text: &'data TextSlice, config: &Config);
+    // Sets that do their real work at the end, or that write to disk, can
+    // fail here
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    // The members of the set, in the order they're written
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me>;
+
+    // Keep only the result lines for which `keep` returns true. (It's not
+    // called `retain` because `UnionSet` and `IntersectSet` already have an
+    // inherent `retain`, which would win.) Sets that build their result in
+    // `finish` should be filtered after it.
+    fn retain_lines(&mut self, keep: impl FnMut(&TextSlice) -> bool);
+
+    // Keep lines of fewer than `max_len` bytes, so `max_len == 0` keeps nothing
+    fn retain_shorter_than(&mut self, max_len: usize) {
+        self.retain_lines(|line| line.len() < max_len);
+    }
+    // Keep lines of more than `min_len` bytes, so `min_len == usize::MAX`
+    // keeps nothing
+    fn retain_longer_than(&mut self, min_len: usize) {
+        self.retain_lines(|line| line.len() > min_len);
+    }
+    // Keep the lines that are valid UTF-8, or those that aren't, to separate
+    // text from binary data
+    fn retain_utf8_valid(&mut self) {
+        self.retain_lines(|line| str::from_utf8(line).is_ok());
+    }
+    fn retain_utf8_invalid(&mut self) {
+        self.retain_lines(|line| str::from_utf8(line).is_err());
+    }
+    // The number of result lines that aren't valid UTF-8
+    fn count_utf8_invalid(&self) -> usize {
+        self.result_lines().filter(|line| str::from_utf8(line).is_err()).count()
+    }
+
+    // Choose `n` result lines at random (or all of them, if there are fewer),
+    // in one pass and without knowing how many lines there are: this is
+    // Vitter's Algorithm R, taking O(n) space. The sample is in no
+    // particular order.
+    #[cfg(feature = "sampling")]
+    fn sample(&self, n: usize, rng: &mut impl rand::Rng) -> Vec<TextVec> {
+        let mut reservoir = Vec::with_capacity(n);
+        for (i, line) in self.result_lines().enumerate() {
+            if i < n {
+                reservoir.push(line.to_vec());
+            } else {
+                let j = rng.gen_range(0..=i);
+                if j < n {
+                    reservoir[j] = line.to_vec();
+                }
+            }
+        }
+        reservoir
+    }
+
+    // True when no further operand can change the result, as for an empty
+    // intersection, so the caller can stop reading operands
+    fn is_settled(&self) -> bool {
+        false
+    }
+
+    // `operate`, then return whether the set is settled, for a caller that
+    // stops reading operands once it is
+    fn operate_early_exit(&mut self, text: &'data TextSlice, config: &Config) -> bool {
+        self.operate(text, config);
+        self.is_settled()
+    }
+
+    // The number of result lines. The default counts them; sets that know
+    // their size say so.
+    fn result_len(&self) -> usize {
+        self.result_lines().count()
+    }
+
+    // Random access to the result, in `result_lines` order. These defaults walk
+    // the lines; sets kept in an `IndexSet` look them up in O(1).
+    fn nth_line(&self, n: usize) -> Option<&TextSlice> {
+        self.result_lines().nth(n)
+    }
+    fn index_of(&self, line: &TextSlice) -> Option<usize> {
+        self.result_lines().position(|l| l == line)
+    }
+
+    // The first and last lines in `result_lines` order, and the bytewise
+    // smallest and largest
+    fn first_line(&self) -> Option<&TextSlice> {
+        self.result_lines().next()
+    }
+    fn last_line(&self) -> Option<&TextSlice> {
+        self.result_lines().last()
+    }
+    fn min_line(&self) -> Option<&TextSlice> {
+        self.result_lines().min()
+    }
+    fn max_line(&self) -> Option<&TextSlice> {
+        self.result_lines().max()
+    }
+
+    // Copies of the result lines, split into those `pred` accepts and those it
+    // doesn't, in one pass and leaving the set as it is
+    fn partition_by<F>(&self, pred: F) -> (Vec<TextVec>, Vec<TextVec>)
+    where F: Fn(&TextSlice) -> bool
+    {
+        let mut accepted = Vec::new();
+        let mut rejected = Vec::new();
+        for line in self.result_lines() {
+            if pred(line) {
+                accepted.push(line.to_vec());
+            } else {
+                rejected.push(line.to_vec());
+            }
+        }
+        (accepted, rejected)
+    }
+
+    // Save the result in the binary format that `UnionSet::read_from_binary`
+    // reads back
+    fn write_to_binary(&self, out: &mut impl Write) -> io::Result<()> {
+        binary::write_binary(self, out)
+    }
+
+    // Write every result line in the `Ndjson` format
+    fn write_to_ndjson(&self, out: &mut impl Write) -> io::Result<()> {
+        output::write_ndjson(self, out, &Config::default())
+    }
+
+    // Every set writes itself the same way, from its `result_lines`.
+    //
+    // Lines are stored without their delimiter, so we write it after each one —
+    // including a last line that had no delimiter in its file, unless
+    // `no_final_delimiter` leaves it off the last line written. Fixed-size
+    // records had no delimiter to begin with, and get none.
+    //
+    // `--skip` and `--max-lines` count the lines actually written, so they
+    // apply after anything that reorders or drops lines on the way out.
+    //
+    // A set that overrides this can still fall back on `output::write_lines`.
+    //
+    fn write_to(&self, out: &mut impl Write, config: &Config) -> io::Result<()> {
+        output::write_lines(self, out, config)
+    }
+
+    // The result as the default `write_to` would write it: each line followed
+    // by a newline
+    fn to_bytes(&self) -> TextVec {
+        let mut out = TextVec::new();
+        self.write_to(&mut out, &Config::default()).expect("writing to a Vec can't fail");
+        out
+    }
+
+    // `to_bytes` as text, with any bytes that aren't UTF-8 replaced by U+FFFD
+    fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.to_bytes()).into_owned()
+    }
+
+    // `to_bytes` as text, failing on the first line that isn't UTF-8
+    fn to_utf8_string(&self) -> Result<String, SetopError> {
+        let mut text = String::new();
+        for (n, line) in self.result_lines().enumerate() {
+            text.push_str(str::from_utf8(line).map_err(|_| SetopError::Utf8Error { line: n + 1 })?);
+            text.push('\n');
+        }
+        Ok(text)
+    }
+}
+
+// The lines `write_to` writes, in the order it writes them
+fn output_lines<'me, 'data>(
+    zelf: &'me impl SetExpression<'data>,
+    config: &Config,
+) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+    let max_lines = config.max_lines.unwrap_or(usize::MAX);
+    let lines: Box<dyn Iterator<Item = &'me TextSlice> + 'me> = match config.sample {
+        #[cfg(feature = "sampling")]
+        Some(size) => Box::new(output::sample_lines(zelf, size, config.sample_seed).into_iter()),
+        _ => zelf.result_lines(),
+    };
+    match config.numeric_sort {
+        None => Box::new(lines.skip(config.skip_lines).take(max_lines)),
+        Some(non_numeric) => {
+            let mut lines: Vec<_> = lines.collect();
+            output::sort_numerically(&mut lines, non_numeric);
+            Box::new(lines.into_iter().skip(config.skip_lines).take(max_lines))
+        }
+    }
+}
+
+// How many lines `output_lines` gives, without going through them unless
+// they're sampled
+fn output_len<'data>(set: &impl SetExpression<'data>, config: &Config) -> usize {
+    let len = match config.sample {
+        #[cfg(feature = "sampling")]
+        Some(size) => output::sample_len(set, size),
+        _ => set.result_len(),
+    };
+    len.saturating_sub(config.skip_lines).min(config.max_lines.unwrap_or(usize::MAX))
+}
+
+pub type UnionSet = IndexSet<TextVec>;
+
+pub trait UnionSetExt: Sized {
+    fn init(text: TextVec, config: &Config) -> Self;
+    fn with_line_capacity(lines: usize) -> Self;
+    fn read_from_binary(data: &TextSlice) -> Result<Self, SetopError>;
+    fn from_lines<L: Into<TextVec>>(lines: impl IntoIterator<Item = L>) -> Self;
+    fn merge(&mut self, other: UnionSet);
+}
+
+impl UnionSetExt for UnionSet {
+    // The first operand is initialized by calling the `LineSet`'s initialization method.
+    fn init(text: TextVec, config: &Config) -> Self {
+        UnionSet::init_from_slice(&text, config)
+    }
+    // An empty set with room for `lines` distinct lines before it has to
+    // grow, for a caller that knows roughly how big the union will be
+    fn with_line_capacity(lines: usize) -> Self {
+        UnionSet::with_capacity(lines)
+    }
+    // A set saved by `write_to_binary` can be reloaded as a `UnionSet`
+    fn read_from_binary(data: &TextSlice) -> Result<Self, SetopError> {
+        binary::read_binary(data)
+    }
+    // Lines from somewhere other than a text operand — `String`s, `Vec<u8>`s
+    // or byte slices — are taken as they are, without splitting or
+    // normalizing. (`UnionSet` is `IndexSet`'s own type, so it can't have
+    // `From` impls of ours; `collect` into it already works for `TextVec`s.)
+    fn from_lines<L: Into<TextVec>>(lines: impl IntoIterator<Item = L>) -> Self {
+        lines.into_iter().map(Into::into).collect()
+    }
+    // Fold in the members of a union built separately, such as by another
+    // thread: `self`'s members keep their places, and `other`'s new ones
+    // follow in `other`'s order. So merging the unions of the pieces of a
+    // list of operands, left to right, gives the union of the whole list.
+    fn merge(&mut self, other: UnionSet) {
+        self.extend(other);
+    }
+}
+
+impl<'data> SetExpression<'data> for UnionSet {
+    // For subsequent operands we simply insert each line into the hash
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.retain(|line| keep(line));
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.iter().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.len()
+    }
+    fn nth_line(&self, n: usize) -> Option<&TextSlice> {
+        self.get_index(n).map(|v| v.as_slice())
+    }
+    fn index_of(&self, line: &TextSlice) -> Option<usize> {
+        self.get_full(line).map(|(i, _)| i)
+    }
+}
+
+// For an `IntersectSet` all result lines will be from the
+// first file operand, so we can avoid additional allocations by keeping its
+// text in memory and using subslices of its text as the members of the set.
+pub type IntersectSet<'data> = IndexSet<&'data TextSlice>;
+
+pub trait IntersectSetExt<'data> {
+    fn init(text: &'data TextVec, config: &Config) -> Self;
+    fn init_with_line_capacity(text: &'data TextVec, lines: usize, config: &Config) -> Self;
+    fn into_union_set(self) -> UnionSet;
+    fn operate_lines<'l>(&mut self, lines: impl IntoIterator<Item = &'l TextSlice>);
+}
+
+impl<'data> IntersectSetExt<'data> for IntersectSet<'data> {
+    fn init(text: &'data TextVec, config: &Config) -> Self {
+        SliceSet::init_from_slice(text, config)
+    }
+    // `init`, with room for `lines` distinct lines of `text` made before it's
+    // read, so a caller that knows how many there are saves the regrowing
+    fn init_with_line_capacity(text: &'data TextVec, lines: usize, config: &Config) -> Self {
+        let mut set = SliceSet::with_capacity(lines);
+        set.insert_all_lines(text, config);
+        set
+    }
+    // Copy the lines out of the first operand into a `UnionSet`, in the same
+    // order, to go on with operations that need owned lines, such as a union
+    // with another operand
+    fn into_union_set(self) -> UnionSet {
+        self.into_iter().map(<[u8]>::to_vec).collect()
+    }
+    // `operate` on lines that come already split, taken as they are, as
+    // `LineSet::insert_lines` takes them. The set starts from lines like
+    // these with `SliceSet::init_from_lines`.
+    fn operate_lines<'l>(&mut self, lines: impl IntoIterator<Item = &'l TextSlice>) {
+        if self.is_empty() {
+            return;
+        }
+        let other = SliceSet::init_from_lines(lines);
+        self.retain(|x| other.contains(x));
+    }
+}
+
+// An operand with at most this many lines is searched as a sorted `Vec`
+// rather than hashed into a `SliceSet`: for a handful of lines, a binary
+// search costs less than hashing every line of it and of the set. In
+// `benches/sorted_operand.rs` the sorted `Vec` is 25-45% faster for operands
+// of 2 or 4 lines whatever the set's size, about even at 8, and for sets of
+// 100 lines or more, slower from 12 lines on, and twice as slow by 64. It only
+// keeps winning for larger operands when the set is as small as they are.
+// `intersect_n` and the command line use a `CachedIntersectSet` instead, which
+// hashes only the operand's lines, and that's faster than either at any size.
+const SORTED_OPERAND_LINES: usize = 4;
+
+// For subsequent operands, we take a `SliceSet` `s` of the operand's text and
+// keep only those lines that occur in `s`, or for a small operand, those
+// found in its sorted lines, which keeps the same lines. Once nothing is left
+// there's nothing to keep, so we don't look at the text at all: callers like
+// `calculate_and_print` stop when `is_settled`, but `operate_all` doesn't.
+impl<'data, 'other> SetExpression<'other> for IntersectSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        if self.is_empty() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let before = self.len();
+        let mut lines = LineList::init_from_slice(text, config).0;
+        if lines.len() <= SORTED_OPERAND_LINES {
+            lines.sort_unstable();
+            self.retain(|x| lines.binary_search(x).is_ok());
+        } else {
+            let other: SliceSet = lines.into_iter().collect();
+            self.retain(|x| other.contains(x));
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(before, after = self.len(), "intersected with operand");
+    }
+    fn is_settled(&self) -> bool {
+        self.is_empty()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.retain(|line| keep(line));
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        // Set<&VecSlice>
+        // .iter => &&VecSlice
+        // .cloned => &VecSlice
+        Box::new(self.iter().cloned())
+    }
+    fn result_len(&self) -> usize {
+        self.len()
+    }
+    fn nth_line(&self, n: usize) -> Option<&TextSlice> {
+        self.get_index(n).cloned()
+    }
+    fn index_of(&self, line: &TextSlice) -> Option<usize> {
+        self.get_full(line).map(|(i, _)| i)
+    }
+}
+
+// All of `texts` is in memory for the whole calculation, so union can borrow
+// its lines rather than copy them.
+pub fn do_calculation(op: OpName, texts: Iter<TextVec>, config: &Config) -> io::Result<bool> {
+    write_calculation(op, texts, &mut io::stdout().lock(), config)
+}
+
+// The simplest entry points: each takes every operand at once and returns
+// the result as `to_bytes` writes it, each line followed by a newline, after
+// the filters in `config`. With no operands the result is empty; with one,
+// `union_n` and `intersect_n` both give its distinct lines.
+pub fn union_n(texts: &[TextVec], config: &Config) -> TextVec {
+    match texts.split_first() {
+        Some((first, rest)) => result_bytes(BorrowingUnionSet::init_from_slice(first, config), rest, config),
+        None => TextVec::new(),
+    }
+}
+
+pub fn intersect_n(texts: &[TextVec], config: &Config) -> TextVec {
+    match texts.split_first() {
+        Some((first, rest)) => result_bytes(CachedIntersectSet::init(first, config), rest, config),
+        None => TextVec::new(),
+    }
+}
+
+// The lines of the first operand that are in none of the others
+pub fn diff_n(texts: &[TextVec], config: &Config) -> TextVec {
+    match texts.split_first() {
+        Some((first, rest)) => result_bytes(ComplementSet::init(first, config), rest, config),
+        None => TextVec::new(),
+    }
+}
+
+// The lines in exactly one operand. For more than two that's not the lines
+// in an odd number of them, as repeated symmetric differences would give.
+pub fn symdiff_n(texts: &[TextVec], config: &Config) -> TextVec {
+    match texts.split_first() {
+        Some((first, rest)) => result_bytes(ExclusiveUnionSet::init(first, config), rest, config),
+        None => TextVec::new(),
+    }
+}
+
+// The same operations under the names they have in mathematics:
+//
+// set_minus(A, [B, C]) = A \ (B ∪ C)
+pub fn set_minus(minuend: TextVec, subtrahends: &[TextVec], config: &Config) -> Vec<u8> {
+    result_bytes(ComplementSet::init(&minuend, config), subtrahends, config)
+}
+
+// set_union([A, B, C]) = A ∪ B ∪ C
+pub fn set_union(texts: &[TextVec], config: &Config) -> Vec<u8> {
+    union_n(texts, config)
+}
+
+// set_intersect([A, B, C]) = A ∩ B ∩ C
+pub fn set_intersect(texts: &[TextVec], config: &Config) -> Vec<u8> {
+    intersect_n(texts, config)
+}
+
+// set_symdiff([A, B]) = (A \ B) ∪ (B \ A), and for more operands the lines in
+// exactly one of them, as for `symdiff_n`
+pub fn set_symdiff(texts: &[TextVec], config: &Config) -> Vec<u8> {
+    symdiff_n(texts, config)
+}
+
+// Combine sets that have already been calculated, each finished and
+// filtered as it should be: the union of their result lines, in the order
+// they were first seen, or the lines in all of them, in the first set's
+// order. The intersection is an `IntersectSet` that borrows its lines from
+// the first set, so it takes the sets by reference, and can go on to
+// `operate` on more text. With no sets, both are empty.
+pub fn fold_into_union<'data, S: SetExpression<'data>>(sets: Vec<S>) -> UnionSet {
+    let mut union = UnionSet::new();
+    for set in &sets {
+        union.extend(set.result_lines().map(<[u8]>::to_vec));
+    }
+    union
+}
+
+pub fn fold_into_intersection<'a, 'data, S: SetExpression<'data>>(sets: &'a [S]) -> IntersectSet<'a> {
+    let (first, rest) = match sets.split_first() {
+        Some(split) => split,
+        None => return IntersectSet::new(),
+    };
+    let mut intersection: IntersectSet = first.result_lines().collect();
+    for set in rest {
+        if intersection.is_empty() {
+            break;
+        }
+        let other: SliceSet = set.result_lines().collect();
+        intersection.retain(|line| other.contains(line));
+    }
+    intersection
+}
+
+// The union of `a` with just those lines of `b` that `pred` accepts, given
+// the line and the set of `a`'s lines. `pred` always sees only `a`'s lines,
+// not the lines of `b` it has already let in. With a `pred` that takes the
+// lines of `b` whose first field is a line of `a`, this is a semi-join of
+// `b` on `a`, with `a`'s own lines kept too.
+pub fn conditional_union<F>(a: TextVec, b: &TextSlice, pred: F, config: &Config) -> UnionSet
+where F: Fn(&TextSlice, &UnionSet) -> bool
+{
+    let mut set = UnionSet::init(a, config);
+    let accepted: Vec<TextVec> =
+        SliceSet::init_from_slice(b, config).into_iter().filter(|line| pred(line, &set)).map(<[u8]>::to_vec).collect();
+    set.extend(accepted);
+    set
+}
+
+// `union_n` and the rest for operands that are all UTF-8. A result line can
+// still fail to be UTF-8 if an option cut a character in two, as
+// truncating long lines or `record_size` can.
+pub fn union_strs(strs: &[&str], config: &Config) -> Result<String, SetopError> {
+    result_string(union_n, strs, config)
+}
+
+pub fn intersect_strs(strs: &[&str], config: &Config) -> Result<String, SetopError> {
+    result_string(intersect_n, strs, config)
+}
+
+pub fn diff_strs(strs: &[&str], config: &Config) -> Result<String, SetopError> {
+    result_string(diff_n, strs, config)
+}
+
+pub fn symdiff_strs(strs: &[&str], config: &Config) -> Result<String, SetopError> {
+    result_string(symdiff_n, strs, config)
+}
+
+fn result_string(op: fn(&[TextVec], &Config) -> TextVec, strs: &[&str], config: &Config) -> Result<String, SetopError> {
+    let texts: Vec<TextVec> = strs.iter().map(|s| s.as_bytes().to_vec()).collect();
+    String::from_utf8(op(&texts, config)).map_err(|err| {
+        let valid = &err.as_bytes()[..err.utf8_error().valid_up_to()];
+        // `to_bytes` ends lines with the default delimiter, and the bad byte is
+        // on the line after the last one that ends before it
+        SetopError::Utf8Error { line: Memchr::new(Config::default().delimiter, valid).count() + 1 }
+    })
+}
+
+fn result_bytes<'data>(mut set: impl SetExpression<'data>, rest: &'data [TextVec], config: &Config) -> TextVec {
+    operate_until_settled(&mut set, rest, config);
+    finish_and_filter(&mut set, config).expect("these sets' finish can't fail");
+    set.to_bytes()
+}
+
+// Like `do_calculation`, but writes the result to `out`
+pub fn write_calculation(op: OpName, texts: Iter<TextVec>, out: &mut impl Write, config: &Config) -> io::Result<bool> {
+    Ok(write_calculation_len(op, texts, out, config)? > 0)
+}
+
+// `write_calculation`, returning how many lines the result has. Every
+// operation needs a first operand, so with none it's an `InvalidInput` error.
+pub(crate) fn write_calculation_len(op: OpName, mut texts: Iter<TextVec>, out: &mut impl Write, config: &Config) -> io::Result<usize> {
+    let txt = match texts.next() {
+        Some(txt) => txt,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} needs at least one operand", op))),
+    };
+    let key_width = config.key_width.unwrap_or(usize::MAX);
+    match op {
+        OpName::Union if config.max_memory.is_some() => {
+            let limit = config.max_memory.unwrap_or(usize::MAX);
+            calculate_and_write_len(&mut BoundedUnionSet::init(txt, limit, config), texts, out, config)
+        }
+        OpName::Union => calculate_and_write_len(&mut BorrowingUnionSet::init_from_slice(txt, config), texts, out, config),
+        OpName::Intersect => calculate_and_write_len(&mut CachedIntersectSet::init(txt, config), texts, out, config),
+        OpName::MultisetUnion => calculate_and_write_len(&mut MultisetUnionSet::init(txt, config), texts, out, config),
+        OpName::MultisetIntersect => calculate_and_write_len(&mut MultisetIntersectSet::init(txt, config), texts, out, config),
+        OpName::WordUnion => calculate_and_write_len(&mut WordUnionSet::init(txt, config), texts, out, config),
+        OpName::WordIntersect => calculate_and_write_len(&mut WordIntersectSet::init(txt, config), texts, out, config),
+        OpName::AnnotatedUnion => calculate_and_write_len(&mut AnnotatedUnionSet::init(txt, config), texts, out, config),
+        OpName::AnnotatedIntersect => {
+            calculate_and_write_len(&mut AnnotatedIntersectSet::init(txt, config), texts, out, config)
+        }
+        OpName::NotInAll => calculate_and_write_len(&mut NotInAllSet::init(txt, config), texts, out, config),
+        OpName::KeyedUnion if config.fields.is_some() => {
+            let fields = config.fields.as_ref().expect("checked by the guard");
+            calculate_and_write_len(&mut KeyedUnionSet::init_by_fields(txt, fields, config), texts, out, config)
+        }
+        OpName::KeyedIntersect if config.fields.is_some() => {
+            let fields = config.fields.as_ref().expect("checked by the guard");
+            calculate_and_write_len(&mut KeyedIntersectSet::init_by_fields(txt, fields, config), texts, out, config)
+        }
+        OpName::KeyedUnion if config.ignore_field_order => {
+            calculate_and_write_len(&mut KeyedUnionSet::init_ignoring_field_order(txt, config), texts, out, config)
+        }
+        OpName::KeyedIntersect if config.ignore_field_order => {
+            calculate_and_write_len(&mut KeyedIntersectSet::init_ignoring_field_order(txt, config), texts, out, config)
+        }
+        OpName::KeyedUnion => calculate_and_write_len(&mut KeyedUnionSet::init(txt, key_width, config), texts, out, config),
+        OpName::KeyedIntersect => calculate_and_write_len(&mut KeyedIntersectSet::init(txt, key_width, config), texts, out, config),
+        OpName::WeightedUnion => calculate_and_write_len(&mut WeightedUnionSet::init(txt, config), texts, out, config),
+        OpName::SortedUnion => calculate_and_write_len(&mut SortedUnionSet::init(txt, config), texts, out, config),
+        OpName::Join => calculate_and_write_len(&mut JoinSet::init(txt, config), texts, out, config),
+        OpName::Exclusive => calculate_and_write_len(&mut ExclusiveUnionSet::init(txt, config), texts, out, config),
+        OpName::Complement => calculate_and_write_len(&mut ComplementSet::init(txt, config), texts, out, config),
+        OpName::Comm => calculate_and_write_len(&mut CommSet::init(txt, config), texts, out, config),
+        OpName::Concat => calculate_and_write_len(&mut ConcatSet::init(txt, config), texts, out, config),
+        OpName::Cross => calculate_and_write_len(&mut CrossSet::init(txt, config), texts, out, config),
+        OpName::Diff3 => calculate_and_write_len(&mut Diff3Set::init(txt, config), texts, out, config),
+        OpName::MapUnion => calculate_and_write_len(&mut MapSet::init_union(txt, config), texts, out, config),
+        OpName::MapIntersect => calculate_and_write_len(&mut MapSet::init_intersect(txt, config), texts, out, config),
+        OpName::WordFreqUnion => calculate_and_write_len(&mut WordFreqSet::init_union(txt, config), texts, out, config),
+        OpName::WordFreqIntersect => {
+            calculate_and_write_len(&mut WordFreqSet::init_intersect(txt, config), texts, out, config)
+        }
+        OpName::AtLeast => {
+            let threshold = config.threshold.unwrap_or(1);
+            calculate_and_write_len(&mut ThresholdSet::init(txt, threshold, config), texts, out, config)
+        }
+        OpName::GroupedUnion => {
+            let key_field = config.group_by_field.unwrap_or(1);
+            calculate_and_write_len(&mut GroupByFieldSet::init(txt, key_field, config), texts, out, config)
+        }
+        OpName::Count => count_and_write_len(&mut BorrowingUnionSet::init_from_slice(txt, config), texts, out, config),
+    }
+}
+
+pub fn calculate_and_print<'data, T>(set: &mut T, texts: Iter<'data, TextVec>, config: &Config) -> io::Result<bool>
+where T: SetExpression<'data>
+{
+    let stdout_for_locking = io::stdout();
+    let mut stdout = stdout_for_locking.lock();
+    calculate_and_write(set, texts, &mut stdout, config)
+}
+
+pub fn calculate_and_write<'data, T>(
+    set: &mut T,
+    texts: Iter<'data, TextVec>,
+    out: &mut impl Write,
+    config: &Config,
+) -> io::Result<bool>
+where T: SetExpression<'data>
+{
+    Ok(calculate_and_write_len(set, texts, out, config)? > 0)
+}
+
+fn calculate_and_write_len<'data, T>(set: &mut T, texts: Iter<'data, TextVec>, out: &mut impl Write, config: &Config) -> io::Result<usize>
+where T: SetExpression<'data>
+{
+    operate_until_settled(set, texts, config);
+    finish_and_write_len(set, out, config)
+}
+
+// Give `set` each of `texts` in turn, and stop as soon as it's settled: an
+// intersection that's empty after the second text never looks at the rest
+fn operate_until_settled<'data>(
+    set: &mut impl SetExpression<'data>,
+    texts: impl IntoIterator<Item = &'data TextVec>,
+    config: &Config,
+) {
+    if set.is_settled() {
+        return;
+    }
+    for txt in texts {
+        if set.operate_early_exit(txt, config) {
+            break;
+        }
+    }
+}
+
+// `SetExpression` has generic methods, so there can't be a `dyn
+// SetExpression`. `Operate` is the part of it that can be a trait object, so
+// that one stream of operands can be fed to several sets of different types.
+pub trait Operate<'data> {
+    fn operate_on(&mut self, text: &'data TextSlice, config: &Config);
+}
+
+impl<'data, T: SetExpression<'data>> Operate<'data> for T {
+    fn operate_on(&mut self, text: &'data TextSlice, config: &Config) {
+        self.operate(text, config);
+    }
+}
+
+// Give each of `texts` to every one of `sets` in turn, so several operations
+// on the same operands need only one pass over them. The caller finishes and
+// writes each set.
+pub fn operate_all<'data>(sets: &mut [&mut dyn Operate<'data>], texts: Iter<'data, TextVec>, config: &Config) {
+    for txt in texts {
+        for set in sets.iter_mut() {
+            set.operate_on(txt, config);
+        }
+    }
+}
+
+// Like `calculate_and_print`, but prints just the number of result lines
+pub fn count_and_print<'data, T>(set: &mut T, texts: Iter<'data, TextVec>, config: &Config) -> io::Result<bool>
+where T: SetExpression<'data>
+{
+    count_and_write(set, texts, &mut io::stdout().lock(), config)
+}
+
+pub fn count_and_write<'data, T>(
+    set: &mut T,
+    texts: Iter<'data, TextVec>,
+    out: &mut impl Write,
+    config: &Config,
+) -> io::Result<bool>
+where T: SetExpression<'data>
+{
+    Ok(count_and_write_len(set, texts, out, config)? > 0)
+}
+
+fn count_and_write_len<'data, T>(set: &mut T, texts: Iter<'data, TextVec>, out: &mut impl Write, config: &Config) -> io::Result<usize>
+where T: SetExpression<'data>
+{
+    for txt in texts {
+        set.operate(txt, config);
+    }
+    finish_and_filter(set, config)?;
+    let count = set.result_len();
+    if !config.quiet {
+        writeln!(out, "{}", count)?;
+    }
+    Ok(count)
+}
+
+// Once every operand is in, finish the calculation and apply the filters the
+// options ask for
+pub fn finish_and_filter<'data>(set: &mut impl SetExpression<'data>, config: &Config) -> io::Result<()> {
+    set.finish()?;
+    if let Some(min) = config.min_length {
+        if min > 0 {
+            set.retain_longer_than(min - 1);
+        }
+    }
+    if let Some(max) = config.max_length {
+        set.retain_shorter_than(max.saturating_add(1));
+    }
+    // With the `tracing` feature, this and the events in `read_file` and the
+    // intersections are there for a subscriber to pick up; without it they
+    // aren't compiled at all
+    #[cfg(feature = "tracing")]
+    tracing::info!(result_lines = set.result_len(), "operation complete");
+    Ok(())
+}
+
+// `finish_and_filter`, then write the result unless `config.quiet` is set.
+// Returns whether the result has any lines.
+pub fn finish_and_write<'data>(set: &mut impl SetExpression<'data>, out: &mut impl Write, config: &Config) -> io::Result<bool> {
+    Ok(finish_and_write_len(set, out, config)? > 0)
+}
+
+// `finish_and_write`, returning how many lines it writes, or with `quiet`
+// would write: after `skip_lines` and `max_lines`, so that the empty marker
+// stands for an empty window too
+fn finish_and_write_len<'data>(set: &mut impl SetExpression<'data>, out: &mut impl Write, config: &Config) -> io::Result<usize> {
+    finish_and_filter(set, config)?;
+    let len = output_len(set, config);
+    if config.quiet {
+        return Ok(len);
+    }
+    match &config.empty_marker {
+        Some(marker) if len == 0 => {
+            out.write_all(marker)?;
+            if !config.no_final_delimiter {
+                out.write_all(&[config.delimiter])?;
+            }
+        }
+        _ => set.write_to(out, config)?,
+    }
+    Ok(len)
+}
+
+// The lines of `text`, each without the `delimiter` that ends it. A last line
+// with no delimiter is a line too, but there's no empty line after a final
+// delimiter, so `a\nb\n` and `a\nb` both have two lines. These are the lines
+// `insert_all_lines` inserts, before `normalize_line` trims them.
+pub fn split_lines(text: &TextSlice, delimiter: u8) -> impl Iterator<Item = &TextSlice> {
+    let last_begin = memchr::memrchr(delimiter, text).map_or(0, |end| end + 1);
+    let last = Some(&text[last_begin..]).filter(|line| !line.is_empty());
+    let mut begin = 0;
+    Memchr::new(delimiter, text)
+        .map(move |end| {
+            let line = &text[begin..end];
+            begin = end + 1;
+            line
+        })
+        .chain(last)
+}
+
+// Sets are implemented as variations on the `IndexSet` type
+//
+pub trait LineSet<'a> {
+    // The only method that implementations need to define is `insert_line`
+    fn insert_line(&mut self, line: &'a TextSlice);
+
+    // The `insert_all_lines` method breaks `text` down into lines ending in
+    // `config.delimiter` and inserts each of them, normalized, into `self`.
+    // With `config.record_size` set it breaks `text` into records of that
+    // size instead; a short record at the end is inserted as is, so callers
+    // that want to reject it must check the text's length.
+    //
+    // Both leave out the lines outside `config.shard`, after normalizing them.
+    fn insert_all_lines(&mut self, text: &'a TextSlice, config: &Config) {
+        if let Some(size) = config.record_size {
+            for record in text.chunks(size) {
+                self.insert_in_shard(config.normalize_line(record), config);
+            }
+            return;
+        }
+        for line in split_lines(text, config.delimiter) {
+            self.insert_in_shard(config.normalize_line(line), config);
+        }
+    }
+    // The `insert_all_words` method is the same, but breaks `text` at every
+    // space, tab, and newline, and skips the empty words between adjacent
+    // whitespace
+    fn insert_all_words(&mut self, text: &'a TextSlice, config: &Config) {
+        let mut begin = 0;
+        for end in Memchr3::new(b' ', b'\t', b'\n', text) {
+            if begin < end {
+                self.insert_in_shard(config.normalize_line(&text[begin..end]), config);
+            }
+            begin = end + 1;
+        }
+        if begin < text.len() {
+            self.insert_in_shard(config.normalize_line(&text[begin..]), config);
+        }
+    }
+    fn insert_in_shard(&mut self, line: &'a TextSlice, config: &Config) {
+        if !config.skips_line(line) && config.shard.is_none_or(|shard| shard.contains(line)) {
+            self.insert_line(line);
+        }
+    }
+    // We initialize a `LineSet` from `text` by inserting every line contained
+    // in text into an empty hash.
+    fn init_from_slice(text: &'a TextSlice, config: &Config) -> Self
+    where
+        Self: Default,
+    {
+        let mut set = Self::default();
+        set.insert_all_lines(text, config);
+        set
+    }
+    // For lines that come already split, as from a database cursor, rather
+    // than in a text: each is inserted as it is, without being split,
+    // normalized or sharded. A set that borrows its lines, like a `SliceSet`,
+    // borrows them from wherever the iterator's items live.
+    fn insert_lines(&mut self, lines: impl IntoIterator<Item = &'a TextSlice>) {
+        for line in lines {
+            self.insert_line(line);
+        }
+    }
+    fn init_from_lines(lines: impl IntoIterator<Item = &'a TextSlice>) -> Self
+    where
+        Self: Default,
+    {
+        let mut set = Self::default();
+        set.insert_lines(lines);
+        set
+    }
+}
+
+// A `LineList` keeps the lines it's given in order, duplicates and all
+//
+#[derive(Default)]
+struct LineList<'a>(Vec<&'a TextSlice>);
+impl<'a> LineSet<'a> for LineList<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.0.push(line);
+    }
+}
+
+// A `LineCount` just counts the lines it's given, duplicates and all
+//
+#[derive(Default)]
+struct LineCount(usize);
+impl<'a> LineSet<'a> for LineCount {
+    fn insert_line(&mut self, _line: &'a TextSlice) {
+        self.0 += 1;
+    }
+}
+
+// The lines of `text`, counted whatever shard they're in
+pub fn count_lines(text: &TextSlice, config: &Config) -> usize {
+    LineCount::init_from_slice(text, &Config { shard: None, ..config.clone() }).0
+}
+
+// Whether `text` has any line more than once
+pub fn has_duplicates(text: &TextSlice, config: &Config) -> bool {
+    count_duplicates(text, config) > 0
+}
+
+// How many lines of `text` repeat an earlier line, in any shard
+pub fn count_duplicates(text: &TextSlice, config: &Config) -> usize {
+    let config = &Config { shard: None, ..config.clone() };
+    count_lines(text, config) - SliceSet::init_from_slice(text, config).len()
+}
+
+// Remembers the lines it's seen until one comes again
+#[derive(Default)]
+struct FirstDuplicate<'a> {
+    seen: SliceSet<'a>,
+    found: Option<&'a TextSlice>,
+}
+
+impl<'a> LineSet<'a> for FirstDuplicate<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        if self.found.is_none() && !self.seen.insert(line) {
+            self.found = Some(line);
+        }
+    }
+}
+
+// The first line of `text` that repeats an earlier one
+pub fn find_first_duplicate<'a>(text: &'a TextSlice, config: &Config) -> Option<&'a TextSlice> {
+    FirstDuplicate::init_from_slice(text, config).found
+}
+
+// Counts lines until it sees one longer than `max`
+struct LongLine {
+    max: usize,
+    lines: usize,
+    found: bool,
+}
+
+impl<'a> LineSet<'a> for LongLine {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        if !self.found {
+            self.lines += 1;
+            self.found = line.len() > self.max;
+        }
+    }
+}
+
+// The number, from 1, of the first line in `text` longer than
+// `config.max_line_length`, when `config.on_long_line` makes one an error.
+// Lines are numbered as they are in `text`, whatever shard they're in.
+pub fn find_long_line(text: &TextSlice, config: &Config) -> Option<usize> {
+    let max = config.max_line_length?;
+    if config.on_long_line != OnLongLine::Error {
+        return None;
+    }
+    let mut long = LongLine { max, lines: 0, found: false };
+    long.insert_all_lines(text, &Config { shard: None, ..config.clone() });
+    if long.found {
+        Some(long.lines)
+    } else {
+        None
+    }
+}
+
+// `find_long_line` as a `LineTooLong` error, for `text` from the file at
+// `path` after its first `lines_before` lines
+pub fn check_line_lengths(path: &Path, text: &TextSlice, lines_before: usize, config: &Config) -> Result<(), SetopError> {
+    let line = match find_long_line(text, config) {
+        Some(line) => line,
+        None => return Ok(()),
+    };
+    let length = match config.record_size {
+        Some(size) => size,
+        None => split_lines(text, config.delimiter).nth(line - 1).map_or(0, |found| config.normalize_line(found).len()),
+    };
+    Err(SetopError::LineTooLong { path: path.to_path_buf(), line: lines_before + line, length })
+}
+
+// `text` split after its first `config.header_lines` lines, delimiter and
+// all, into the header and the rest. A text with no more lines than that is
+// all header.
+pub fn split_header<'a>(text: &'a TextSlice, config: &Config) -> (&'a TextSlice, &'a TextSlice) {
+    if config.header_lines == 0 {
+        return text.split_at(0);
+    }
+    let end = Memchr::new(config.delimiter, text).nth(config.header_lines - 1).map_or(text.len(), |n| n + 1);
+    text.split_at(end)
+}
+
+// The simplest `LineSet` is a `SliceSet`, whose members (hash keys) are slices
+// borrowed from a text string, each slice corresponding to a line.
+//
+pub type SliceSet<'a> = IndexSet<&'a TextSlice>;
+impl<'a> LineSet<'a> for SliceSet<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.insert(line);
+    }
+}
+
+// A `SliceCounts` is a `SliceSet` that also counts how many times each line
+// occurs, for the multiset operations.
+//
+pub type SliceCounts<'a> = IndexMap<&'a TextSlice, usize>;
+impl<'a> LineSet<'a> for SliceCounts<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        *self.entry(line).or_insert(0) += 1;
+    }
+}
+
+// The next simplest set is a `UnionSet`, which we use to calculate the union
+// of the lines which occur in at least one of a sequence of files. Rather than
+// keep the text of all files in memory, we allocate a `TextVec` for each set member.
+//
+impl<'a> LineSet<'a> for UnionSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.insert(line.to_vec());
+    }
+}
+
+// When every operand's text will outlive the set, a union can borrow its
+// members just as a `SliceSet` does, and skip allocating a `TextVec` per line.
+// The operands are borrowed for `'a`, so none of them can be dropped or
+// changed while the set exists.
+//
+#[derive(Default)]
+pub struct BorrowingUnionSet<'a>(SliceSet<'a>);
+
+impl<'a> LineSet<'a> for BorrowingUnionSet<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.0.insert(line);
+    }
+}
+
+impl<'a> SetExpression<'a> for BorrowingUnionSet<'a> {
+    fn operate(&mut self, text: &'a TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.retain(|line| keep(line));
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.iter().cloned())
+    }
+    fn result_len(&self) -> usize {
+        self.0.len()
+    }
+    fn nth_line(&self, n: usize) -> Option<&TextSlice> {
+        self.0.get_index(n).cloned()
+    }
+    fn index_of(&self, line: &TextSlice) -> Option<usize> {
+        self.0.get_full(line).map(|(i, _)| i)
+    }
+}
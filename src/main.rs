@@ -1,165 +1,1352 @@
 use std::{
-    io::{self, Write},
-    slice::Iter,
+    borrow::Cow,
+    env, fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
 };
 
-use indexmap::IndexSet;
-use memchr::Memchr;
+use minimal::{
+    check_line_lengths, content_hash, count_duplicates, count_lines, decode_utf16_bom, directory_files, do_calculation,
+    estimate_union_cardinality, find_first_duplicate, finish_and_write, intersect_with_positions,
+    merge_sorted_difference, merge_sorted_intersect, merge_sorted_union, minimum_set_cover, operate_all, split_header,
+    write_calculation, write_calculation_with_stats, write_positions, write_rolling_union, AnnotatedIntersectSet,
+    BorrowingUnionSet, BoundedUnionSet, CachedIntersectSet, CaseTransform, CommSet, ComplementSet, Config, Diff3Set,
+    ExternalSortSet, FieldConfig, IntersectSet, IntersectSetExt, JoinSet, KeyedIntersectSet, LineSet, MergeStrategy,
+    MultisetIntersectSet, NonNumeric, NotInAllSet, OnLongLine, OpName, OutputFormat, SampleSize, SetCache, SetExpr, SetExpression,
+    SetopError, Shard, StreamingUnion, TextVec, WordIntersectSet,
+};
+
+const USAGE: &str = "usage: minimal [OPTIONS] OPERATION FILE...
+       minimal [OPTIONS] --estimate-union FILE...
+       minimal [OPTIONS] --cover FILE...
+       minimal [OPTIONS] --check FILE...
+       minimal [OPTIONS] --complement UNIVERSE FILE...
+       minimal [OPTIONS] --cross FILE FILE
+       minimal [OPTIONS] diff3 --base FILE --a FILE --b FILE
+       minimal [OPTIONS] --expr EXPRESSION
+
+operations:
+    union, u, +         lines in any file
+    intersect, i, x     lines in every file
+    multiset-union      lines in any file, each repeated as many times as
+                        in the file where it's most frequent
+    multiset-intersect  lines in every file, each repeated as many times as
+                        in the file where it's least frequent
+    not-in-all          lines in the first file that are missing from at
+                        least one of the others
+    join                lines whose first tab-separated field is in every
+                        file, each written as that field followed by the
+                        rest of its first line in each file in turn
+    complement          lines in the first file that are in none of the
+                        others
+    comm                compare two files like comm: lines only in the
+                        first, then indented by a tab lines only in the
+                        second, then by two tabs lines in both
+    cross               every pair of a line of the first file and a line
+                        of the second, separated by a tab or --separator
+    diff3               merge two files, A and B, with the base file they
+                        both came from, the first of the three, like diff3
+                        -m: what only one of them changed is taken from it,
+                        and where they changed the same lines differently
+                        there's a conflict, marked with A's lines, the
+                        base's and B's; each file is taken as its distinct
+                        lines
+    at-least            lines in at least 1 file, or as many as --at-least
+                        says
+    concat              every line of every file, in order, repeats and all
+    count               the number of distinct lines in a single file
+
+options:
+    --expr EXPRESSION   calculate EXPRESSION, like '(a.txt + b.txt) x c.txt',
+                        instead of one operation over every file: + is
+                        union and x is intersect, x goes before +, and
+                        parentheses group; operators need spaces around them
+    --estimate-union    print an approximate count of the distinct lines in
+                        all the files, using constant memory
+    --cover             print the names of a few of the files whose union is
+                        the union of them all, picking the file with the most
+                        lines not yet covered each time; not always the
+                        fewest files, but never more than about ln(L) + 1
+                        times as many, for L distinct lines
+    --complement        the same as the complement operation
+    --suppress COLUMNS  with comm, leave out the columns numbered in COLUMNS,
+                        like --suppress 1 or --suppress 12
+    -v, --invert-match  with not-in-all, write the lines of the first file
+                        that are in all the others instead, in its order
+    --print-positions   with intersect, write a line for every place each
+                        result line is, as the line, a colon, the index of
+                        the file (from 0), a colon, and the line number
+    --base FILE, --a FILE, --b FILE
+                        with diff3, the base file and the two files to
+                        compare with it, in place of three files in order
+    --master FILE       use FILE as the first file, wherever it's given, and
+                        leave it out of the other files if it's there too;
+                        for operations where the first file is special, so
+                        the others can come from a glob
+    -D, --directory DIR also operate on every file under DIR, in order of
+                        their paths, after any files given
+    --extension EXT     with --directory, only the files ending in .EXT
+    --delimiter BYTE    end lines with BYTE instead of newline; BYTE is a
+                        decimal number (30), a hex number (0x1e), a single
+                        character (,), or \\t for tab or \\0 for NUL
+    -W, --ignore-trailing-whitespace
+                        compare lines without trailing spaces, tabs and
+                        carriage returns, and output them trimmed
+    --trim-cr-only      compare and output lines without one carriage return
+                        at the end, if they have it, as for files with CRLF
+                        line endings, leaving other whitespace alone; does
+                        nothing with --delimiter \\0
+    --format FORMAT     raw (the default) writes each line followed by the
+                        delimiter; json writes a JSON array of strings, and
+                        fails if a result line isn't UTF-8; ndjson writes
+                        each line as a JSON string on its own line, with
+                        bytes that aren't UTF-8 escaped as \\u00XX;
+                        summary writes the number of lines, their total
+                        bytes, and their shortest, longest and mean length,
+                        each as a name, a tab and the number
+    --ndjson            the same as --format ndjson
+    --summary           the same as --format summary
+    --rolling-window N  with union, write the union of every run of N
+                        consecutive files in turn, with a blank line between
+                        one run's result and the next
+    --max-memory BYTES  with union, fail when the distinct lines, with an
+                        estimate of what it takes to hold them, would take
+                        more than BYTES; the files are read one at a time,
+                        and the one being read isn't counted
+    --external          compute a union by sorting chunks of the input to
+                        temporary files and merging them, for input too big
+                        for memory; the output is sorted
+    --chunk-size BYTES  with --external, the bytes of distinct lines to hold
+                        in memory before writing a sorted chunk (default 64MiB)
+    --record-size BYTES treat the files as fixed-size records of BYTES bytes
+                        rather than lines; a file whose length isn't a
+                        multiple of BYTES is an error
+    --header            treat the first line of each file as a header: leave
+                        it out of the operation, and write the first file's
+                        header, unchanged, before the result
+    --header-lines N    the same, with a header of N lines
+    --print-empty-marker MARKER
+                        if the result is empty, write MARKER as a line of its
+                        own rather than nothing
+    --shard I/N         work on just the lines whose hash puts them in shard
+                        I of N, numbered from 0, in every file, so that the
+                        results for shards 0/N to N-1/N between them have the
+                        lines of the whole result, for splitting one big
+                        operation across machines; only with union,
+                        intersect, complement and operations like them
+    --sample N          write a random sample of N of the result lines, or
+                        with a fraction like 0.01, that share of them; the
+                        sample keeps the lines' order, and is taken before
+                        --numeric-sort, --skip and --max-lines; only when
+                        built with the sampling feature
+    --seed N            with --sample, pick the sample using seed N, so the
+                        same seed picks the same lines
+    --skip N            leave out the first N result lines
+    --max-lines N, --limit N
+                        write at most N result lines, after any skipped
+    -n, --no-final-newline
+                        write the delimiter between result lines but not
+                        after the last, as a separated list
+    --numeric-sort      write the result sorted by the number at the start
+                        of each line, such as -2.5 or 10, ignoring the
+                        locale; lines without one come first
+    --non-numeric-last  with --numeric-sort, put lines without a number last
+    --max-line-length N fail if any line in a file is longer than N bytes,
+                        to guard against input that isn't really lines
+    --truncate-long-lines
+                        with --max-line-length, cut long lines down to N
+                        bytes instead of failing
+    --on-long-line truncate|skip|reject
+                        with --max-line-length, truncate long lines as
+                        --truncate-long-lines does, leave them out, or fail
+                        (the default)
+    --min-length N      leave out result lines shorter than N bytes
+    --max-length N      leave out result lines longer than N bytes
+    --to-lower, --to-upper
+                        change the files' text to lower or upper case before
+                        comparing lines, so the output is changed too; only
+                        ASCII letters change, and other bytes pass through
+    --hash              compare the SHA-256 digests of the lines rather than
+                        the lines themselves, and write the digests in hex;
+                        a digest is of the line without its delimiter
+    --unicode-case      with --to-lower or --to-upper, change the case of
+                        non-ASCII letters too in files that are UTF-8
+    -w, --words         with union or intersect, work on the words of the
+                        files (separated by spaces, tabs and newlines) rather
+                        than their lines
+    --word-frequency    with union or intersect, count the words of the
+                        files, and write each with a tab and its count, the
+                        most frequent first: with union, its total count in
+                        all the files, and with intersect, the words in
+                        every file, counted in the file with fewest
+    --key-width N       with union or intersect, count two lines as the same
+                        if their first N bytes match, and output the first
+                        whole line seen with each key
+    --ignore-field-order
+                        with union or intersect, count two lines as the same
+                        if they have the same tab-separated fields in any
+                        order, and output the first line seen with them
+    --exclusive         with union, keep only the lines that are in exactly
+                        one file
+    --stream            with union, write each line as soon as it's first
+                        seen, reading the files one at a time, rather than
+                        once they've all been read; the output can't be
+                        sorted or formatted
+    --sorted-set        with union, keep the lines in a sorted set, so the
+                        output is sorted bytewise
+    --sorted            with union, intersect or complement, trust that every
+                        file is already sorted bytewise, as by LC_ALL=C sort,
+                        and merge them a line at a time like sort -m, using
+                        almost no memory; if they aren't sorted, neither is
+                        the result, and it may be wrong
+    --weighted          with union, read each line as a count, a tab and a
+                        line, and write each distinct line once after the
+                        total of its counts and a tab
+    --at-least K        with union, keep only the lines in at least K files
+    --file-weights LIST with --at-least, give the files these weights, like
+                        3,1,1, in the order they're operated on, and keep the
+                        lines whose files weigh at least K between them;
+                        files past the end of LIST weigh 1
+    --map-delimiter BYTE
+                        with union or intersect, read each line as a key
+                        and a value separated by the first BYTE, such as :,
+                        and do the operation on the keys, writing each key
+                        once with the values of its lines merged
+    --map-merge first|last|append|append:BYTE
+                        with --map-delimiter, keep the first value seen for
+                        each key (the default), the last, or all of them
+                        separated by commas or BYTE
+    --group-by-field N  with union, group the lines by the value of their
+                        Nth tab-separated field, counting from 1, and write
+                        each group's value followed by its distinct lines,
+                        each indented by a tab
+    --field LIST        with union or intersect, count two lines as the same
+                        if the tab-separated fields numbered in LIST, like 2
+                        or 1,3, match, and output the first whole line seen
+                        with them
+    --separator TEXT    with cross, put TEXT between the lines of each pair
+                        instead of a tab
+    --fd, --field-delimiter BYTE
+                        with join, --field, --ignore-field-order or
+                        --group-by-field, end fields with BYTE instead of a
+                        tab
+    --weight-delimiter BYTE
+                        with --weighted, separate counts with BYTE instead
+                        of a tab
+    --require-weights   with --weighted, fail on a line without a count
+                        rather than counting it as 1
+    -S, --source        with union, start each line with the index (from 0)
+                        of the first file it appears in, and a tab
+    -F, --print-files   with union, start each line with the name of the
+                        first file it appears in, and a tab; with
+                        intersect, with the names of all the files,
+                        separated by commas, and a tab
+    -p, --progress      show the files being read on stderr, with the bytes
+                        read so far and the time left; only when built with
+                        the progress feature
+    --cache             keep the result in .setop_cache in the current
+                        directory, and when the operation, options and
+                        files are all the same as for a result kept there,
+                        write that result instead of computing it again
+    --print-stats       after the result, write to stderr how many lines and
+                        distinct lines each file has, how many lines the
+                        result has, the bytes read and the milliseconds the
+                        operation took
+    -q, --quiet         write nothing; exit with status 0 if there are any
+                        lines to write, after --skip and --limit, and 1 if
+                        there are none
+    --ignore-errors     warn about a file that can't be opened and leave it
+                        out, rather than failing; the result is written as
+                        if it had never been given, but the exit status is
+                        still 2
+    --check-unique      before the operation, check that no file has a line
+                        more than once, and fail if any does
+    --check             just read every file and report how many lines it
+                        has; fail if any file can't be read (with --quiet,
+                        only the exit status tells)
 
-#[derive(Clone, Copy)]
-enum OpName {
-    Union,
-    Intersect,
+A file that starts with a UTF-16 byte order mark is read as UTF-16 and
+converted to UTF-8, so its lines match the same lines in UTF-8 files; except
+with --record-size, --external or --sorted, which take the bytes as they are.
+
+The exit status is 2 after an error. If the output is closed before all of it
+is written, as by `head`, minimal stops and exits with status 0. Built with
+the color feature, minimal writes errors in red and warnings in yellow when
+stderr is a terminal, unless NO_COLOR is set.";
+
+#[derive(Default)]
+struct Args {
+    op: Option<OpName>,
+    estimate_union: bool,
+    cover: bool,
+    check: bool,
+    check_unique: bool,
+    non_numeric_last: bool,
+    seed: Option<u64>,
+    progress: bool,
+    words: bool,
+    source: bool,
+    print_files: bool,
+    ignore_errors: bool,
+    stream: bool,
+    map: bool,
+    word_frequency: bool,
+    weighted: bool,
+    sorted: bool,
+    presorted: bool,
+    cache: bool,
+    print_stats: bool,
+    expression: Option<SetExpr>,
+    exclusive: bool,
+    external: bool,
+    chunk_size: Option<usize>,
+    rolling_window: Option<usize>,
+    master: Option<PathBuf>,
+    // diff3's base, A and B, if given by name rather than in order
+    diff3_files: [Option<PathBuf>; 3],
+    field_indices: Option<Vec<usize>>,
+    directory: Option<PathBuf>,
+    extension: Option<String>,
+    positions: bool,
+    files: Vec<PathBuf>,
+    config: Config,
 }
 
-type TextVec = Vec<u8>;
-type TextSlice = [u8];
+// Parse a byte given as a decimal number, a `0x` hex number, a single
+// character, or the escape `\t` or `\0`. Digits are read as numbers, so the
+// character `5` must be given as `0x35`.
+fn parse_byte(text: &str) -> Result<u8, String> {
+    match text {
+        "\\t" => return Ok(b'\t'),
+        "\\0" => return Ok(0),
+        _ => {}
+    }
+    let number = if text.starts_with("0x") || text.starts_with("0X") {
+        Some(u32::from_str_radix(&text[2..], 16))
+    } else if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) {
+        Some(text.parse::<u32>())
+    } else {
+        None
+    };
+    match number {
+        Some(Ok(n)) if n <= 0xff => Ok(n as u8),
+        Some(_) => Err(format!("{} is not a byte value (0 to 255)", text)),
+        None if text.len() == 1 => Ok(text.as_bytes()[0]),
+        None => Err(format!("{} is not a single byte", text)),
+    }
+}
 
-trait SetExpression
-where
-    Self: Sized,
-    // We can't say Sized + IntoLineIterator: rustc complains that there's
-    // no implementation for type Foo, just for type &'a Foo
-{
-    fn operate(&mut self, text: &TextSlice);
-    fn finish(&mut self) {}
-    fn iter<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me>;
+// A shard and the number of shards, like `0/4`
+fn parse_shard(text: &str) -> Result<Shard, String> {
+    let shard = text
+        .split_once('/')
+        .and_then(|(index, count)| Some(Shard { index: index.parse().ok()?, count: count.parse().ok()? }));
+    match shard {
+        Some(shard) if shard.index < shard.count => Ok(shard),
+        _ => Err(format!("--shard needs a shard and a number of shards, like 0/4, not {}", text)),
+    }
 }
 
-// I can't figure out how to implement this function inside the `SetExpression` trait,
-// so every `impl trait SetExpression` will have have a `write_to` function that
-// just calls `rite_to`
-//
-fn rite_to(zelf: &impl SetExpression, out: &mut impl Write) {
-    for line in zelf.iter() {
-        out.write_all(line.as_ref()).unwrap();
+// A number of lines, like `100`, or a fraction of them, like `0.01`
+fn parse_sample(text: &str) -> Result<SampleSize, String> {
+    if let Ok(lines) = text.parse() {
+        return Ok(SampleSize::Lines(lines));
+    }
+    match text.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(SampleSize::Fraction(fraction)),
+        _ => Err(format!("--sample needs a number of lines or a fraction from 0 to 1, not {}", text)),
     }
 }
 
-type UnionSet = IndexSet<TextVec>;
+fn parse_count(option: &str, text: &str) -> Result<usize, String> {
+    text.parse().map_err(|_| format!("{} needs a number, not {}", option, text))
+}
 
-trait UnionSetExt {
-    fn init(text: TextVec) -> Self;
+// `first`, `last`, or `append`, which separates values with a comma, or
+// `append:BYTE` for any other separator
+fn parse_merge(text: &str) -> Result<MergeStrategy, String> {
+    match text {
+        "first" => Ok(MergeStrategy::FirstWins),
+        "last" => Ok(MergeStrategy::LastWins),
+        "append" => Ok(MergeStrategy::Append(b',')),
+        _ if text.starts_with("append:") => Ok(MergeStrategy::Append(parse_byte(&text["append:".len()..])?)),
+        _ => Err(format!("--map-merge needs first, last, append or append:BYTE, not {}", text)),
+    }
 }
 
-impl UnionSetExt for UnionSet {
-    // The first operand is initialized by calling the `LineSet`'s initialization method.
-    fn init(text: TextVec) -> Self {
-        UnionSet::init_from_slice(&text)
+// A comma-separated list of field numbers, from 1, such as `2` or `1,3`
+fn parse_fields(option: &str, text: &str) -> Result<Vec<usize>, String> {
+    let fields = text.split(',').map(|field| parse_count(option, field)).collect::<Result<Vec<_>, _>>()?;
+    if fields.contains(&0) {
+        return Err(format!("{} counts fields from 1", option));
     }
+    Ok(fields)
+}
+
+fn option_value(option: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs a value\n{}", option, USAGE))
 }
 
-impl SetExpression for UnionSet {
-    // For subsequent operands we simply insert each line into the hash
-    fn operate(&mut self, text: &TextSlice) {
-        self.insert_all_lines(&text);
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--estimate-union" => parsed.estimate_union = true,
+            "--cover" => parsed.cover = true,
+            "--complement" => parsed.op = Some(OpName::Complement),
+            "--cross" => parsed.op = Some(OpName::Cross),
+            "--separator" => parsed.config.cross_separator = option_value(&arg, &mut args)?.into_bytes(),
+            "--suppress" => {
+                for column in option_value(&arg, &mut args)?.chars() {
+                    match column {
+                        '1'..='3' => parsed.config.comm_suppress[column as usize - '1' as usize] = true,
+                        _ => return Err(format!("--suppress takes column numbers 1, 2 and 3, not {}", column)),
+                    }
+                }
+            }
+            "-v" | "--invert-match" => parsed.config.invert_match = true,
+            "--print-positions" => parsed.positions = true,
+            "-D" | "--directory" => parsed.directory = Some(PathBuf::from(option_value(&arg, &mut args)?)),
+            "--extension" => parsed.extension = Some(option_value(&arg, &mut args)?),
+            "--master" => parsed.master = Some(PathBuf::from(option_value(&arg, &mut args)?)),
+            "--base" => parsed.diff3_files[0] = Some(PathBuf::from(option_value(&arg, &mut args)?)),
+            "--a" => parsed.diff3_files[1] = Some(PathBuf::from(option_value(&arg, &mut args)?)),
+            "--b" => parsed.diff3_files[2] = Some(PathBuf::from(option_value(&arg, &mut args)?)),
+            "--check" => parsed.check = true,
+            "--check-unique" => parsed.check_unique = true,
+            "--header" => parsed.config.header_lines = 1,
+            "--header-lines" => parsed.config.header_lines = parse_count(&arg, &option_value(&arg, &mut args)?)?,
+            "-p" | "--progress" => parsed.progress = true,
+            "--cache" => parsed.cache = true,
+            "--expr" => {
+                let expression = option_value(&arg, &mut args)?.parse().map_err(|err: SetopError| err.to_string())?;
+                parsed.expression = Some(expression);
+            }
+            "--print-stats" => parsed.print_stats = true,
+            "-q" | "--quiet" => parsed.config.quiet = true,
+            "-n" | "--no-final-newline" => parsed.config.no_final_delimiter = true,
+            "--min-length" => parsed.config.min_length = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "--max-length" => parsed.config.max_length = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "--to-lower" => parsed.config.case_transform = Some(CaseTransform::Lower),
+            "--to-upper" => parsed.config.case_transform = Some(CaseTransform::Upper),
+            "--hash" => parsed.config.hash_lines = true,
+            "--unicode-case" => parsed.config.unicode_case = true,
+            "--numeric-sort" => parsed.config.numeric_sort = Some(NonNumeric::First),
+            "--non-numeric-last" => parsed.non_numeric_last = true,
+            "--print-empty-marker" => parsed.config.empty_marker = Some(option_value(&arg, &mut args)?.into_bytes()),
+            "--shard" => parsed.config.shard = Some(parse_shard(&option_value(&arg, &mut args)?)?),
+            "--sample" => parsed.config.sample = Some(parse_sample(&option_value(&arg, &mut args)?)?),
+            "--seed" => {
+                let seed = option_value(&arg, &mut args)?;
+                parsed.seed = Some(seed.parse().map_err(|_| format!("--seed needs a number, not {}", seed))?);
+            }
+            "--skip" => parsed.config.skip_lines = parse_count(&arg, &option_value(&arg, &mut args)?)?,
+            "--max-lines" | "--limit" => parsed.config.max_lines = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "-w" | "--words" => parsed.words = true,
+            "--word-frequency" => parsed.word_frequency = true,
+            "-S" | "--source" => parsed.source = true,
+            "-F" | "--print-files" => parsed.print_files = true,
+            "--ignore-errors" => parsed.ignore_errors = true,
+            "--stream" => parsed.stream = true,
+            "--map-delimiter" => {
+                parsed.config.map_delimiter = parse_byte(&option_value(&arg, &mut args)?)?;
+                parsed.map = true;
+            }
+            "--map-merge" => {
+                parsed.config.map_merge = parse_merge(&option_value(&arg, &mut args)?)?;
+                parsed.map = true;
+            }
+            "--at-least" => parsed.config.threshold = Some(parse_count(&arg, &option_value(&arg, &mut args)?)? as u64),
+            "--file-weights" => {
+                let weights = option_value(&arg, &mut args)?;
+                parsed.config.operand_weights =
+                    weights.split(',').map(|weight| parse_count(&arg, weight).map(|w| w as u64)).collect::<Result<_, _>>()?;
+            }
+            "--group-by-field" => parsed.config.group_by_field = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "--weighted" => parsed.weighted = true,
+            "--sorted-set" => parsed.sorted = true,
+            "--sorted" => parsed.presorted = true,
+            "--exclusive" => parsed.exclusive = true,
+            "--field" => parsed.field_indices = Some(parse_fields(&arg, &option_value(&arg, &mut args)?)?),
+            "--fd" | "--field-delimiter" => parsed.config.field_delimiter = parse_byte(&option_value(&arg, &mut args)?)?,
+            "--weight-delimiter" => parsed.config.weight_delimiter = parse_byte(&option_value(&arg, &mut args)?)?,
+            "--require-weights" => parsed.config.require_weights = true,
+            "--max-line-length" => parsed.config.max_line_length = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "--truncate-long-lines" => parsed.config.on_long_line = OnLongLine::Truncate,
+            "--on-long-line" => {
+                parsed.config.on_long_line = match option_value(&arg, &mut args)?.as_str() {
+                    "truncate" => OnLongLine::Truncate,
+                    "skip" => OnLongLine::Skip,
+                    "reject" => OnLongLine::Error,
+                    other => return Err(format!("--on-long-line needs truncate, skip or reject, not {}", other)),
+                }
+            }
+            "--ignore-field-order" => parsed.config.ignore_field_order = true,
+            "--key-width" => parsed.config.key_width = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "--external" => parsed.external = true,
+            "--rolling-window" => parsed.rolling_window = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "--max-memory" => parsed.config.max_memory = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "--chunk-size" => parsed.chunk_size = Some(parse_count(&arg, &option_value(&arg, &mut args)?)?),
+            "-W" | "--ignore-trailing-whitespace" => parsed.config.ignore_trailing_whitespace = true,
+            "--trim-cr-only" => parsed.config.trim_cr = true,
+            "--ndjson" => parsed.config.format = OutputFormat::Ndjson,
+            "--summary" => parsed.config.format = OutputFormat::Summary,
+            "--format" => {
+                parsed.config.format = match option_value(&arg, &mut args)?.as_str() {
+                    "raw" => OutputFormat::Raw,
+                    "json" => OutputFormat::Json,
+                    "ndjson" => OutputFormat::Ndjson,
+                    "summary" => OutputFormat::Summary,
+                    other => return Err(format!("unknown format {} (expected raw, json, ndjson or summary)", other)),
+                }
+            }
+            "--record-size" => {
+                let size = parse_count(&arg, &option_value(&arg, &mut args)?)?;
+                if size == 0 {
+                    return Err("--record-size must be at least 1".to_string());
+                }
+                parsed.config.record_size = Some(size);
+            }
+            "--delimiter" => parsed.config.delimiter = parse_byte(&option_value(&arg, &mut args)?)?,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+            _ => positional.push(arg),
+        }
+    }
+    let mut positional = positional.into_iter();
+    if !parsed.estimate_union && !parsed.cover && !parsed.check && parsed.op.is_none() && parsed.expression.is_none() {
+        parsed.op = match positional.next() {
+            Some(name) => Some(name.parse().map_err(|err| format!("{}\n{}", err, USAGE))?),
+            None => return Err(USAGE.to_string()),
+        };
+    }
+    if parsed.words {
+        parsed.op = match parsed.op {
+            Some(OpName::Union) => Some(OpName::WordUnion),
+            Some(OpName::Intersect) => Some(OpName::WordIntersect),
+            _ => return Err("--words only works with union and intersect".to_string()),
+        };
+    }
+    if parsed.word_frequency {
+        parsed.op = match parsed.op {
+            Some(OpName::Union) => Some(OpName::WordFreqUnion),
+            Some(OpName::Intersect) => Some(OpName::WordFreqIntersect),
+            _ => return Err("--word-frequency only works with union and intersect of lines".to_string()),
+        };
+    }
+    if parsed.map {
+        parsed.op = match parsed.op {
+            Some(OpName::Union) => Some(OpName::MapUnion),
+            Some(OpName::Intersect) => Some(OpName::MapIntersect),
+            _ => return Err("--map-delimiter and --map-merge only work with union and intersect of lines".to_string()),
+        };
+    }
+    if parsed.print_files {
+        if parsed.source {
+            return Err("--print-files can't be used with --source".to_string());
+        }
+        parsed.op = match parsed.op {
+            Some(OpName::Union) => Some(OpName::AnnotatedUnion),
+            Some(OpName::Intersect) => Some(OpName::AnnotatedIntersect),
+            _ => return Err("--print-files only works with union and intersect of lines".to_string()),
+        };
+    }
+    if parsed.source {
+        if parsed.words || !matches!(parsed.op, Some(OpName::Union)) {
+            return Err("--source only works with union of lines".to_string());
+        }
+        parsed.op = Some(OpName::AnnotatedUnion);
+    }
+    if parsed.exclusive {
+        if !matches!(parsed.op, Some(OpName::Union)) {
+            return Err("--exclusive only works with union of lines".to_string());
+        }
+        parsed.op = Some(OpName::Exclusive);
+    }
+    if parsed.sorted {
+        if !matches!(parsed.op, Some(OpName::Union)) {
+            return Err("--sorted-set only works with union of lines".to_string());
+        }
+        parsed.op = Some(OpName::SortedUnion);
+    }
+    if parsed.config.invert_match && parsed.op != Some(OpName::NotInAll) {
+        return Err("--invert-match only works with not-in-all".to_string());
+    }
+    if parsed.positions && !matches!(parsed.op, Some(OpName::Intersect)) {
+        return Err("--print-positions only works with intersect of lines".to_string());
+    }
+    if parsed.config.threshold.is_some() {
+        if !matches!(parsed.op, Some(OpName::Union)) {
+            return Err("--at-least only works with union of lines".to_string());
+        }
+        parsed.op = Some(OpName::AtLeast);
+    }
+    if !parsed.config.operand_weights.is_empty() && parsed.op != Some(OpName::AtLeast) {
+        return Err("--file-weights only works with --at-least".to_string());
+    }
+    if let Some(field) = parsed.config.group_by_field {
+        if !matches!(parsed.op, Some(OpName::Union)) {
+            return Err("--group-by-field only works with union of lines".to_string());
+        }
+        if field == 0 {
+            return Err("--group-by-field counts fields from 1".to_string());
+        }
+        parsed.op = Some(OpName::GroupedUnion);
+    }
+    if parsed.weighted {
+        if !matches!(parsed.op, Some(OpName::Union)) {
+            return Err("--weighted only works with union of lines".to_string());
+        }
+        parsed.op = Some(OpName::WeightedUnion);
+    }
+    if let Some(field_indices) = parsed.field_indices.take() {
+        if parsed.config.key_width.is_some() || parsed.config.ignore_field_order {
+            return Err("--field can't be used with --key-width or --ignore-field-order".to_string());
+        }
+        parsed.op = match parsed.op {
+            Some(OpName::Union) => Some(OpName::KeyedUnion),
+            Some(OpName::Intersect) => Some(OpName::KeyedIntersect),
+            _ => return Err("--field only works with union and intersect of lines".to_string()),
+        };
+        parsed.config.fields = Some(FieldConfig { field_indices, field_delimiter: parsed.config.field_delimiter });
+    }
+    if parsed.config.key_width.is_some() && parsed.config.ignore_field_order {
+        return Err("--key-width can't be used with --ignore-field-order".to_string());
+    }
+    if parsed.config.ignore_field_order {
+        parsed.op = match parsed.op {
+            Some(OpName::Union) => Some(OpName::KeyedUnion),
+            Some(OpName::Intersect) => Some(OpName::KeyedIntersect),
+            _ => return Err("--ignore-field-order only works with union and intersect of lines".to_string()),
+        };
+    }
+    if parsed.config.key_width.is_some() {
+        parsed.op = match parsed.op {
+            Some(OpName::Union) => Some(OpName::KeyedUnion),
+            Some(OpName::Intersect) => Some(OpName::KeyedIntersect),
+            _ => return Err("--key-width only works with union and intersect of lines".to_string()),
+        };
+    }
+    if let Some(size) = parsed.rolling_window {
+        if !matches!(parsed.op, Some(OpName::Union)) {
+            return Err("--rolling-window only works with union of lines".to_string());
+        }
+        if size == 0 {
+            return Err("--rolling-window needs at least 1 file".to_string());
+        }
+    }
+    if parsed.config.max_memory.is_some() && (parsed.op != Some(OpName::Union) || parsed.external) {
+        return Err("--max-memory only works with union of lines, without --external".to_string());
+    }
+    if parsed.presorted {
+        if !matches!(parsed.op, Some(OpName::Union) | Some(OpName::Intersect) | Some(OpName::Complement)) {
+            return Err("--sorted only works with union, intersect and complement of lines".to_string());
+        }
+        let config = &parsed.config;
+        let changes_lines = config.format != OutputFormat::Raw
+            || config.record_size.is_some()
+            || config.header_lines > 0
+            || config.case_transform.is_some()
+            || config.hash_lines
+            || config.numeric_sort.is_some()
+            || config.max_memory.is_some()
+            || (config.max_line_length.is_some() && config.on_long_line != OnLongLine::Truncate);
+        if changes_lines || parsed.external {
+            return Err("--sorted can't be used with options that check, change or sort lines as they're read".to_string());
+        }
+    }
+    if parsed.external && !matches!(parsed.op, Some(OpName::Union)) {
+        return Err("--external only works with union".to_string());
+    }
+    if parsed.config.hash_lines && parsed.config.record_size.is_some() {
+        return Err("--hash can't be used with --record-size".to_string());
+    }
+    if parsed.config.empty_marker.is_some() && parsed.config.format != OutputFormat::Raw {
+        return Err("--print-empty-marker only works with raw output".to_string());
     }
-    fn iter<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
-        Box::new(self.iter().map(|v| v.as_slice()))
+    if parsed.config.no_final_delimiter {
+        let config = &parsed.config;
+        let other_mode = parsed.positions || parsed.rolling_window.is_some() || parsed.check || parsed.estimate_union || parsed.cover;
+        if config.format != OutputFormat::Raw || config.record_size.is_some() || other_mode {
+            return Err("--no-final-newline only works with raw output of result lines".to_string());
+        }
+    }
+    if parsed.config.header_lines > 0 {
+        if parsed.config.format != OutputFormat::Raw || parsed.config.record_size.is_some() {
+            return Err("--header only works with raw output of lines".to_string());
+        }
+        if parsed.estimate_union || parsed.cover || parsed.op == Some(OpName::Count) {
+            return Err("--header only works with operations that write lines".to_string());
+        }
+    }
+    if parsed.config.skip_lines > 0 && (parsed.stream || parsed.presorted) {
+        return Err("--skip can't be used with --stream or --sorted".to_string());
+    }
+    if parsed.stream {
+        let config = &parsed.config;
+        let other_mode = parsed.presorted
+            || parsed.external
+            || parsed.rolling_window.is_some()
+            || parsed.cache
+            || parsed.print_stats
+            || config.max_memory.is_some();
+        if parsed.op != Some(OpName::Union) || config.format != OutputFormat::Raw || config.numeric_sort.is_some() || other_mode {
+            return Err("--stream only works with union of lines, written raw and unsorted".to_string());
+        }
+    }
+    if parsed.cache || parsed.print_stats {
+        let other_mode = parsed.external
+            || parsed.presorted
+            || parsed.positions
+            || parsed.rolling_window.is_some()
+            || parsed.estimate_union
+            || parsed.cover
+            || parsed.check;
+        let option = if parsed.cache { "--cache" } else { "--print-stats" };
+        if parsed.op.is_none() || other_mode {
+            return Err(format!("{} only works with an operation that writes its result", option));
+        }
+        if parsed.cache && parsed.print_stats {
+            return Err("--print-stats can't be used with --cache".to_string());
+        }
+    }
+    if parsed.external && parsed.config.record_size.is_some() {
+        return Err("--external can't be used with --record-size".to_string());
+    }
+    parsed.files = positional.map(PathBuf::from).collect();
+    match (&parsed.directory, &parsed.extension) {
+        (Some(dir), extension) => {
+            let found = directory_files(dir, extension.as_deref()).map_err(|err| format!("{}: {}", dir.display(), err))?;
+            parsed.files.extend(found);
+        }
+        (None, Some(_)) => return Err("--extension needs --directory".to_string()),
+        (None, None) => {}
+    }
+    if let Some(expression) = &parsed.expression {
+        let other_mode = parsed.op.is_some() || parsed.estimate_union || parsed.cover || parsed.master.is_some();
+        if other_mode || !parsed.files.is_empty() {
+            return Err("--expr takes the place of the operation and the files".to_string());
+        }
+        parsed.files = expression.operands().into_iter().map(PathBuf::from).collect();
+    }
+    if parsed.diff3_files.iter().any(Option::is_some) {
+        if parsed.op != Some(OpName::Diff3) {
+            return Err("--base, --a and --b only work with diff3".to_string());
+        }
+        if !parsed.files.is_empty() || parsed.master.is_some() {
+            return Err("--base, --a and --b take the place of diff3's files".to_string());
+        }
+        parsed.files = parsed.diff3_files.iter().flatten().cloned().collect();
+        if parsed.files.len() != 3 {
+            return Err("diff3 needs all of --base, --a and --b".to_string());
+        }
+    }
+    if let Some(master) = parsed.master.take() {
+        parsed.files.retain(|path| *path != master);
+        parsed.files.insert(0, master);
+    }
+    if parsed.files.is_empty() {
+        return Err(format!("no input files\n{}", USAGE));
+    }
+    if parsed.print_files {
+        parsed.config.file_names = parsed.files.iter().map(|path| path.display().to_string().into_bytes()).collect();
+    }
+    if parsed.config.sample.is_some() {
+        if cfg!(not(feature = "sampling")) {
+            return Err("--sample needs minimal built with the sampling feature".to_string());
+        }
+        if parsed.stream || parsed.presorted {
+            return Err("--sample can't be used with --stream or --sorted".to_string());
+        }
+        // Without a seed each run picks its own sample
+        #[cfg(feature = "sampling")]
+        {
+            parsed.config.sample_seed = parsed.seed.unwrap_or_else(rand::random);
+        }
+    } else if parsed.seed.is_some() {
+        return Err("--seed needs --sample".to_string());
+    }
+    if parsed.progress && cfg!(not(feature = "progress")) {
+        return Err("--progress needs minimal built with the progress feature".to_string());
+    }
+    if parsed.non_numeric_last {
+        if parsed.config.numeric_sort.is_none() {
+            return Err("--non-numeric-last needs --numeric-sort".to_string());
+        }
+        parsed.config.numeric_sort = Some(NonNumeric::Last);
+    }
+    if parsed.config.on_long_line != OnLongLine::Error && parsed.config.max_line_length.is_none() {
+        return Err("truncating or skipping long lines needs --max-line-length".to_string());
+    }
+    if parsed.config.operand_weights.len() > parsed.files.len() {
+        return Err("--file-weights has more weights than there are files".to_string());
+    }
+    if parsed.config.shard.is_some() {
+        // Operations whose result lines are whole lines of the operands,
+        // each kept or not by which operands have it
+        let by_whole_lines = matches!(
+            parsed.op,
+            Some(OpName::Union)
+                | Some(OpName::Intersect)
+                | Some(OpName::Complement)
+                | Some(OpName::NotInAll)
+                | Some(OpName::Exclusive)
+                | Some(OpName::AtLeast)
+                | Some(OpName::MultisetUnion)
+                | Some(OpName::MultisetIntersect)
+                | Some(OpName::WordUnion)
+                | Some(OpName::WordIntersect)
+                | Some(OpName::SortedUnion)
+                | Some(OpName::Count)
+        );
+        if !(by_whole_lines || parsed.expression.is_some()) || parsed.external || parsed.presorted {
+            return Err("--shard only works with operations on whole lines, like union, intersect and complement".to_string());
+        }
+    }
+    if parsed.ignore_errors && (parsed.expression.is_some() || !parsed.config.operand_weights.is_empty()) {
+        return Err("--ignore-errors can't be used with --expr or --file-weights".to_string());
+    }
+    if parsed.op == Some(OpName::Count) && parsed.files.len() > 1 {
+        return Err("count takes a single file".to_string());
     }
+    if parsed.op == Some(OpName::Comm) && parsed.files.len() != 2 {
+        return Err("comm takes two files".to_string());
+    }
+    if parsed.op == Some(OpName::Cross) && parsed.files.len() != 2 {
+        return Err("cross takes two files".to_string());
+    }
+    if parsed.op == Some(OpName::Diff3) && parsed.files.len() != 3 {
+        return Err("diff3 takes three files: the base, A and B".to_string());
+    }
+    Ok(parsed)
 }
 
-// For an `IntersectSet` all result lines will be from the
-// first file operand, so we can avoid additional allocations by keeping its
-// text in memory and using subslices of its text as the members of the set.
-type IntersectSet<'data> = IndexSet<&'data TextSlice>;
+fn read_file(path: &PathBuf, config: &Config) -> Result<TextVec, String> {
+    let mut text = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    if let Some(size) = config.record_size {
+        if text.len() % size != 0 {
+            return Err(format!("{}: length {} is not a multiple of the record size {}", path.display(), text.len(), size));
+        }
+    }
+    // Records are bytes, not text, so they're never decoded
+    if config.record_size.is_none() {
+        if let Some(decoded) = decode_utf16_bom(&text) {
+            text = decoded;
+        }
+    }
+    let header_len = split_header(&text, config).0.len();
+    text.drain(..header_len);
+    check_line_lengths(path, &text, config.header_lines, config).map_err(|err| err.to_string())?;
+    #[cfg(feature = "tracing")]
+    tracing::info!(file = %path.display(), lines = count_lines(&text, config), "read operand");
+    let transformed = match config.transform_text(&text) {
+        Cow::Owned(transformed) => Some(transformed),
+        Cow::Borrowed(_) => None,
+    };
+    Ok(transformed.unwrap_or(text))
+}
 
-trait IntersectSetExt<'data> {
-    fn init(text: &'data TextVec) -> Self;
+// When whoever reads our output stops early, as `head` does, we stop too and
+// exit with status 0, as if the whole result had been written: a closed pipe
+// isn't an error worth reporting. (Rust ignores SIGPIPE, so it shows up here
+// as a `BrokenPipe` write error rather than killing the process.)
+fn output_error(err: io::Error) -> String {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        process::exit(0);
+    }
+    err.to_string()
 }
 
-impl<'data> IntersectSetExt<'data> for IntersectSet<'data> {
-    fn init(text: &'data TextVec) -> Self {
-        SliceSet::init_from_slice(text)
+fn read_files(args: &Args) -> Result<Vec<TextVec>, String> {
+    let progress = Progress::start(&args.files, args.progress && !args.config.quiet);
+    let mut texts = Vec::with_capacity(args.files.len());
+    for (n, path) in args.files.iter().enumerate() {
+        progress.reading(n);
+        texts.push(read_file(path, &args.config)?);
     }
+    Ok(texts)
+}
+
+// With the `progress` feature, --progress shows a bar on stderr for the bytes
+// of the files read so far, with the name of the one being read and the time
+// left. The bar is cleared when `Progress` is dropped, so an error partway
+// through doesn't leave it drawn over the message.
+#[cfg(feature = "progress")]
+struct Progress {
+    bar: Option<indicatif::ProgressBar>,
+    files: Vec<(String, u64)>,
 }
 
-// For subsequent operands, we take a `SliceSet` `s` of the operand's text and
-// keep only those lines that occur in `s`.
-impl<'data> SetExpression for IntersectSet<'data> {
-    fn operate(&mut self, text: &TextSlice) {
-        let other = SliceSet::init_from_slice(text);
-        self.retain(|x| other.contains(x));
+#[cfg(feature = "progress")]
+impl Progress {
+    fn start(files: &[PathBuf], show: bool) -> Self {
+        if !show {
+            return Progress { bar: None, files: Vec::new() };
+        }
+        let files: Vec<_> = files
+            .iter()
+            .map(|path| (path.display().to_string(), fs::metadata(path).map_or(0, |meta| meta.len())))
+            .collect();
+        let bar = indicatif::ProgressBar::new(files.iter().map(|(_, size)| size).sum());
+        let style = indicatif::ProgressStyle::with_template("{msg} {bytes}/{total_bytes} {wide_bar} {eta} left")
+            .expect("the template is valid");
+        bar.set_style(style);
+        Progress { bar: Some(bar), files }
+    }
+
+    // Count the files before file `n` as read, and show its name
+    fn reading(&self, n: usize) {
+        if let Some(bar) = &self.bar {
+            bar.set_position(self.files[..n].iter().map(|(_, size)| size).sum());
+            bar.set_message(self.files[n].0.clone());
+        }
     }
-    fn iter<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
-        // Set<&VecSlice>
-        // .iter => &&VecSlice
-        // .cloned => &VecSlice
-        Box::new(self.iter().cloned())
+}
+
+#[cfg(feature = "progress")]
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
     }
 }
 
-fn do_calculation(op: OpName, mut texts: Iter<TextVec>) {
-    let txt = texts.next().unwrap();
-    match op {
-        OpName::Union => calculate_and_print(&mut UnionSet::init(txt.to_vec()), texts),
-        OpName::Intersect => calculate_and_print(&mut IntersectSet::init(&txt), texts),
+#[cfg(not(feature = "progress"))]
+struct Progress;
+
+#[cfg(not(feature = "progress"))]
+impl Progress {
+    fn start(_files: &[PathBuf], _show: bool) -> Self {
+        Progress
     }
+
+    fn reading(&self, _n: usize) {}
 }
 
-fn calculate_and_print<T>(set: &mut T, texts: Iter<TextVec>)
-where T: SetExpression
-{
-    for txt in texts {
-        set.operate(txt);
+// Feed the files to an `ExternalSortSet` in pieces of about `chunk_size`
+// bytes, each ending at a delimiter, so no file is ever wholly in memory
+fn external_union(args: &Args) -> Result<bool, String> {
+    let config = &args.config;
+    let chunk_size = args.chunk_size.unwrap_or(ExternalSortSet::DEFAULT_CHUNK_SIZE);
+    let mut set = ExternalSortSet::new(chunk_size).map_err(|err| format!("can't create temporary directory: {}", err))?;
+    for path in &args.files {
+        let read_error = |err: io::Error| format!("{}: {}", path.display(), err);
+        let mut reader = BufReader::new(File::open(path).map_err(read_error)?);
+        let mut piece = TextVec::new();
+        for _ in 0..config.header_lines {
+            reader.read_until(config.delimiter, &mut piece).map_err(read_error)?;
+        }
+        piece.clear();
+        let mut lines_before = config.header_lines;
+        loop {
+            let count = reader.read_until(config.delimiter, &mut piece).map_err(read_error)?;
+            if count == 0 || piece.len() >= chunk_size {
+                check_line_lengths(path, &piece, lines_before, config).map_err(|err| err.to_string())?;
+                if config.max_line_length.is_some() {
+                    lines_before += count_lines(&piece, config);
+                }
+                set.operate(&config.transform_text(&piece), config);
+                piece.clear();
+            }
+            if count == 0 {
+                break;
+            }
+        }
+    }
+    finish_and_write(&mut set, &mut io::stdout().lock(), config).map_err(output_error)
+}
+
+// Read one file at a time, so that the limit covers most of what we hold
+fn bounded_union(args: &Args) -> Result<bool, String> {
+    let limit = args.config.max_memory.unwrap_or(usize::MAX);
+    let mut set = BoundedUnionSet::new(limit);
+    for path in &args.files {
+        set.operate(&read_file(path, &args.config)?, &args.config);
+        if let Some((_, line)) = set.limit_reached_at() {
+            let line = args.config.header_lines + line;
+            return Err(format!("{}: line {} takes the union over the memory limit of {} bytes", path.display(), line, limit));
+        }
+    }
+    finish_and_write(&mut set, &mut io::stdout().lock(), &args.config).map_err(output_error)
+}
+
+// The result is keyed by a hash of the operation, every option (as `Config`
+// prints them) and each file's path and contents, and stored under the first
+// file's path. Only results with lines are stored, so a result found in the
+// cache is never empty.
+fn cached_calculation(op: OpName, texts: &[TextVec], args: &Args) -> Result<bool, String> {
+    let cache = SetCache::in_current_dir();
+    let mut key = format!("{:?} {:?}", op, args.config).into_bytes();
+    for (path, text) in args.files.iter().zip(texts) {
+        key.extend_from_slice(format!("\n{} {:016x}", path.display(), content_hash(text)).as_bytes());
+    }
+    let key = content_hash(&key);
+    let mut stdout = io::stdout().lock();
+    if let Some(result) = cache.check(&args.files[0], key) {
+        stdout.write_all(&result).map_err(output_error)?;
+        return Ok(true);
+    }
+    let mut result = Vec::new();
+    let found = write_calculation(op, texts.iter(), &mut result, &args.config).map_err(|err| err.to_string())?;
+    stdout.write_all(&result).map_err(output_error)?;
+    if found && !result.is_empty() {
+        let cache_error = |err: io::Error| format!("{}: {}", SetCache::FILE_NAME, err);
+        cache.store(&args.files[0], key, &result).map_err(cache_error)?;
+    }
+    Ok(found)
+}
+
+// Write the first file's header as it is, before the result. Only the header
+// is read here; `read_file` drops the headers of every file, this one too.
+fn write_header(path: &Path, config: &Config) -> Result<(), String> {
+    let read_error = |err: io::Error| format!("{}: {}", path.display(), err);
+    let mut reader = BufReader::new(File::open(path).map_err(read_error)?);
+    // A UTF-16 file has to be decoded whole to find the end of its header
+    if let [0xff, 0xfe, ..] | [0xfe, 0xff, ..] = reader.fill_buf().map_err(read_error)? {
+        let text = fs::read(path).map_err(read_error)?;
+        let text = decode_utf16_bom(&text).unwrap_or(text);
+        return io::stdout().lock().write_all(split_header(&text, config).0).map_err(output_error);
     }
-    set.finish();
+    let mut header = TextVec::new();
+    for _ in 0..config.header_lines {
+        reader.read_until(config.delimiter, &mut header).map_err(read_error)?;
+    }
+    io::stdout().lock().write_all(&header).map_err(output_error)
+}
+
+// Merge the files, which we're told are sorted, holding only a line of each
+fn merge_files(args: &Args) -> Result<bool, String> {
+    let config = &args.config;
+    let mut sources: Vec<Box<dyn BufRead>> = Vec::new();
+    for path in &args.files {
+        let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        sources.push(Box::new(BufReader::new(file)));
+    }
+    let merged: Box<dyn Iterator<Item = io::Result<TextVec>>> = match args.op {
+        Some(OpName::Intersect) => Box::new(merge_sorted_intersect(sources, config)),
+        Some(OpName::Complement) => Box::new(merge_sorted_difference(sources, config)),
+        _ => Box::new(merge_sorted_union(sources, config)),
+    };
+    let min = config.min_length.unwrap_or(0);
+    let max = config.max_length.unwrap_or(usize::MAX);
     let stdout_for_locking = io::stdout();
     let mut stdout = stdout_for_locking.lock();
-    rite_to(set, &mut stdout);
+    let mut written = 0;
+    for line in merged {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.len() < min || line.len() > max {
+            continue;
+        }
+        // Quiet, we only need to know there's a line
+        if config.quiet {
+            return Ok(true);
+        }
+        if Some(written) == config.max_lines {
+            break;
+        }
+        // Without a final delimiter, each goes before the line after it
+        if written > 0 && config.no_final_delimiter {
+            stdout.write_all(&[config.delimiter]).map_err(output_error)?;
+        }
+        stdout.write_all(&line).map_err(output_error)?;
+        if !config.no_final_delimiter {
+            stdout.write_all(&[config.delimiter]).map_err(output_error)?;
+        }
+        written += 1;
+    }
+    if let (0, Some(marker), false) = (written, &config.empty_marker, config.quiet) {
+        stdout.write_all(marker).map_err(output_error)?;
+        if !config.no_final_delimiter {
+            stdout.write_all(&[config.delimiter]).map_err(output_error)?;
+        }
+    }
+    Ok(written > 0)
 }
 
-// Sets are implemented as variations on the `IndexSet` type
-//
-trait LineSet<'a>
+// Report on every file, even after one fails, so a single run shows all the
+// problems
+fn check_files(args: &Args) -> Result<bool, String> {
+    let mut failures = 0;
+    for path in &args.files {
+        match read_file(path, &args.config) {
+            Ok(_) if args.config.quiet => {}
+            Ok(text) => {
+                let lines = count_lines(&text, &args.config);
+                writeln!(io::stdout(), "{}: {} lines", path.display(), lines).map_err(output_error)?;
+            }
+            Err(message) => {
+                report_error(message);
+                failures += 1;
+            }
+        }
+    }
+    match failures {
+        0 => Ok(true),
+        1 => Err("1 file could not be read".to_string()),
+        n => Err(format!("{} files could not be read", n)),
+    }
+}
+
+// Like `check_files`, report every file with a repeated line before failing
+fn check_unique(args: &Args) -> Result<(), String> {
+    let mut failures = 0;
+    for path in &args.files {
+        let text = read_file(path, &args.config)?;
+        if let Some(line) = find_first_duplicate(&text, &args.config) {
+            let count = count_duplicates(&text, &args.config);
+            let line = String::from_utf8_lossy(line);
+            let plural = if count == 1 { "" } else { "s" };
+            report_warning(format!("{}: {} repeated line{}, the first being {}", path.display(), count, plural, line));
+            failures += 1;
+        }
+    }
+    match failures {
+        0 => Ok(()),
+        1 => Err("1 file has repeated lines".to_string()),
+        n => Err(format!("{} files have repeated lines", n)),
+    }
+}
+
+// A set that doesn't borrow from its later operands can take them one at a
+// time, so we only read each file when it's needed — and once the result is
+// settled we don't open the remaining files at all.
+fn calculate_lazily<S>(mut set: S, args: &Args) -> Result<bool, String>
 where
-    Self: Default,
+    S: for<'x> SetExpression<'x>,
 {
-    // The only method that implementations need to define is `insert_line`
-    fn insert_line(&mut self, line: &'a TextSlice);
-
-    // The `insert_all_lines` method breaks `text` down into lines and inserts
-    // each of them into `self`
-    fn insert_all_lines(&mut self, text: &'a TextSlice) {
-        let mut begin = 0;
-        for end in Memchr::new(b'\n', text) {
-            self.insert_line(&text[begin..=end]);
-            begin = end + 1;
+    // The bar goes before the result is written
+    {
+        let progress = Progress::start(&args.files[1..], args.progress && !args.config.quiet);
+        if !set.is_settled() {
+            for (n, path) in args.files[1..].iter().enumerate() {
+                progress.reading(n);
+                if set.operate_early_exit(&read_file(path, &args.config)?, &args.config) {
+                    break;
+                }
+            }
         }
-        if begin < text.len() {
-            self.insert_line(&text[begin..]);
+    }
+    finish_and_write(&mut set, &mut io::stdout().lock(), &args.config).map_err(output_error)
+}
+
+// Like `calculate_lazily`, but a complement only looks a later file's lines
+// up in the first file's, so it reads each one a line at a time instead of
+// whole. A file that has to be checked, decoded or transformed as a whole is
+// still read by `read_file`.
+fn complement_streaming(mut set: ComplementSet, args: &Args) -> Result<bool, String> {
+    let config = &args.config;
+    let whole = config.record_size.is_some()
+        || config.header_lines > 0
+        || config.case_transform.is_some()
+        || config.hash_lines
+        || (config.max_line_length.is_some() && config.on_long_line == OnLongLine::Error);
+    {
+        let progress = Progress::start(&args.files[1..], args.progress && !config.quiet);
+        for (n, path) in args.files[1..].iter().enumerate() {
+            if set.is_settled() {
+                break;
+            }
+            progress.reading(n);
+            let read_error = |err: io::Error| format!("{}: {}", path.display(), err);
+            let mut reader = BufReader::new(File::open(path).map_err(read_error)?);
+            let utf16 = matches!(reader.fill_buf().map_err(read_error)?, [0xff, 0xfe, ..] | [0xfe, 0xff, ..]);
+            if whole || utf16 {
+                set.operate(&read_file(path, config)?, config);
+            } else {
+                set.operate_reader(reader, config).map_err(read_error)?;
+            }
         }
     }
-    // We initialize a `LineSet` from `text` by inserting every line contained
-    // in text into an empty hash.
-    fn init_from_slice(text: &'a TextSlice) -> Self {
-        let mut set = Self::default();
-        set.insert_all_lines(text);
-        set
+    finish_and_write(&mut set, &mut io::stdout().lock(), config).map_err(output_error)
+}
+
+// Each file is read only once the lines of the ones before it are written,
+// and once --max-lines lines are, the rest aren't read at all
+fn stream_union(args: &Args) -> Result<bool, String> {
+    let mut union = StreamingUnion::new(io::stdout().lock(), &args.config);
+    for path in &args.files {
+        if union.is_settled() {
+            break;
+        }
+        union.operate(&read_file(path, &args.config)?, &args.config).map_err(output_error)?;
     }
+    Ok(union.finish().map_err(output_error)? > 0)
 }
 
-// The simplest `LineSet` is a `SliceSet`, whose members (hash keys) are slices
-// borrowed from a text string, each slice corresponding to a line.
-//
-type SliceSet<'a> = IndexSet<&'a TextSlice>;
-impl<'a> LineSet<'a> for SliceSet<'a> {
-    fn insert_line(&mut self, line: &'a TextSlice) {
-        self.insert(line);
+// An expression reads each file when it needs it, so an intersection that
+// comes out empty can skip what's left of its operands
+fn evaluate_expression(expression: &SetExpr, args: &Args) -> Result<bool, String> {
+    let mut read = |name: &str| read_file(&PathBuf::from(name), &args.config);
+    let mut set = expression.evaluate(&mut read, &args.config)?;
+    finish_and_write(&mut set, &mut io::stdout().lock(), &args.config).map_err(output_error)
+}
+
+// A cross product of two big files is bigger than anyone's likely to want
+fn warn_about_large_product(texts: &[TextVec], config: &Config) {
+    const LARGE: usize = 1_000_000;
+    let distinct = |text: &TextVec| count_lines(text, config) - count_duplicates(text, config);
+    let pairs = texts.iter().map(distinct).fold(1usize, |product, n| product.saturating_mul(n));
+    if pairs > LARGE {
+        report_warning(format!("the cross product has {} lines", pairs));
     }
 }
 
-// The next simplest set is a `UnionSet`, which we use to calculate the union
-// of the lines which occur in at least one of a sequence of files. Rather than
-// keep the text of all files in memory, we allocate a `TextVec` for each set member.
-//
-impl<'a> LineSet<'a> for UnionSet {
-    fn insert_line(&mut self, line: &'a TextSlice) {
-        self.insert(line.to_vec());
+// Returns whether the result has any lines, for --quiet's exit status
+fn run(args: Args) -> Result<bool, String> {
+    if args.check {
+        return check_files(&args);
+    }
+    if args.check_unique {
+        check_unique(&args)?;
+    }
+    if args.config.header_lines > 0 && !args.config.quiet {
+        write_header(&args.files[0], &args.config)?;
+    }
+    if let Some(expression) = &args.expression {
+        return evaluate_expression(expression, &args);
+    }
+    if args.stream {
+        return stream_union(&args);
+    }
+    if args.presorted {
+        return merge_files(&args);
+    }
+    if args.external {
+        return external_union(&args);
+    }
+    if args.positions {
+        let mut found = intersect_with_positions(&read_files(&args)?, &args.config);
+        // Number lines as they are in the files, headers and all
+        for (_, positions) in &mut found {
+            for (_, number) in positions.iter_mut() {
+                *number += args.config.header_lines;
+            }
+        }
+        if !args.config.quiet {
+            write_positions(&found, &mut io::stdout().lock(), &args.config).map_err(output_error)?;
+        }
+        return Ok(!found.is_empty());
+    }
+    if let (true, Some(op)) = (args.cache, args.op) {
+        return cached_calculation(op, &read_files(&args)?, &args);
+    }
+    if let (true, Some(op)) = (args.print_stats, args.op) {
+        let texts = read_files(&args)?;
+        let stats = write_calculation_with_stats(op, texts.iter(), &mut io::stdout().lock(), &args.config)
+            .map_err(output_error)?;
+        stats.write_to(&mut io::stderr().lock()).map_err(|err| err.to_string())?;
+        return Ok(stats.result_line_count > 0);
+    }
+    match args.op {
+        Some(OpName::Union) if args.config.max_memory.is_some() => return bounded_union(&args),
+        Some(OpName::Intersect) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(CachedIntersectSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::MultisetIntersect) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(MultisetIntersectSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::AnnotatedIntersect) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(AnnotatedIntersectSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::NotInAll) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(NotInAllSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::WordIntersect) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(WordIntersectSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::Complement) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return complement_streaming(ComplementSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::Comm) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(CommSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::Diff3) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(Diff3Set::init(&first, &args.config), &args);
+        }
+        Some(OpName::Join) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(JoinSet::init(&first, &args.config), &args);
+        }
+        Some(OpName::KeyedIntersect) if args.config.fields.is_some() => {
+            let first = read_file(&args.files[0], &args.config)?;
+            let fields = args.config.fields.as_ref().expect("checked by the guard");
+            return calculate_lazily(KeyedIntersectSet::init_by_fields(&first, fields, &args.config), &args);
+        }
+        Some(OpName::KeyedIntersect) if args.config.ignore_field_order => {
+            let first = read_file(&args.files[0], &args.config)?;
+            return calculate_lazily(KeyedIntersectSet::init_ignoring_field_order(&first, &args.config), &args);
+        }
+        Some(OpName::KeyedIntersect) => {
+            let first = read_file(&args.files[0], &args.config)?;
+            let key_width = args.config.key_width.unwrap_or(usize::MAX);
+            return calculate_lazily(KeyedIntersectSet::init(&first, key_width, &args.config), &args);
+        }
+        _ => {}
+    }
+    let texts = read_files(&args)?;
+    if let Some(size) = args.rolling_window {
+        return write_rolling_union(&texts, size, &mut io::stdout().lock(), &args.config).map_err(output_error);
+    }
+    if args.estimate_union {
+        let slices: Vec<_> = texts.iter().map(|t| t.as_slice()).collect();
+        let estimate = estimate_union_cardinality(&slices, &args.config);
+        if !args.config.quiet {
+            writeln!(io::stdout(), "{:.0}", estimate).map_err(output_error)?;
+        }
+        return Ok(estimate >= 0.5);
+    }
+    if args.op == Some(OpName::Cross) {
+        warn_about_large_product(&texts, &args.config);
+    }
+    if args.cover {
+        let chosen = minimum_set_cover(&texts, &args.config);
+        if !args.config.quiet {
+            let mut stdout = io::stdout().lock();
+            for &n in &chosen {
+                writeln!(stdout, "{}", args.files[n].display()).map_err(output_error)?;
+            }
+        }
+        return Ok(!chosen.is_empty());
+    }
+    match args.op {
+        Some(op) => do_calculation(op, texts.iter(), &args.config).map_err(output_error),
+        None => Ok(false),
     }
 }
 
-fn main() {
+// The demo's output is fixed: lines come out in the order they were first
+// seen, because `UnionSet` and `IntersectSet` are `IndexSet`s and iterating one
+// follows insertion order, not hash order. So changing the hasher or the set
+// type must not change these bytes. The union is
+//
+//     now is the time
+//     now is the hour
+//     there is the rhyme
+//     but where is the flower?
+//     eh? what's that you say?
+//     and there's a bunny on road
+//
+// (`txt_a` in its own order, then the lines only `txt_b` has, in its order,
+// each once), and the intersection is `txt_a`'s lines that `txt_b` has too,
+// in `txt_a`'s order:
+//
+//     now is the hour
+//     there is the rhyme
+//     but where is the flower?
+fn demo() -> io::Result<()> {
+    let config = Config::default();
     let txt_a = b"now is the time
 now is the hour
 there is the rhyme
@@ -172,11 +1359,131 @@ there is the rhyme
 and there's a bunny on road
 and there's a bunny on road
 ".to_vec();
-    let texts = vec![txt_a, txt_b];
+    let texts = [txt_a, txt_b];
+
+    // Both sets take each text in the same pass
+    let mut union = BorrowingUnionSet::init_from_slice(&texts[0], &config);
+    let mut intersection = IntersectSet::init(&texts[0], &config);
+    operate_all(&mut [&mut union, &mut intersection], texts[1..].iter(), &config);
 
-    println!("\nUnion =========================");
-    do_calculation(OpName::Union, texts.iter());
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "\nUnion =========================")?;
+    finish_and_write(&mut union, &mut out, &config)?;
 
-    println!("\nIntersection =========================");
-    do_calculation(OpName::Intersect, texts.iter());
+    writeln!(out, "\nIntersection =========================")?;
+    finish_and_write(&mut intersection, &mut out, &config)?;
+    Ok(())
+}
+
+// With the `color` feature, messages on stderr are colored with ANSI escapes
+// when it's a terminal and NO_COLOR isn't set to anything. Only stderr is ever
+// colored; the result on stdout never is.
+fn report(message: impl fmt::Display, color: &str) {
+    let colored = cfg!(feature = "color")
+        && io::stderr().is_terminal()
+        && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    if colored {
+        eprintln!("\x1b[{}m{}\x1b[0m", color, message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+// In red
+fn report_error(message: impl fmt::Display) {
+    report(message, "31");
+}
+
+// In yellow: a problem with a file that doesn't stop the others being read
+fn report_warning(message: impl fmt::Display) {
+    report(message, "33");
+}
+
+// With --ignore-errors, drop the files that can't be opened before anything
+// else reads them, warning about each, and return how many there were. Left
+// out, a file can change the result a great deal — an intersection without it
+// may have more lines, a complement of it far fewer — so the warning says so.
+fn skip_unopenable(args: &mut Args) -> usize {
+    let mut keep = Vec::with_capacity(args.files.len());
+    for path in &args.files {
+        let opened = File::open(path);
+        if let Err(err) = &opened {
+            report_warning(format!("{}: {}; leaving it out of the result", path.display(), err));
+        }
+        keep.push(opened.is_ok());
+    }
+    let skipped = keep.iter().filter(|&&kept| !kept).count();
+    let mut kept = keep.iter();
+    args.files.retain(|_| *kept.next().unwrap_or(&true));
+    if !args.config.file_names.is_empty() {
+        let mut kept = keep.iter();
+        args.config.file_names.retain(|_| *kept.next().unwrap_or(&true));
+    }
+    skipped
+}
+
+// With no arguments we show the sample calculation this program started out as
+fn main() {
+    if env::args().len() <= 1 {
+        if let Err(err) = demo() {
+            report_error(err);
+            process::exit(2);
+        }
+        return;
+    }
+    let mut args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            report_error(message);
+            process::exit(2);
+        }
+    };
+    let skipped = if args.ignore_errors { skip_unopenable(&mut args) } else { 0 };
+    if args.files.is_empty() {
+        report_error("none of the files could be opened");
+        process::exit(2);
+    }
+    let quiet = args.config.quiet;
+    match run(args) {
+        Ok(_) if skipped > 0 => {
+            let plural = if skipped == 1 { " was" } else { "s were" };
+            report_error(format!("{} file{} left out", skipped, plural));
+            process::exit(2);
+        }
+        Ok(false) if quiet => process::exit(1),
+        Ok(_) => {}
+        Err(message) => {
+            report_error(message);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_byte_takes_numbers_characters_and_escapes() {
+        assert_eq!(parse_byte("30"), Ok(30));
+        assert_eq!(parse_byte("0"), Ok(0));
+        assert_eq!(parse_byte("255"), Ok(255));
+        assert_eq!(parse_byte("0x1e"), Ok(0x1e));
+        assert_eq!(parse_byte("0XFF"), Ok(0xff));
+        assert_eq!(parse_byte(","), Ok(b','));
+        assert_eq!(parse_byte("\\t"), Ok(b'\t'));
+        assert_eq!(parse_byte("\\0"), Ok(0));
+        assert_eq!(parse_byte("0x35"), Ok(b'5'));
+    }
+
+    #[test]
+    fn parse_byte_rejects_what_isnt_one_byte() {
+        assert_eq!(parse_byte("256"), Err("256 is not a byte value (0 to 255)".to_string()));
+        assert_eq!(parse_byte("0x100"), Err("0x100 is not a byte value (0 to 255)".to_string()));
+        assert_eq!(parse_byte("0xg"), Err("0xg is not a byte value (0 to 255)".to_string()));
+        assert_eq!(parse_byte("ab"), Err("ab is not a single byte".to_string()));
+        assert_eq!(parse_byte("é"), Err("é is not a single byte".to_string()));
+        assert_eq!(parse_byte(""), Err(" is not a single byte".to_string()));
+    }
 }
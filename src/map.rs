@@ -0,0 +1,214 @@
+use std::io;
+
+use indexmap::IndexMap;
+
+use crate::{buffer::LineBuffer, Config, LineSet, SetExpression, SliceSet, TextSlice, TextVec};
+
+// How a `MapSet` combines the values of lines with the same key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeStrategy {
+    // Keep the first value seen with the key
+    FirstWins,
+    // Keep the last
+    LastWins,
+    // Keep them all, in the order they were seen, separated by this byte
+    Append(u8),
+}
+
+// A `MapSet` reads each line as a key and a value, split at the first
+// `key_delim`, and does set operations on the keys while merging the values
+// of lines that share one. A line without `key_delim` is a key with no value.
+// The map union has every key; the map intersection only the keys in every
+// operand, with the values merged from all of them. Repeats of a line within
+// one operand are merged only once, but different lines with the same key
+// all are.
+//
+// `finish` writes each key, `key_delim` and its value on a line, or just the
+// key if no line with it had a value, with the keys in the order they were
+// first seen. Filters see these whole lines.
+//
+pub struct MapSet {
+    key_delim: u8,
+    map: IndexMap<TextVec, Option<TextVec>>,
+    merge: MergeStrategy,
+    intersect: bool,
+    // For each key in `map`, in the same order, how many operands have it and
+    // the index of the last that did
+    operands_with: Vec<(usize, usize)>,
+    operands: usize,
+    merged: LineBuffer,
+}
+
+impl MapSet {
+    pub fn init_union(text: &TextSlice, config: &Config) -> Self {
+        MapSet::init(text, false, config)
+    }
+
+    pub fn init_intersect(text: &TextSlice, config: &Config) -> Self {
+        MapSet::init(text, true, config)
+    }
+
+    fn init(text: &TextSlice, intersect: bool, config: &Config) -> Self {
+        let mut set = MapSet {
+            key_delim: config.map_delimiter,
+            map: IndexMap::new(),
+            merge: config.map_merge,
+            intersect,
+            operands_with: Vec::new(),
+            operands: 0,
+            merged: LineBuffer::default(),
+        };
+        set.operate(text, config);
+        set
+    }
+
+    fn write_lines(&mut self) {
+        self.merged = LineBuffer::default();
+        for (key, value) in &self.map {
+            match value {
+                Some(value) => self.merged.push(&[key, &[self.key_delim], value]),
+                None => self.merged.push(&[key]),
+            }
+        }
+    }
+}
+
+impl<'a> LineSet<'a> for MapSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        let (key, value) = match memchr::memchr(self.key_delim, line) {
+            Some(at) => (&line[..at], Some(&line[at + 1..])),
+            None => (line, None),
+        };
+        let i = match self.map.get_full_mut(key) {
+            Some((i, _, old)) => {
+                match (self.merge, old.as_mut(), value) {
+                    (_, _, None) | (MergeStrategy::FirstWins, Some(_), _) => {}
+                    (MergeStrategy::Append(separator), Some(old), Some(value)) => {
+                        old.push(separator);
+                        old.extend_from_slice(value);
+                    }
+                    (_, _, Some(value)) => *old = Some(value.to_vec()),
+                }
+                i
+            }
+            // In an intersection, a key the first operand doesn't have can't
+            // be in every operand
+            None if self.intersect && self.operands > 0 => return,
+            None => {
+                self.map.insert(key.to_vec(), value.map(|value| value.to_vec()));
+                self.operands_with.push((0, usize::MAX));
+                self.map.len() - 1
+            }
+        };
+        let (count, last) = &mut self.operands_with[i];
+        if *last != self.operands {
+            *count += 1;
+            *last = self.operands;
+        }
+    }
+}
+
+impl<'data> SetExpression<'data> for MapSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        for line in SliceSet::init_from_slice(text, config) {
+            self.insert_line(line);
+        }
+        self.operands += 1;
+    }
+    fn is_settled(&self) -> bool {
+        self.intersect && self.map.is_empty()
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        if self.intersect {
+            let operands = self.operands;
+            let mut counts = self.operands_with.iter();
+            self.map.retain(|_, _| counts.next().is_some_and(|&(count, _)| count == operands));
+            self.operands_with.retain(|&(count, _)| count == operands);
+        }
+        self.write_lines();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.merged.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        let key_delim = self.key_delim;
+        let mut counts = self.operands_with.iter();
+        let mut kept_counts = Vec::new();
+        self.map.retain(|key, value| {
+            let count = counts.next();
+            let mut line = key.clone();
+            if let Some(value) = value {
+                line.push(key_delim);
+                line.extend_from_slice(value);
+            }
+            let kept = keep(&line);
+            if kept {
+                kept_counts.extend(count);
+            }
+            kept
+        });
+        self.operands_with = kept_counts;
+        // If `finish` has already run, its output needs the same filtering
+        if !self.merged.is_empty() {
+            self.write_lines();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(merge: MergeStrategy) -> Config {
+        Config { map_merge: merge, ..Config::default() }
+    }
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    fn union(merge: MergeStrategy) -> MapSet {
+        let config = config(merge);
+        let mut set = MapSet::init_union(b"u1:a\nu2:b\nu1:a\n", &config);
+        set.operate(b"u3:c\nu1:d\nu2\n", &config);
+        set.finish().unwrap();
+        set
+    }
+
+    #[test]
+    fn first_wins_keeps_the_first_value() {
+        assert_eq!(lines(&union(MergeStrategy::FirstWins)), [&b"u1:a"[..], b"u2:b", b"u3:c"]);
+    }
+
+    #[test]
+    fn last_wins_keeps_the_last_value() {
+        assert_eq!(lines(&union(MergeStrategy::LastWins)), [&b"u1:d"[..], b"u2:b", b"u3:c"]);
+    }
+
+    #[test]
+    fn append_keeps_every_value_once_for_each_operand() {
+        assert_eq!(lines(&union(MergeStrategy::Append(b','))), [&b"u1:a,d"[..], b"u2:b", b"u3:c"]);
+    }
+
+    #[test]
+    fn a_key_without_a_value_is_written_alone() {
+        let config = config(MergeStrategy::LastWins);
+        let mut set = MapSet::init_union(b"k\n", &config);
+        set.operate(b"k\nj:1\n", &config);
+        set.finish().unwrap();
+        assert_eq!(lines(&set), [&b"k"[..], b"j:1"]);
+    }
+
+    #[test]
+    fn intersection_keeps_the_keys_in_every_operand() {
+        let config = config(MergeStrategy::Append(b'|'));
+        let mut set = MapSet::init_intersect(b"u1:a\nu2:b\nu3:c\n", &config);
+        set.operate(b"u2:x\nu1:y\nu4:z\n", &config);
+        set.operate(b"u1:w\nu2:v\n", &config);
+        set.finish().unwrap();
+        assert_eq!(lines(&set), [&b"u1:a|y|w"[..], b"u2:b|x|v"]);
+        set.retain_lines(|line| line.starts_with(b"u2"));
+        assert_eq!(lines(&set), [&b"u2:b|x|v"[..]]);
+    }
+}
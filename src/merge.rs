@@ -0,0 +1,184 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{self, BufRead},
+};
+
+use crate::{Config, TextVec};
+
+// `merge_sorted_union` computes the union of inputs that are already sorted,
+// a line at a time, holding just the next line of each source. Like
+// `ExternalSortSet::merge` it's a k-way merge: the heap holds the smallest
+// line not yet taken from each source, tagged with the source it came from,
+// and every copy of the smallest line is taken at once, noting which sources
+// had it. If a source isn't sorted the output isn't either, and repeated
+// lines may get through.
+//
+// Lines are split at `config.delimiter` and normalized, but come out without
+// their delimiter. A read error ends the iteration.
+//
+pub fn merge_sorted_union(sources: Vec<Box<dyn BufRead>>, config: &Config) -> impl Iterator<Item = io::Result<TextVec>> {
+    SortedMerge::new(sources, Keep::Any, config)
+}
+
+// The lines in every source, as `merge_sorted_union` finds the lines in any
+pub fn merge_sorted_intersect(sources: Vec<Box<dyn BufRead>>, config: &Config) -> impl Iterator<Item = io::Result<TextVec>> {
+    SortedMerge::new(sources, Keep::All, config)
+}
+
+// The lines in the first source and none of the others
+pub fn merge_sorted_difference(sources: Vec<Box<dyn BufRead>>, config: &Config) -> impl Iterator<Item = io::Result<TextVec>> {
+    SortedMerge::new(sources, Keep::FirstOnly, config)
+}
+
+// Which lines a merge keeps, by the sources that have them
+#[derive(Clone, Copy)]
+enum Keep {
+    Any,
+    All,
+    FirstOnly,
+}
+
+impl Keep {
+    fn keeps(self, present: &[bool]) -> bool {
+        match self {
+            Keep::Any => true,
+            Keep::All => present.iter().all(|&p| p),
+            Keep::FirstOnly => present[0] && !present[1..].iter().any(|&p| p),
+        }
+    }
+}
+
+struct SortedMerge {
+    sources: Vec<Box<dyn BufRead>>,
+    heap: BinaryHeap<Reverse<(TextVec, usize)>>,
+    keep: Keep,
+    started: bool,
+    done: bool,
+    config: Config,
+}
+
+impl SortedMerge {
+    fn new(sources: Vec<Box<dyn BufRead>>, keep: Keep, config: &Config) -> Self {
+        SortedMerge { sources, heap: BinaryHeap::new(), keep, started: false, done: false, config: config.clone() }
+    }
+
+    fn read_line(&mut self, k: usize) -> io::Result<()> {
+        let mut line = TextVec::new();
+        if self.sources[k].read_until(self.config.delimiter, &mut line)? == 0 {
+            return Ok(());
+        }
+        if line.last() == Some(&self.config.delimiter) {
+            line.pop();
+        }
+        let len = self.config.normalize_line(&line).len();
+        line.truncate(len);
+        self.heap.push(Reverse((line, k)));
+        Ok(())
+    }
+
+    fn next_line(&mut self) -> io::Result<Option<TextVec>> {
+        if !self.started {
+            self.started = true;
+            for k in 0..self.sources.len() {
+                self.read_line(k)?;
+            }
+        }
+        while let Some(Reverse((line, k))) = self.heap.pop() {
+            let mut present = vec![false; self.sources.len()];
+            present[k] = true;
+            self.read_line(k)?;
+            // The source's next line may be the same again, so this takes
+            // every copy of it, from any source
+            while let Some(Reverse((next, k))) = self.heap.peek() {
+                if *next != line {
+                    break;
+                }
+                present[*k] = true;
+                let k = *k;
+                self.heap.pop();
+                self.read_line(k)?;
+            }
+            if self.keep.keeps(&present) {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for SortedMerge {
+    type Item = io::Result<TextVec>;
+    fn next(&mut self) -> Option<io::Result<TextVec>> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_line().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{diff_n, intersect_n};
+
+    fn sources(texts: &[&'static str]) -> Vec<Box<dyn BufRead>> {
+        texts.iter().map(|text| Box::new(Cursor::new(text.as_bytes())) as Box<dyn BufRead>).collect()
+    }
+
+    fn collect(lines: impl Iterator<Item = io::Result<TextVec>>) -> Vec<String> {
+        lines.map(|line| String::from_utf8(line.unwrap()).unwrap()).collect()
+    }
+
+    #[test]
+    fn union_of_sorted_sources_is_sorted_and_distinct() {
+        let config = Config::default();
+        let merged = merge_sorted_union(sources(&["a\nc\nc\ne\n", "b\nc\nf", "a\nd\ne\ng\n"]), &config);
+        assert_eq!(collect(merged), ["a", "b", "c", "d", "e", "f", "g"]);
+    }
+
+    #[test]
+    fn union_of_no_lines_is_empty() {
+        let config = Config::default();
+        assert!(collect(merge_sorted_union(sources(&["", ""]), &config)).is_empty());
+        assert!(collect(merge_sorted_union(Vec::new(), &config)).is_empty());
+    }
+
+    // What `n`, the in-memory operation, gives for `texts`, one line a string
+    fn in_memory(n: fn(&[TextVec], &Config) -> TextVec, texts: &[&str]) -> Vec<String> {
+        let texts: Vec<TextVec> = texts.iter().map(|text| text.as_bytes().to_vec()).collect();
+        let result = String::from_utf8(n(&texts, &Config::default())).unwrap();
+        result.lines().map(String::from).collect()
+    }
+
+    const SORTED: [[&str; 3]; 4] = [
+        ["a\nb\nb\nc\nd\n", "b\nc\nc\ne\n", "a\nb\nc\nc\n"],
+        ["a\na\nb\n", "a\nb\nb\n", "a\n"],
+        ["a\nb\n", "", "b\nc\n"],
+        ["", "a\n", "a\nb\n"],
+    ];
+
+    #[test]
+    fn intersect_of_sorted_sources_matches_intersect_n() {
+        let config = Config::default();
+        for texts in &SORTED {
+            assert_eq!(collect(merge_sorted_intersect(sources(texts), &config)), in_memory(intersect_n, texts));
+        }
+        assert_eq!(collect(merge_sorted_intersect(sources(&SORTED[0]), &config)), ["b", "c"]);
+        assert_eq!(collect(merge_sorted_intersect(sources(&["a\na\nb\n"]), &config)), ["a", "b"]);
+    }
+
+    #[test]
+    fn difference_of_sorted_sources_matches_diff_n() {
+        let config = Config::default();
+        for texts in &SORTED {
+            assert_eq!(collect(merge_sorted_difference(sources(texts), &config)), in_memory(diff_n, texts));
+        }
+        assert_eq!(collect(merge_sorted_difference(sources(&SORTED[0]), &config)), ["d"]);
+        assert_eq!(collect(merge_sorted_difference(sources(&SORTED[2]), &config)), ["a"]);
+    }
+}
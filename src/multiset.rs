@@ -0,0 +1,139 @@
+use std::{io, iter};
+
+use indexmap::IndexMap;
+
+use crate::{Config, LineSet, SetExpression, SliceCounts, TextSlice, TextVec};
+
+// A multiset keeps a count for each line, and is written out with each line
+// repeated `count` times.
+//
+fn repeat_lines<'me>(counts: &'me IndexMap<TextVec, usize>) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+    Box::new(counts.iter().flat_map(|(line, &n)| iter::repeat_n(line.as_slice(), n)))
+}
+
+// The multiset union of `{a, a, b}` and `{a, b, b, c}` is `{a, a, b, b, c}`:
+// each line appears as many times as it does in the operand where it's most
+// frequent.
+//
+#[derive(Default)]
+pub struct MultisetUnionSet {
+    counts: IndexMap<TextVec, usize>,
+}
+
+impl MultisetUnionSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let mut set = Self::default();
+        set.operate(text, config);
+        set
+    }
+}
+
+impl<'data> SetExpression<'data> for MultisetUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        for (line, n) in SliceCounts::init_from_slice(text, config) {
+            match self.counts.get_mut(line) {
+                Some(count) => *count = (*count).max(n),
+                None => {
+                    self.counts.insert(line.to_vec(), n);
+                }
+            }
+        }
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        repeat_lines(&self.counts)
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.counts.retain(|line, _| keep(line));
+    }
+}
+
+// The multiset intersection keeps each line as many times as it appears in the
+// operand where it's least frequent, so a line that's in file A three times
+// and in file B twice is in the result twice.
+//
+#[derive(Default)]
+pub struct MultisetIntersectSet {
+    counts: IndexMap<TextVec, usize>,
+}
+
+impl MultisetIntersectSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let counts = SliceCounts::init_from_slice(text, config);
+        MultisetIntersectSet { counts: counts.into_iter().map(|(line, n)| (line.to_vec(), n)).collect() }
+    }
+}
+
+impl<'data> SetExpression<'data> for MultisetIntersectSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        let other = SliceCounts::init_from_slice(text, config);
+        self.counts.retain(|line, count| match other.get(line.as_slice()) {
+            Some(&n) => {
+                *count = (*count).min(n);
+                true
+            }
+            None => false,
+        });
+    }
+    fn is_settled(&self) -> bool {
+        self.counts.is_empty()
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.counts.retain(|_, count| *count > 0);
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        repeat_lines(&self.counts)
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.counts.retain(|line, _| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn union_keeps_each_line_as_often_as_its_most_frequent_operand() {
+        let config = Config::default();
+        let mut set = MultisetUnionSet::init(b"a\na\nb\n", &config);
+        set.operate(b"a\nb\nb\nc\n", &config);
+        let expected: [&[u8]; 5] = [b"a", b"a", b"b", b"b", b"c"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn union_doesnt_add_up_counts() {
+        let config = Config::default();
+        let mut set = MultisetUnionSet::init(b"x\nx\n", &config);
+        set.operate(b"x\nx\n", &config);
+        set.operate(b"x\n", &config);
+        let expected: [&[u8]; 2] = [b"x", b"x"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn intersection_keeps_each_line_as_often_as_its_least_frequent_operand() {
+        let config = Config::default();
+        let mut set = MultisetIntersectSet::init(b"a\na\na\nb\nc\n", &config);
+        set.operate(b"b\na\na\n", &config);
+        set.finish().unwrap();
+        let expected: [&[u8]; 3] = [b"a", b"a", b"b"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn intersection_settles_once_no_line_is_left() {
+        let config = Config::default();
+        let mut set = MultisetIntersectSet::init(b"a\nb\n", &config);
+        set.operate(b"a\n", &config);
+        assert!(!set.is_settled());
+        set.operate(b"c\n", &config);
+        assert!(set.is_settled());
+        assert!(lines(&set).is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    cmp::Ordering,
+    io::{self, Write},
+    str,
+};
+
+#[cfg(feature = "sampling")]
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[cfg(feature = "sampling")]
+use crate::SampleSize;
+use crate::{output_lines, Config, NonNumeric, OutputFormat, SetExpression, TextSlice};
+
+// A random `size` of the result lines of `set`, picked in one pass by
+// reservoir sampling, as `SetExpression::sample` picks them, so only the
+// sample is kept. The sample is put back in result order.
+#[cfg(feature = "sampling")]
+pub(crate) fn sample_lines<'me, 'data>(set: &'me impl SetExpression<'data>, size: SampleSize, seed: u64) -> Vec<&'me TextSlice> {
+    let n = sample_len(set, size);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut reservoir = Vec::new();
+    for (i, line) in set.result_lines().enumerate() {
+        if i < n {
+            reservoir.push((i, line));
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < n {
+                reservoir[j] = (i, line);
+            }
+        }
+    }
+    reservoir.sort_unstable_by_key(|&(i, _)| i);
+    reservoir.into_iter().map(|(_, line)| line).collect()
+}
+
+// How many lines `sample_lines` picks
+#[cfg(feature = "sampling")]
+pub(crate) fn sample_len<'data>(set: &impl SetExpression<'data>, size: SampleSize) -> usize {
+    let n = match size {
+        SampleSize::Lines(n) => n,
+        SampleSize::Fraction(fraction) => (set.result_len() as f64 * fraction).round() as usize,
+    };
+    n.min(set.result_len())
+}
+
+// What the default `SetExpression::write_to` does
+pub(crate) fn write_lines<'data>(set: &impl SetExpression<'data>, out: &mut impl Write, config: &Config) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Raw => write_raw(output_lines(set, config), out, config),
+        OutputFormat::Json => write_json(set, out, config),
+        OutputFormat::Ndjson => write_ndjson(set, out, config),
+        OutputFormat::Summary => write_summary(set, out, config),
+    }
+}
+
+// Write `lines` in the raw format, each followed by the delimiter
+pub(crate) fn write_raw<L: AsRef<TextSlice>>(lines: impl Iterator<Item = L>, out: &mut impl Write, config: &Config) -> io::Result<()> {
+    let mut lines = lines.peekable();
+    while let Some(line) = lines.next() {
+        out.write_all(line.as_ref())?;
+        let last = lines.peek().is_none();
+        if config.record_size.is_none() && !(last && config.no_final_delimiter) {
+            out.write_all(&[config.delimiter])?;
+        }
+    }
+    Ok(())
+}
+
+// Write the lines of `set` as a JSON array, one string per line. We check
+// that every line is UTF-8 before writing anything, so that a rejected result
+// doesn't leave half an array on the output.
+pub(crate) fn write_json<'data>(set: &impl SetExpression<'data>, out: &mut impl Write, config: &Config) -> io::Result<()> {
+    if let Some(n) = output_lines(set, config).position(|line| str::from_utf8(line).is_err()) {
+        let message = format!("result line {} is not UTF-8, so can't be written as JSON", n + 1);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    out.write_all(b"[")?;
+    for (n, line) in output_lines(set, config).enumerate() {
+        if n > 0 {
+            out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut *out, &String::from_utf8_lossy(line))?;
+    }
+    out.write_all(b"]\n")
+}
+
+// Write each line of `set` as a JSON string followed by a newline. Where a
+// line isn't UTF-8 each stray byte is written as the `\u00XX` escape of the
+// character with that code, so every line can be written, though such a
+// line doesn't read back as the same bytes.
+pub(crate) fn write_ndjson<'data>(set: &impl SetExpression<'data>, out: &mut impl Write, config: &Config) -> io::Result<()> {
+    for line in output_lines(set, config) {
+        out.write_all(b"\"")?;
+        for chunk in line.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    '"' => out.write_all(b"\\\"")?,
+                    '\\' => out.write_all(b"\\\\")?,
+                    '\n' => out.write_all(b"\\n")?,
+                    '\r' => out.write_all(b"\\r")?,
+                    '\t' => out.write_all(b"\\t")?,
+                    c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+                    c => out.write_all(c.encode_utf8(&mut [0; 4]).as_bytes())?,
+                }
+            }
+            for byte in chunk.invalid() {
+                write!(out, "\\u{:04x}", byte)?;
+            }
+        }
+        out.write_all(b"\"\n")?;
+    }
+    Ok(())
+}
+
+// The fields are few and fixed, so scripts can rely on them
+pub(crate) fn write_summary<'data>(set: &impl SetExpression<'data>, out: &mut impl Write, config: &Config) -> io::Result<()> {
+    let (mut lines, mut bytes, mut min, mut max) = (0, 0, usize::MAX, 0);
+    for line in output_lines(set, config) {
+        lines += 1;
+        bytes += line.len();
+        min = min.min(line.len());
+        max = max.max(line.len());
+    }
+    if lines == 0 {
+        min = 0;
+    }
+    let mean = if lines == 0 { 0.0 } else { bytes as f64 / lines as f64 };
+    write!(out, "lines\t{}\nbytes\t{}\nmin_length\t{}\nmax_length\t{}\nmean_length\t{:.2}\n", lines, bytes, min, max, mean)
+}
+
+// The number at the start of `line`, after any spaces and tabs: an optional
+// sign, digits, and optionally a decimal point and more digits. Only ASCII
+// digits and `.` count, whatever the locale.
+fn leading_number(line: &TextSlice) -> Option<f64> {
+    let digits_from = |from: usize| from + line[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let start = line.iter().position(|b| !b" \t".contains(b)).unwrap_or(line.len());
+    let mut end = start;
+    if let Some(b'-') | Some(b'+') = line.get(end) {
+        end += 1;
+    }
+    let whole_end = digits_from(end);
+    let mut has_digits = whole_end > end;
+    end = whole_end;
+    if line.get(end) == Some(&b'.') {
+        let fraction_end = digits_from(end + 1);
+        if fraction_end > end + 1 {
+            has_digits = true;
+            end = fraction_end;
+        }
+    }
+    if !has_digits {
+        return None;
+    }
+    str::from_utf8(&line[start..end]).ok()?.parse().ok()
+}
+
+// Sort `lines` by the numbers they start with, keeping the order of lines
+// with equal numbers. A line that is just a number sorts the same as one that
+// starts with that number and goes on.
+pub(crate) fn sort_numerically(lines: &mut [&TextSlice], non_numeric: NonNumeric) {
+    lines.sort_by(|a, b| match (leading_number(a), leading_number(b), non_numeric) {
+        (Some(x), Some(y), _) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        (None, None, _) => Ordering::Equal,
+        (None, Some(_), NonNumeric::First) | (Some(_), None, NonNumeric::Last) => Ordering::Less,
+        (None, Some(_), NonNumeric::Last) | (Some(_), None, NonNumeric::First) => Ordering::Greater,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{leading_number, sort_numerically};
+    use crate::{NonNumeric, SetExpression, TextSlice, UnionSet, UnionSetExt};
+
+    fn ndjson(set: &UnionSet) -> String {
+        let mut out = Vec::new();
+        set.write_to_ndjson(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn each_ndjson_line_parses_back_to_its_line() {
+        let lines = ["plain", "", "quote \" and \\ backslash", "tab\tcr\r", "bell\x07 nul\0", "café ✓"];
+        let set = UnionSet::from_lines(lines);
+        let out = ndjson(&set);
+        let parsed: Vec<String> = out.lines().map(|json| serde_json::from_str(json).unwrap()).collect();
+        assert_eq!(parsed, lines);
+    }
+
+    #[test]
+    fn bytes_that_arent_utf8_are_escaped_by_their_value() {
+        let set = UnionSet::from_lines([&b"a\xffb"[..]]);
+        assert_eq!(ndjson(&set), "\"a\\u00ffb\"\n");
+        let parsed: String = serde_json::from_str(ndjson(&set).trim_end()).unwrap();
+        assert_eq!(parsed, "a\u{ff}b");
+    }
+
+    fn sorted(lines: &[&'static str], non_numeric: NonNumeric) -> Vec<&'static str> {
+        let mut lines: Vec<&TextSlice> = lines.iter().map(|line| line.as_bytes()).collect();
+        sort_numerically(&mut lines, non_numeric);
+        lines.iter().map(|line| std::str::from_utf8(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn leading_numbers_may_be_signed_prefixes_or_fractions() {
+        assert_eq!(leading_number(b"42"), Some(42.0));
+        assert_eq!(leading_number(b"-7"), Some(-7.0));
+        assert_eq!(leading_number(b"+3 apples"), Some(3.0));
+        assert_eq!(leading_number(b"  2.5kg"), Some(2.5));
+        assert_eq!(leading_number(b".5"), Some(0.5));
+        assert_eq!(leading_number(b"12."), Some(12.0));
+        assert_eq!(leading_number(b"-"), None);
+        assert_eq!(leading_number(b"x1"), None);
+        assert_eq!(leading_number(b""), None);
+    }
+
+    #[test]
+    fn negative_numbers_sort_before_positive_ones() {
+        let lines = ["10", "-2", "2", "-10", "0"];
+        assert_eq!(sorted(&lines, NonNumeric::First), ["-10", "-2", "0", "2", "10"]);
+    }
+
+    #[test]
+    fn a_numeric_prefix_sorts_like_the_bare_number_and_keeps_its_place() {
+        let lines = ["3 c", "10", "3", "2 b", "3 a"];
+        assert_eq!(sorted(&lines, NonNumeric::First), ["2 b", "3 c", "3", "3 a", "10"]);
+    }
+
+    #[test]
+    fn lines_without_a_number_go_first_or_last_in_their_order() {
+        let lines = ["b", "2", "a", "1"];
+        assert_eq!(sorted(&lines, NonNumeric::First), ["b", "a", "1", "2"]);
+        assert_eq!(sorted(&lines, NonNumeric::Last), ["1", "2", "b", "a"]);
+    }
+
+    #[cfg(feature = "sampling")]
+    #[test]
+    fn the_same_seed_picks_the_same_sample_in_result_order() {
+        use super::sample_lines;
+        use crate::SampleSize;
+        let set = UnionSet::from_lines((0..200).map(|n| n.to_string()));
+        let sample = sample_lines(&set, SampleSize::Lines(20), 42);
+        assert_eq!(sample.len(), 20);
+        assert_eq!(sample, sample_lines(&set, SampleSize::Lines(20), 42));
+        assert_ne!(sample, sample_lines(&set, SampleSize::Lines(20), 43));
+        let positions: Vec<usize> = sample.iter().map(|line| set.get_full(*line).unwrap().0).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[cfg(feature = "sampling")]
+    #[test]
+    fn a_fraction_samples_that_share_of_the_lines() {
+        use super::{sample_len, sample_lines};
+        use crate::SampleSize;
+        let set = UnionSet::from_lines((0..200).map(|n| n.to_string()));
+        assert_eq!(sample_lines(&set, SampleSize::Fraction(0.1), 1).len(), 20);
+        assert_eq!(sample_len(&set, SampleSize::Fraction(0.0)), 0);
+        assert_eq!(sample_len(&set, SampleSize::Fraction(1.0)), 200);
+        assert_eq!(sample_len(&set, SampleSize::Lines(500)), 200);
+    }
+}
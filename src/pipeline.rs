@@ -0,0 +1,92 @@
+use crate::{Config, LineSet, SetExpression, SliceSet, TextVec, UnionSet, UnionSetExt};
+
+#[derive(Clone, Copy)]
+enum Step {
+    Union,
+    Intersect,
+    Diff,
+}
+
+// A `Pipeline` chains set operations left to right, as in
+// `Pipeline::new(a, &config).union(b).intersect(c).execute()` for (A ∪ B) ∩ C.
+// Every step works on the same `UnionSet`: a union inserts the operand's
+// lines, and an intersection or difference just filters the lines already
+// there, so no intermediate result is ever written out and reparsed.
+//
+pub struct Pipeline {
+    first: TextVec,
+    steps: Vec<(Step, TextVec)>,
+    config: Config,
+}
+
+impl Pipeline {
+    pub fn new(text: TextVec, config: &Config) -> Self {
+        Pipeline { first: text, steps: Vec::new(), config: config.clone() }
+    }
+
+    pub fn union(mut self, text: TextVec) -> Self {
+        self.steps.push((Step::Union, text));
+        self
+    }
+
+    pub fn intersect(mut self, text: TextVec) -> Self {
+        self.steps.push((Step::Intersect, text));
+        self
+    }
+
+    pub fn diff(mut self, text: TextVec) -> Self {
+        self.steps.push((Step::Diff, text));
+        self
+    }
+
+    pub fn execute(self) -> UnionSet {
+        let config = &self.config;
+        let mut set = UnionSet::init(self.first, config);
+        for (step, text) in &self.steps {
+            match step {
+                Step::Union => set.operate(text, config),
+                Step::Intersect => {
+                    let other = SliceSet::init_from_slice(text, config);
+                    set.retain(|line| other.contains(line.as_slice()));
+                }
+                Step::Diff => {
+                    let other = SliceSet::init_from_slice(text, config);
+                    set.retain(|line| !other.contains(line.as_slice()));
+                }
+            }
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(pipeline: Pipeline) -> Vec<TextVec> {
+        pipeline.execute().result_lines().map(|line| line.to_vec()).collect()
+    }
+
+    fn lines(lines: &[&str]) -> Vec<TextVec> {
+        lines.iter().map(|line| line.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn steps_apply_left_to_right() {
+        let config = Config::default();
+        let union_then_intersect = Pipeline::new(b"a\nb\n".to_vec(), &config)
+            .union(b"c\nd\n".to_vec())
+            .intersect(b"d\nb\nx\n".to_vec());
+        assert_eq!(run(union_then_intersect), lines(&["b", "d"]));
+        let diff_then_union = Pipeline::new(b"a\nb\nc\n".to_vec(), &config)
+            .diff(b"b\n".to_vec())
+            .union(b"b\ne\n".to_vec());
+        assert_eq!(run(diff_then_union), lines(&["a", "c", "b", "e"]));
+    }
+
+    #[test]
+    fn a_pipeline_without_steps_is_the_first_operand_once() {
+        let config = Config::default();
+        assert_eq!(run(Pipeline::new(b"a\na\nb".to_vec(), &config)), lines(&["a", "b"]));
+    }
+}
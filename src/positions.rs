@@ -0,0 +1,85 @@
+use std::io::{self, Write};
+
+use indexmap::IndexMap;
+
+use crate::{Config, LineSet, TextSlice, TextVec};
+
+// Where each line of one operand is: the operand's index and the line's
+// number in it, from 1, for every time it occurs
+struct Positions<'a> {
+    file: usize,
+    lines: usize,
+    positions: IndexMap<&'a TextSlice, Vec<(usize, usize)>>,
+}
+
+impl<'a> LineSet<'a> for Positions<'a> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        self.lines += 1;
+        self.positions.entry(line).or_insert_with(Vec::new).push((self.file, self.lines));
+    }
+}
+
+// The intersection of `texts`, in the first operand's order, with every place
+// each line occurs: `(operand index, line number)` pairs, both operands and
+// lines in order.
+pub fn intersect_with_positions(texts: &[TextVec], config: &Config) -> Vec<(TextVec, Vec<(usize, usize)>)> {
+    let mut found = IndexMap::new();
+    for (file, text) in texts.iter().enumerate() {
+        let mut other = Positions { file, lines: 0, positions: IndexMap::new() };
+        other.insert_all_lines(text, config);
+        if file == 0 {
+            found = other.positions;
+            continue;
+        }
+        found.retain(|line, positions| match other.positions.get(line) {
+            Some(more) => {
+                positions.extend_from_slice(more);
+                true
+            }
+            None => false,
+        });
+    }
+    found.into_iter().map(|(line, positions)| (line.to_vec(), positions)).collect()
+}
+
+// Write one `line:operand:number` line for every place a line occurs
+pub fn write_positions(found: &[(TextVec, Vec<(usize, usize)>)], out: &mut impl Write, config: &Config) -> io::Result<()> {
+    for (line, positions) in found {
+        for (file, number) in positions {
+            out.write_all(line)?;
+            write!(out, ":{}:{}", file, number)?;
+            out.write_all(&[config.delimiter])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(texts: &[&str]) -> Vec<TextVec> {
+        texts.iter().map(|text| text.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn every_place_a_repeated_line_occurs_is_kept() {
+        let found = intersect_with_positions(&texts(&["a\nb\na\n", "c\na\n", "a\na\n"]), &Config::default());
+        assert_eq!(found, [(b"a".to_vec(), vec![(0, 1), (0, 3), (1, 2), (2, 1), (2, 2)])]);
+    }
+
+    #[test]
+    fn a_line_missing_from_a_later_operand_is_dropped() {
+        let found = intersect_with_positions(&texts(&["b\na\nc\n", "a\nb\nc\n", "c\nb\n"]), &Config::default());
+        let expected = [(b"b".to_vec(), vec![(0, 1), (1, 2), (2, 2)]), (b"c".to_vec(), vec![(0, 3), (1, 3), (2, 1)])];
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn each_place_is_written_as_line_operand_number() {
+        let found = intersect_with_positions(&texts(&["x\ny\nx\n", "x\n"]), &Config::default());
+        let mut out = Vec::new();
+        write_positions(&found, &mut out, &Config::default()).unwrap();
+        assert_eq!(out, b"x:0:1\nx:0:3\nx:1:1\n");
+    }
+}
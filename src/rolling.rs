@@ -0,0 +1,118 @@
+use std::io::{self, Write};
+
+use indexmap::IndexMap;
+
+use crate::{finish_and_filter, output_len, Config, LineSet, OutputFormat, SetExpression, SliceSet, TextSlice, TextVec};
+
+// Call `visit` with the union of each run of `window_size` consecutive texts,
+// in order, like `slice::windows`: none if there are fewer texts than that.
+// Rather than build each union afresh, we count how many texts in the window
+// have each line, and when a text slides out of the window drop the lines
+// whose count falls to zero. A line keeps its place while it stays in the
+// window, so each union is in the order its lines last came into the window.
+fn for_each_window<'data>(
+    texts: &'data [TextVec],
+    window_size: usize,
+    config: &Config,
+    mut visit: impl FnMut(SliceSet<'data>) -> io::Result<()>,
+) -> io::Result<()> {
+    assert!(window_size > 0, "window size must be at least 1");
+    let mut counts: IndexMap<&'data TextSlice, usize> = IndexMap::new();
+    for (n, text) in texts.iter().enumerate() {
+        for line in SliceSet::init_from_slice(text, config) {
+            *counts.entry(line).or_insert(0) += 1;
+        }
+        if n + 1 < window_size {
+            continue;
+        }
+        visit(counts.keys().cloned().collect())?;
+        // Hash the oldest text's lines again rather than keep a set for every
+        // text in the window
+        for line in SliceSet::init_from_slice(&texts[n + 1 - window_size], config) {
+            if let Some(count) = counts.get_mut(line) {
+                *count -= 1;
+            }
+        }
+        counts.retain(|_, count| *count > 0);
+    }
+    Ok(())
+}
+
+// The union of each run of `window_size` consecutive texts, filtered as
+// `config` says and written in its raw format, whatever `config.format` is.
+// Panics if `window_size` is 0.
+pub fn rolling_union(texts: &[TextVec], window_size: usize, config: &Config) -> Vec<TextVec> {
+    let raw = Config { format: OutputFormat::Raw, ..config.clone() };
+    let mut windows = Vec::new();
+    for_each_window(texts, window_size, config, |mut set| {
+        finish_and_filter(&mut set, &raw)?;
+        let mut out = TextVec::new();
+        set.write_to(&mut out, &raw)?;
+        windows.push(out);
+        Ok(())
+    })
+    .expect("raw output to a Vec can't fail");
+    windows
+}
+
+// Write the union of each window to `out` in `config.format`, with a blank
+// line between windows, and return whether any of them has a line
+pub fn write_rolling_union(texts: &[TextVec], window_size: usize, out: &mut impl Write, config: &Config) -> io::Result<bool> {
+    let mut first = true;
+    let mut non_empty = false;
+    for_each_window(texts, window_size, config, |mut set| {
+        finish_and_filter(&mut set, config)?;
+        non_empty |= output_len(&set, config) > 0;
+        if !config.quiet {
+            if !first {
+                out.write_all(&[config.delimiter])?;
+            }
+            set.write_to(out, config)?;
+        }
+        first = false;
+        Ok(())
+    })?;
+    Ok(non_empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(texts: &[&str]) -> Vec<TextVec> {
+        texts.iter().map(|text| text.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn each_window_is_the_union_of_its_texts() {
+        let config = Config::default();
+        let days = texts(&["a\nb\n", "b\nc\n", "d\n", "a\nd\n"]);
+        let windows = rolling_union(&days, 2, &config);
+        assert_eq!(windows, texts(&["a\nb\nc\n", "b\nc\nd\n", "d\na\n"]));
+        assert_eq!(rolling_union(&days, 1, &config), texts(&["a\nb\n", "b\nc\n", "d\n", "a\nd\n"]));
+        assert_eq!(rolling_union(&days, 4, &config), texts(&["a\nb\nc\nd\n"]));
+        assert!(rolling_union(&days, 5, &config).is_empty());
+    }
+
+    #[test]
+    fn a_line_leaves_only_when_no_text_in_the_window_has_it() {
+        let config = Config::default();
+        let days = texts(&["x\n", "x\ny\n", "y\n", "z\n"]);
+        assert_eq!(rolling_union(&days, 2, &config), texts(&["x\ny\n", "x\ny\n", "y\nz\n"]));
+    }
+
+    #[test]
+    fn written_windows_are_separated_by_a_blank_line() {
+        let config = Config::default();
+        let mut out = Vec::new();
+        let non_empty = write_rolling_union(&texts(&["1\n", "2\n", "3\n"]), 2, &mut out, &config).unwrap();
+        assert!(non_empty);
+        assert_eq!(out, b"1\n2\n\n2\n3\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "window size must be at least 1")]
+    fn a_window_of_zero_texts_panics() {
+        rolling_union(&texts(&["a\n"]), 0, &Config::default());
+    }
+}
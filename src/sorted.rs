@@ -0,0 +1,56 @@
+use std::collections::BTreeSet;
+
+use crate::{Config, LineSet, SetExpression, TextSlice, TextVec};
+
+// A `SortedUnionSet` is a union kept in a `BTreeSet`, so its lines come out
+// sorted bytewise without a separate sort. Each insert costs O(log n) rather
+// than the `UnionSet` hash's O(1), and `benches/sorted_union.rs` finds that
+// for 100,000 lines a `UnionSet` sorted afterwards is still two or three
+// times faster; what this saves is the sorted copy of the lines.
+//
+#[derive(Default)]
+pub struct SortedUnionSet(BTreeSet<TextVec>);
+
+impl SortedUnionSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        Self::init_from_slice(text, config)
+    }
+}
+
+impl<'a> LineSet<'a> for SortedUnionSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        if !self.0.contains(line) {
+            self.0.insert(line.to_vec());
+        }
+    }
+}
+
+impl<'data> SetExpression<'data> for SortedUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.iter().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.0.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.retain(|line| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_come_out_sorted_and_distinct() {
+        let config = Config::default();
+        let mut set = SortedUnionSet::init(b"pear\napple\npear\n", &config);
+        set.operate(b"fig\napple\nBanana\n", &config);
+        let lines: Vec<&TextSlice> = set.result_lines().collect();
+        assert_eq!(lines, [&b"Banana"[..], b"apple", b"fig", b"pear"]);
+        assert_eq!(set.result_len(), 4);
+    }
+}
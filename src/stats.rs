@@ -0,0 +1,91 @@
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+    slice::Iter,
+    time::Instant,
+};
+
+use crate::{count_lines, write_calculation_len, Config, LineSet, OpName, SliceSet, TextVec};
+
+// What a calculation took in and gave out. Lines are counted as the sets
+// count them, after normalizing, but in every shard, and `bytes_processed` is
+// the operands' total length. Every operand is counted, even those a settled
+// set never needed to read; `elapsed_ms` is just the calculation, not the
+// counting.
+//
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpStats {
+    pub input_line_counts: Vec<usize>,
+    pub input_unique_counts: Vec<usize>,
+    pub result_line_count: usize,
+    pub bytes_processed: usize,
+    pub elapsed_ms: u64,
+}
+
+impl OpStats {
+    // One field to a line, its name and value separated by a tab, with the
+    // counts for each operand separated by spaces
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let list = |counts: &[usize]| counts.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(" ");
+        writeln!(out, "input_line_counts\t{}", list(&self.input_line_counts))?;
+        writeln!(out, "input_unique_counts\t{}", list(&self.input_unique_counts))?;
+        writeln!(out, "result_line_count\t{}", self.result_line_count)?;
+        writeln!(out, "bytes_processed\t{}", self.bytes_processed)?;
+        writeln!(out, "elapsed_ms\t{}", self.elapsed_ms)
+    }
+}
+
+// Like `write_calculation`, but returns the calculation's `OpStats`
+pub fn write_calculation_with_stats(
+    op: OpName,
+    texts: Iter<TextVec>,
+    out: &mut impl Write,
+    config: &Config,
+) -> io::Result<OpStats> {
+    let start = Instant::now();
+    let result_line_count = write_calculation_len(op, texts.clone(), out, config)?;
+    let elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+    let texts = texts.as_slice();
+    let unsharded = Config { shard: None, ..config.clone() };
+    Ok(OpStats {
+        input_line_counts: texts.iter().map(|text| count_lines(text, config)).collect(),
+        input_unique_counts: texts.iter().map(|text| SliceSet::init_from_slice(text, &unsharded).len()).collect(),
+        result_line_count,
+        bytes_processed: texts.iter().map(|text| text.len()).sum(),
+        elapsed_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_count_each_operand_and_the_result() {
+        let texts = [b"a\nb\na\nc\n".to_vec(), b"b\nc\nc\n".to_vec()];
+        let mut out = Vec::new();
+        let stats = write_calculation_with_stats(OpName::Intersect, texts.iter(), &mut out, &Config::default()).unwrap();
+        assert_eq!(out, b"b\nc\n");
+        assert_eq!(stats.input_line_counts, [4, 3]);
+        assert_eq!(stats.input_unique_counts, [3, 2]);
+        assert_eq!(stats.result_line_count, 2);
+        assert_eq!(stats.bytes_processed, 14);
+    }
+
+    #[test]
+    fn stats_are_written_a_field_to_a_line() {
+        let stats = OpStats {
+            input_line_counts: vec![4, 3],
+            input_unique_counts: vec![3, 2],
+            result_line_count: 2,
+            bytes_processed: 14,
+            elapsed_ms: 5,
+        };
+        let mut out = Vec::new();
+        stats.write_to(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "input_line_counts\t4 3\ninput_unique_counts\t3 2\nresult_line_count\t2\nbytes_processed\t14\nelapsed_ms\t5\n"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use crate::{Config, LineSet, TextSlice, TextVec};
+
+// A `StreamingUnion` writes each line of a union to `out` the moment it's
+// first seen, instead of keeping the result to write at the end, so output
+// starts with the first operand and a reader like `head` needn't wait for the
+// rest. It still has to remember every distinct line to leave out repeats,
+// but in no particular order. The lines come out in first-seen order, raw,
+// each followed by the delimiter, or with `no_final_delimiter` each after
+// the first preceded by it; the length filters and `max_lines` apply as
+// they're written, and with `quiet` nothing is.
+//
+// `insert_line` can't return an error, so a failed write is kept for
+// `operate` to return, and nothing more is written after it.
+//
+pub struct StreamingUnion<W: Write> {
+    seen: HashSet<TextVec>,
+    out: W,
+    config: Config,
+    written: usize,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> StreamingUnion<W> {
+    pub fn new(out: W, config: &Config) -> Self {
+        StreamingUnion { seen: HashSet::new(), out, config: config.clone(), written: 0, error: None }
+    }
+
+    pub fn operate(&mut self, text: &TextSlice, config: &Config) -> io::Result<()> {
+        self.insert_all_lines(text, config);
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    // Whether the lines written so far are all there will be: `max_lines`
+    // of them, or with `quiet`, the first
+    pub fn is_settled(&self) -> bool {
+        let limit = if self.config.quiet { Some(1) } else { self.config.max_lines };
+        limit.is_some_and(|limit| self.written >= limit)
+    }
+
+    // Write the empty marker if nothing else was written, and return how
+    // many lines the union had, up to any limit
+    pub fn finish(mut self) -> io::Result<usize> {
+        if let (0, Some(marker), false) = (self.written, &self.config.empty_marker, self.config.quiet) {
+            self.out.write_all(marker)?;
+            if !self.config.no_final_delimiter {
+                self.out.write_all(&[self.config.delimiter])?;
+            }
+        }
+        self.out.flush()?;
+        Ok(self.written)
+    }
+
+    fn wanted(&self, line: &TextSlice) -> bool {
+        let min = self.config.min_length.unwrap_or(0);
+        let max = self.config.max_length.unwrap_or(usize::MAX);
+        (min..=max).contains(&line.len()) && !self.is_settled()
+    }
+}
+
+impl<'a, W: Write> LineSet<'a> for StreamingUnion<W> {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        if self.error.is_some() || self.seen.contains(line) {
+            return;
+        }
+        self.seen.insert(line.to_vec());
+        if !self.wanted(line) {
+            return;
+        }
+        self.written += 1;
+        if self.config.quiet {
+            return;
+        }
+        let delimiter = [self.config.delimiter];
+        let result = if !self.config.no_final_delimiter {
+            self.out.write_all(line).and_then(|_| self.out.write_all(&delimiter))
+        } else if self.written > 1 {
+            self.out.write_all(&delimiter).and_then(|_| self.out.write_all(line))
+        } else {
+            self.out.write_all(line)
+        };
+        if let Err(err) = result {
+            self.error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::union_n;
+
+    #[test]
+    fn the_streamed_union_is_union_n() {
+        let operand = |seed: usize, len: usize| -> TextVec {
+            (0..len).flat_map(|i| vec![b'a' + ((seed * 3 + i * i) % 11) as u8, b'\n']).collect()
+        };
+        let config = Config::default();
+        for seed in 0..20 {
+            let texts = [operand(seed, 7), operand(seed + 1, seed % 4), operand(seed + 2, 9)];
+            let mut union = StreamingUnion::new(Vec::new(), &config);
+            for text in &texts {
+                union.operate(text, &config).unwrap();
+            }
+            let expected = union_n(&texts, &config);
+            assert_eq!(union.out, expected, "seed {}", seed);
+            assert_eq!(union.finish().unwrap(), expected.iter().filter(|&&b| b == b'\n').count());
+        }
+    }
+
+    #[test]
+    fn each_operands_new_lines_are_written_as_it_is_read() {
+        let config = Config::default();
+        let mut union = StreamingUnion::new(Vec::new(), &config);
+        union.operate(b"b\na\nb\n", &config).unwrap();
+        assert_eq!(union.out, b"b\na\n");
+        union.operate(b"a\nc\n", &config).unwrap();
+        assert_eq!(union.out, b"b\na\nc\n");
+    }
+
+    #[test]
+    fn max_lines_settles_the_union() {
+        let config = Config { max_lines: Some(2), ..Config::default() };
+        let mut union = StreamingUnion::new(Vec::new(), &config);
+        union.operate(b"a\n", &config).unwrap();
+        assert!(!union.is_settled());
+        union.operate(b"a\nb\nc\n", &config).unwrap();
+        assert!(union.is_settled());
+        assert_eq!(union.out, b"a\nb\n");
+    }
+}
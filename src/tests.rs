@@ -0,0 +1,798 @@
+// Tests of the sets and functions defined in lib.rs. Modules with sets of
+// their own test them at their end.
+
+use super::*;
+
+fn config() -> Config {
+    Config::default()
+}
+
+fn texts(texts: &[&str]) -> Vec<TextVec> {
+    texts.iter().map(|text| text.as_bytes().to_vec()).collect()
+}
+
+#[test]
+fn trailing_whitespace_is_ignored_only_when_asked() {
+    let operands = texts(&["hello   \n", "hello\n"]);
+    assert_eq!(intersect_n(&operands, &config()), b"");
+    assert_eq!(union_n(&operands, &config()), b"hello   \nhello\n");
+    let trimming = Config { ignore_trailing_whitespace: true, ..config() };
+    assert_eq!(intersect_n(&operands, &trimming), b"hello\n");
+    assert_eq!(union_n(&operands, &trimming), b"hello\n");
+}
+
+#[test]
+fn ignoring_trailing_whitespace_trims_spaces_tabs_and_carriage_returns_only() {
+    let trimming = Config { ignore_trailing_whitespace: true, ..config() };
+    assert_eq!(trimming.normalize_line(b"a b \t\r \t"), b"a b");
+    assert_eq!(trimming.normalize_line(b"  a"), b"  a");
+    assert_eq!(trimming.normalize_line(b"a\x0b"), b"a\x0b");
+}
+
+#[test]
+fn an_intersection_is_settled_once_it_is_empty() {
+    let config = config();
+    let first = b"1\n2\n".to_vec();
+    let mut set = IntersectSet::init(&first, &config);
+    set.operate(b"2\n3\n", &config);
+    assert!(!set.is_settled());
+    set.operate(b"3\n", &config);
+    assert!(set.is_settled());
+}
+
+fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+    set.result_lines().collect()
+}
+
+#[test]
+fn retaining_by_length_compares_lines_without_their_delimiter() {
+    let config = config();
+    let mixed = UnionSet::init(b"a\nbbb\ncc\n\ndddd\n".to_vec(), &config);
+    let mut shorter = mixed.clone();
+    shorter.retain_shorter_than(3);
+    let expected: [&[u8]; 3] = [b"a", b"cc", b""];
+    assert_eq!(lines(&shorter), expected);
+    let mut longer = mixed.clone();
+    longer.retain_longer_than(2);
+    let expected: [&[u8]; 2] = [b"bbb", b"dddd"];
+    assert_eq!(lines(&longer), expected);
+}
+
+#[test]
+fn retaining_by_length_can_keep_nothing() {
+    let config = config();
+    let mixed = UnionSet::init(b"a\nbbb\n\n".to_vec(), &config);
+    let mut shorter = mixed.clone();
+    shorter.retain_shorter_than(0);
+    assert!(lines(&shorter).is_empty());
+    let mut longer = mixed;
+    longer.retain_longer_than(usize::MAX);
+    assert!(lines(&longer).is_empty());
+}
+
+#[test]
+fn min_and_max_length_are_inclusive() {
+    let config = Config { min_length: Some(2), max_length: Some(3), ..config() };
+    let mut set = UnionSet::init(b"a\nbb\nccc\ndddd\n".to_vec(), &config);
+    finish_and_filter(&mut set, &config).unwrap();
+    let expected: [&[u8]; 2] = [b"bb", b"ccc"];
+    assert_eq!(lines(&set), expected);
+}
+
+#[cfg(feature = "sampling")]
+#[test]
+fn a_sample_has_min_n_and_result_size_lines_all_from_the_result() {
+    use rand::{rngs::StdRng, SeedableRng};
+    let config = config();
+    let text: TextVec = (0..100).flat_map(|i| format!("{}\n", i).into_bytes()).collect();
+    let set = UnionSet::init(text, &config);
+    let mut rng = StdRng::seed_from_u64(7);
+    for n in [0, 1, 10, 100, 150] {
+        let sample = set.sample(n, &mut rng);
+        assert_eq!(sample.len(), n.min(100));
+        assert!(sample.iter().all(|line| set.contains(line)));
+        let distinct: std::collections::HashSet<_> = sample.iter().collect();
+        assert_eq!(distinct.len(), sample.len());
+    }
+}
+
+#[test]
+fn from_lines_takes_strings_vecs_and_slices_as_they_are() {
+    let from_strs = UnionSet::from_lines(["b", "a", "b", "a\nc"]);
+    assert_eq!(lines(&from_strs), [&b"b"[..], b"a", b"a\nc"]);
+    let from_strings = UnionSet::from_lines((1..=3).map(|i| format!("line {}", i)));
+    assert_eq!(lines(&from_strings), [&b"line 1"[..], b"line 2", b"line 3"]);
+    let from_vecs = UnionSet::from_lines(vec![b"x".to_vec(), b"x".to_vec()]);
+    assert_eq!(lines(&from_vecs), [&b"x"[..]]);
+    let from_slices = UnionSet::from_lines([&b"\xff"[..], b""]);
+    assert_eq!(lines(&from_slices), [&b"\xff"[..], b""]);
+    let collected: UnionSet = ["p", "q"].iter().map(|line| line.as_bytes().to_vec()).collect();
+    assert_eq!(collected, UnionSet::from_lines(["p", "q"]));
+}
+
+#[test]
+fn to_bytes_writes_the_deduplicated_lines() {
+    let set = UnionSet::init(b"b\na\nb\na\nc".to_vec(), &config());
+    assert_eq!(set.to_bytes(), b"b\na\nc\n");
+    assert_eq!(UnionSet::init(set.to_bytes(), &config()), set);
+}
+
+#[test]
+fn to_string_lossy_replaces_bytes_that_arent_utf8() {
+    let set = UnionSet::from_lines([&b"caf\xc3\xa9"[..], b"x\xffy"]);
+    assert_eq!(set.to_string_lossy(), "café\nx\u{fffd}y\n");
+}
+
+#[test]
+fn to_utf8_string_fails_on_the_first_line_that_isnt_utf8() {
+    let set = UnionSet::from_lines([&b"ok"[..], b"caf\xc3\xa9", b"bad\xff", b"\xfe"]);
+    assert!(matches!(set.to_utf8_string(), Err(SetopError::Utf8Error { line: 3 })));
+    assert_eq!(UnionSet::from_lines(["one", "two"]).to_utf8_string().unwrap(), "one\ntwo\n");
+}
+
+#[test]
+fn case_folding_changes_only_ascii_letters_by_default() {
+    let lower = Config { case_transform: Some(CaseTransform::Lower), ..config() };
+    assert_eq!(&*lower.transform_text("ÉCOLE Café ÀÖ\n".as_bytes()), "École café ÀÖ\n".as_bytes());
+    let upper = Config { case_transform: Some(CaseTransform::Upper), ..config() };
+    assert_eq!(&*upper.transform_text("naïve é".as_bytes()), "NAïVE é".as_bytes());
+}
+
+#[test]
+fn unicode_case_folds_non_ascii_letters_in_utf8_text() {
+    let lower = Config { case_transform: Some(CaseTransform::Lower), unicode_case: true, ..config() };
+    assert_eq!(&*lower.transform_text("ÉCOLE ÀÖ\n".as_bytes()), "école àö\n".as_bytes());
+    assert_eq!(&*lower.transform_text(b"\xc9COLE \xff"), b"\xc9cole \xff");
+}
+
+#[test]
+fn nth_line_and_index_of_agree_and_stop_at_the_end() {
+    let config = config();
+    let union = UnionSet::init(b"c\na\nc\nb\n".to_vec(), &config);
+    let first = b"x\ny\nz\n".to_vec();
+    let mut intersection = IntersectSet::init(&first, &config);
+    intersection.operate(b"z\nx\n", &config);
+    for k in 0..3 {
+        let line = union.nth_line(k).unwrap();
+        assert_eq!(union.index_of(line), Some(k));
+    }
+    assert_eq!(union.nth_line(1), Some(&b"a"[..]));
+    assert_eq!(union.nth_line(3), None);
+    assert_eq!(union.index_of(b"d"), None);
+    for k in 0..2 {
+        let line = intersection.nth_line(k).unwrap();
+        assert_eq!(intersection.index_of(line), Some(k));
+    }
+    assert_eq!(intersection.nth_line(1), Some(&b"z"[..]));
+    assert_eq!(intersection.nth_line(2), None);
+    assert_eq!(intersection.index_of(b"y"), None);
+}
+
+#[test]
+fn every_operation_parses_back_from_its_name() {
+    for op in OpName::ALL {
+        assert_eq!(op.to_string().parse::<OpName>().unwrap(), op);
+    }
+    assert_eq!("u".parse::<OpName>().unwrap(), OpName::Union);
+    assert_eq!("+".parse::<OpName>().unwrap(), OpName::Union);
+    assert_eq!("i".parse::<OpName>().unwrap(), OpName::Intersect);
+    assert_eq!("x".parse::<OpName>().unwrap(), OpName::Intersect);
+}
+
+#[test]
+fn an_unknown_operation_error_lists_the_names() {
+    let err = "onion".parse::<OpName>().unwrap_err();
+    assert!(matches!(&err, SetopError::UnknownOperation(name) if name == "onion"));
+    let message = err.to_string();
+    assert!(message.starts_with("unknown operation onion (expected one of union, intersect, "), "{}", message);
+}
+
+#[test]
+fn first_last_min_and_max_lines() {
+    let set = UnionSet::from_lines(["mango", "apple", "zebra", "kiwi", "banana"]);
+    assert_eq!(set.first_line(), Some(&b"mango"[..]));
+    assert_eq!(set.last_line(), Some(&b"banana"[..]));
+    assert_eq!(set.min_line(), Some(&b"apple"[..]));
+    assert_eq!(set.max_line(), Some(&b"zebra"[..]));
+    let empty = UnionSet::default();
+    assert_eq!(empty.first_line(), None);
+    assert_eq!(empty.last_line(), None);
+    assert_eq!(empty.min_line(), None);
+    assert_eq!(empty.max_line(), None);
+}
+
+#[test]
+fn partition_by_splits_the_result_into_two_disjoint_parts() {
+    let set = UnionSet::from_lines(["a", "bbb", "cc", "dddd", ""]);
+    let (long, short) = set.partition_by(|line| line.len() > 2);
+    assert_eq!(long, texts(&["bbb", "dddd"]));
+    assert_eq!(short, texts(&["a", "cc", ""]));
+    assert!(long.iter().all(|line| !short.contains(line)));
+    let mut both: Vec<_> = long.into_iter().chain(short).collect();
+    both.sort();
+    let mut all: Vec<_> = set.result_lines().map(|line| line.to_vec()).collect();
+    all.sort();
+    assert_eq!(both, all);
+}
+
+#[test]
+fn find_long_line_numbers_the_first_line_over_the_limit() {
+    let text = b"ok\nfour\nfive5\nsixsix\n";
+    assert_eq!(find_long_line(text, &config()), None);
+    let limited = Config { max_line_length: Some(4), ..config() };
+    assert_eq!(find_long_line(text, &limited), Some(3));
+    assert_eq!(find_long_line(b"ok\nfour", &limited), None);
+    let truncating = Config { on_long_line: OnLongLine::Truncate, ..limited.clone() };
+    assert_eq!(find_long_line(text, &truncating), None);
+    let skipping = Config { on_long_line: OnLongLine::Skip, ..limited };
+    assert_eq!(find_long_line(text, &skipping), None);
+}
+
+#[test]
+fn check_line_lengths_reports_the_line_and_its_length() {
+    let limited = Config { max_line_length: Some(4), ..config() };
+    let path = Path::new("dir/a");
+    assert_eq!(check_line_lengths(path, b"ok\nfour\n", 0, &limited), Ok(()));
+    assert_eq!(
+        check_line_lengths(path, b"ok\nfive5\nsixsix\n", 3, &limited),
+        Err(SetopError::LineTooLong { path: path.to_path_buf(), line: 5, length: 5 })
+    );
+    let records = Config { record_size: Some(6), ..limited };
+    assert_eq!(
+        check_line_lengths(path, b"abcdef", 0, &records),
+        Err(SetopError::LineTooLong { path: path.to_path_buf(), line: 1, length: 6 })
+    );
+}
+
+#[test]
+fn skipping_long_lines_leaves_them_out_of_every_operand() {
+    let skipping = Config { max_line_length: Some(4), on_long_line: OnLongLine::Skip, ..config() };
+    let operands = texts(&["abcdef\nxy\nab\n", "abcdef\nxy\n"]);
+    assert_eq!(union_n(&operands, &skipping), b"xy\nab\n");
+    assert_eq!(intersect_n(&operands, &skipping), b"xy\n");
+    assert_eq!(diff_n(&operands, &skipping), b"ab\n");
+    // Lines are measured after normalizing, so trimmed lines can fit
+    let trimming = Config { ignore_trailing_whitespace: true, ..skipping };
+    assert_eq!(union_n(&texts(&["abcd   \nabcde\n"]), &trimming), b"abcd\n");
+}
+
+#[test]
+fn truncating_long_lines_compares_and_writes_their_start() {
+    let truncating = Config { max_line_length: Some(4), on_long_line: OnLongLine::Truncate, ..config() };
+    let operands = texts(&["abcdef\nxy\n", "abcdzz\n"]);
+    assert_eq!(intersect_n(&operands, &truncating), b"abcd\n");
+    assert_eq!(union_n(&operands, &truncating), b"abcd\nxy\n");
+}
+
+// One line of 100MB, to check that nothing is quadratic in the length of a
+// line. It needs a few hundred MB and some seconds, so it only runs with
+// `large-tests`.
+#[cfg(feature = "large-tests")]
+#[test]
+fn a_100mb_line_is_handled_in_one_piece() {
+    let len = 100 << 20;
+    let line: TextVec = (0..len).map(|i| b'a' + (i % 26) as u8).collect();
+    let operands = vec![line.clone(), line.clone()];
+    let mut expected = line.clone();
+    expected.push(b'\n');
+    assert_eq!(union_n(&operands, &config()), expected);
+    assert_eq!(intersect_n(&operands, &config()), expected);
+    let limited = Config { max_line_length: Some(len - 1), ..config() };
+    assert_eq!(find_long_line(&line, &limited), Some(1));
+    let truncating = Config { on_long_line: OnLongLine::Truncate, ..limited };
+    assert_eq!(union_n(&operands, &truncating).len(), len);
+}
+
+#[test]
+fn utf8_filters_separate_text_lines_from_binary_ones() {
+    let mixed = UnionSet::from_lines([&b"plain"[..], b"caf\xc3\xa9", b"\xff\xfe", b"", b"half \xc3", b"\xe2\x9c\x93"]);
+    assert_eq!(mixed.count_utf8_invalid(), 2);
+    let mut valid = mixed.clone();
+    valid.retain_utf8_valid();
+    assert_eq!(lines(&valid), [&b"plain"[..], b"caf\xc3\xa9", b"", b"\xe2\x9c\x93"]);
+    let mut invalid = mixed;
+    invalid.retain_utf8_invalid();
+    assert_eq!(lines(&invalid), [&b"\xff\xfe"[..], b"half \xc3"]);
+    assert_eq!(invalid.count_utf8_invalid(), 2);
+    assert_eq!(valid.count_utf8_invalid(), 0);
+}
+
+#[test]
+fn split_header_takes_the_first_lines_with_their_delimiters() {
+    let header = |n| Config { header_lines: n, ..config() };
+    let text = b"id,name\nunit,x\n1,a\n2,b";
+    assert_eq!(split_header(text, &header(0)), (&b""[..], &text[..]));
+    assert_eq!(split_header(text, &header(1)), (&b"id,name\n"[..], &b"unit,x\n1,a\n2,b"[..]));
+    assert_eq!(split_header(text, &header(2)), (&b"id,name\nunit,x\n"[..], &b"1,a\n2,b"[..]));
+    assert_eq!(split_header(text, &header(4)), (&text[..], &b""[..]));
+    assert_eq!(split_header(text, &header(9)), (&text[..], &b""[..]));
+}
+
+#[test]
+fn split_lines_strips_delimiters_and_adds_no_empty_last_line() {
+    let split = |text: &'static [u8], delimiter| split_lines(text, delimiter).collect::<Vec<_>>();
+    assert_eq!(split(b"a\nb\n", b'\n'), [&b"a"[..], b"b"]);
+    assert_eq!(split(b"a\nb", b'\n'), [&b"a"[..], b"b"]);
+    assert_eq!(split(b"\n\na\n\n", b'\n'), [&b""[..], b"", b"a", b""]);
+    assert_eq!(split(b"a\r\nb", b'\n'), [&b"a\r"[..], b"b"]);
+    assert_eq!(split(b"x\0y\nz\0", b'\0'), [&b"x"[..], b"y\nz"]);
+    assert!(split(b"", b'\n').is_empty());
+    assert_eq!(split(b"\n", b'\n'), [&b""[..]]);
+}
+
+#[test]
+fn split_lines_gives_the_lines_sets_insert() {
+    let text = b"b\n\na\nb\nc";
+    let set = UnionSet::init(text.to_vec(), &config());
+    let lines: UnionSet = split_lines(text, b'\n').map(|line| line.to_vec()).collect();
+    assert!(set.iter().eq(lines.iter()));
+}
+
+#[test]
+fn operate_early_exit_says_when_the_set_is_settled() {
+    let config = config();
+    let first = b"a\nb\n".to_vec();
+    let mut intersection = IntersectSet::init(&first, &config);
+    assert!(!intersection.operate_early_exit(b"b\nc\n", &config));
+    assert!(intersection.operate_early_exit(b"c\n", &config));
+    let mut union = UnionSet::init(first, &config);
+    assert!(!union.operate_early_exit(b"", &config));
+}
+
+#[test]
+fn the_n_ary_functions_keep_first_seen_order() {
+    let operands = texts(&["c\na\nb\n", "b\nd\nc\n", "c\ne\nb\n"]);
+    assert_eq!(union_n(&operands, &config()), b"c\na\nb\nd\ne\n");
+    assert_eq!(intersect_n(&operands, &config()), b"c\nb\n");
+    assert_eq!(diff_n(&operands, &config()), b"a\n");
+    assert_eq!(symdiff_n(&operands, &config()), b"a\nd\ne\n");
+}
+
+#[test]
+fn one_operand_gives_its_lines_deduplicated() {
+    let operands = texts(&["b\na\nb\na"]);
+    for op in [union_n, intersect_n, diff_n, symdiff_n] {
+        assert_eq!(op(&operands, &config()), b"b\na\n");
+    }
+}
+
+#[test]
+fn no_operands_give_nothing() {
+    for op in [union_n, intersect_n, diff_n, symdiff_n] {
+        assert_eq!(op(&[], &config()), b"");
+    }
+}
+
+#[test]
+fn symdiff_n_keeps_lines_in_exactly_one_operand() {
+    // "a" is in all three operands, an odd number, and still isn't kept
+    let operands = texts(&["a\nb\n", "a\nc\n", "a\nb\nd\n"]);
+    assert_eq!(symdiff_n(&operands, &config()), b"c\nd\n");
+}
+
+#[test]
+fn the_str_functions_give_strings() {
+    let strs = ["é\nb\n", "b\nü"];
+    assert_eq!(union_strs(&strs, &config()).unwrap(), "é\nb\nü\n");
+    assert_eq!(intersect_strs(&strs, &config()).unwrap(), "b\n");
+    assert_eq!(diff_strs(&strs, &config()).unwrap(), "é\n");
+    assert_eq!(symdiff_strs(&strs, &config()).unwrap(), "é\nü\n");
+}
+
+// The examples the n-ary functions were asked to document; the crate has no
+// rustdoc, so they live here
+#[test]
+fn the_n_ary_functions_on_two_word_lists() {
+    let operands = texts(&["apple\nbanana\ncherry\n", "banana\ndate\napple\n"]);
+    assert_eq!(union_n(&operands, &config()), b"apple\nbanana\ncherry\ndate\n");
+    assert_eq!(intersect_n(&operands, &config()), b"apple\nbanana\n");
+    assert_eq!(diff_n(&operands, &config()), b"cherry\n");
+    assert_eq!(symdiff_n(&operands, &config()), b"cherry\ndate\n");
+}
+
+#[test]
+fn the_mathematical_names_of_two_operands() {
+    let (a, b) = (b"1\n2\n3\n".to_vec(), b"3\n4\n".to_vec());
+    let both = [a.clone(), b.clone()];
+    assert_eq!(set_minus(a.clone(), &both[1..], &config()), b"1\n2\n");
+    assert_eq!(set_union(&both, &config()), b"1\n2\n3\n4\n");
+    assert_eq!(set_intersect(&both, &config()), b"3\n");
+    // (A \ B) ∪ (B \ A)
+    let halves = [set_minus(a, &both[1..], &config()), set_minus(b, &both[..1], &config())];
+    assert_eq!(set_symdiff(&both, &config()), set_union(&halves, &config()));
+}
+
+#[test]
+fn the_str_functions_on_string_literals() {
+    let strs = ["red\ngreen\nblue", "green\nyellow\n"];
+    assert_eq!(union_strs(&strs, &config()).unwrap(), "red\ngreen\nblue\nyellow\n");
+    assert_eq!(intersect_strs(&strs, &config()).unwrap(), "green\n");
+    assert_eq!(diff_strs(&strs, &config()).unwrap(), "red\nblue\n");
+    assert_eq!(symdiff_strs(&strs, &config()).unwrap(), "red\nblue\nyellow\n");
+    assert_eq!(intersect_strs(&["b\na\nb\n"], &config()).unwrap(), "b\na\n");
+    assert_eq!(union_strs(&[], &config()).unwrap(), "");
+}
+
+#[test]
+fn utf8_errors_give_the_line_of_the_bad_byte() {
+    let truncating = Config { max_line_length: Some(2), on_long_line: OnLongLine::Truncate, ..config() };
+    assert!(matches!(union_strs(&["aé\n"], &truncating), Err(SetopError::Utf8Error { line: 1 })));
+    assert!(matches!(union_strs(&["ab\ncd\naé\n"], &truncating), Err(SetopError::Utf8Error { line: 3 })));
+    assert!(matches!(union_strs(&["ab\nxé"], &truncating), Err(SetopError::Utf8Error { line: 2 })));
+}
+
+// A subscriber that keeps each event as its fields written out in order, for
+// checking which events the `tracing` feature emits
+#[cfg(feature = "tracing")]
+#[derive(Clone, Default)]
+struct EventLog(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for EventLog {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        tracing::span::Id::from_u64(1)
+    }
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+    fn event(&self, event: &tracing::Event<'_>) {
+        struct Fields(String);
+        impl tracing::field::Visit for Fields {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                self.0.push_str(&format!(" {}={:?}", field.name(), value));
+            }
+        }
+        let mut fields = Fields(event.metadata().level().to_string());
+        event.record(&mut fields);
+        self.0.lock().unwrap().push(fields.0);
+    }
+    fn enter(&self, _: &tracing::span::Id) {}
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn intersections_and_results_emit_tracing_events() {
+    let log = EventLog::default();
+    let operands = texts(&["a\nb\nc\n", "b\nc\nd\n", "c\n"]);
+    tracing::subscriber::with_default(log.clone(), || intersect_n(&operands, &config()));
+    assert_eq!(
+        *log.0.lock().unwrap(),
+        [
+            "DEBUG message=intersected with operand before=3 after=2",
+            "DEBUG message=intersected with operand before=2 after=1",
+            "INFO message=operation complete result_lines=1",
+        ]
+    );
+}
+
+#[test]
+fn merge_keeps_self_first_then_others_new_lines() {
+    let mut set = UnionSet::from_lines(["b", "a"]);
+    set.merge(UnionSet::from_lines(["c", "a", "d"]));
+    assert_eq!(lines(&set), [&b"b"[..], b"a", b"c", b"d"]);
+}
+
+#[test]
+fn merging_is_associative() {
+    let pieces = || (UnionSet::from_lines(["a", "b"]), UnionSet::from_lines(["c", "a"]), UnionSet::from_lines(["d", "c", "b"]));
+    let (mut left, middle, right) = pieces();
+    left.merge(middle);
+    left.merge(right);
+    let (mut outer, mut middle, right) = pieces();
+    middle.merge(right);
+    outer.merge(middle);
+    assert_eq!(lines(&left), lines(&outer));
+    assert_eq!(lines(&left), [&b"a"[..], b"b", b"c", b"d"]);
+    let whole = UnionSet::init(b"a\nb\nc\na\nd\nc\nb\n".to_vec(), &config());
+    assert_eq!(lines(&left), lines(&whole));
+}
+
+#[test]
+fn no_final_delimiter_separates_lines_without_ending_the_last() {
+    let set = UnionSet::init(b"a\nb\nc\n".to_vec(), &config());
+    let separated = Config { no_final_delimiter: true, ..config() };
+    let mut out = Vec::new();
+    set.write_to(&mut out, &separated).unwrap();
+    assert_eq!(out, b"a\nb\nc");
+    let one = UnionSet::init(b"a".to_vec(), &config());
+    let mut out = Vec::new();
+    one.write_to(&mut out, &separated).unwrap();
+    assert_eq!(out, b"a");
+    let limited = Config { max_lines: Some(2), ..separated };
+    let mut out = Vec::new();
+    set.write_to(&mut out, &limited).unwrap();
+    assert_eq!(out, b"a\nb");
+}
+
+#[test]
+fn no_final_delimiter_writes_an_empty_result_as_nothing_or_the_bare_marker() {
+    let separated = Config { no_final_delimiter: true, ..config() };
+    let mut empty = UnionSet::default();
+    let mut out = Vec::new();
+    finish_and_write(&mut empty, &mut out, &separated).unwrap();
+    assert_eq!(out, b"");
+    let marked = Config { empty_marker: Some(b"(none)".to_vec()), ..separated };
+    finish_and_write(&mut empty, &mut out, &marked).unwrap();
+    assert_eq!(out, b"(none)");
+}
+
+#[test]
+fn small_and_large_operands_intersect_alike() {
+    // Operands on both sides of `SORTED_OPERAND_LINES`, with repeats, empty
+    // lines and lines that only match once trimmed
+    let trimming = Config { ignore_trailing_whitespace: true, ..config() };
+    let first: TextVec = (0..40).flat_map(|n| format!("{}\n\n", n % 30).into_bytes()).collect();
+    for len in 1..=2 * SORTED_OPERAND_LINES + 2 {
+        let operand: TextVec = (0..len).flat_map(|n| format!("{} \n", 3 * n % 45).into_bytes()).collect();
+        let mut set = IntersectSet::init(&first, &trimming);
+        set.operate(&operand, &trimming);
+        let other: SliceSet = split_lines(&operand, b'\n').map(|line| trimming.normalize_line(line)).collect();
+        let expected: Vec<&TextSlice> = split_lines(&first, b'\n').filter(|line| other.contains(line)).collect::<SliceSet>().into_iter().collect();
+        // `intersect_n` and the command line intersect with a
+        // `CachedIntersectSet`, which hashes even the smallest operand
+        let bytes: TextVec = expected.iter().flat_map(|line| [*line, b"\n"].concat()).collect();
+        let operands = [first.clone(), operand];
+        assert_eq!(intersect_n(&operands, &trimming), bytes, "{} lines", len);
+        let mut dispatched = TextVec::new();
+        write_calculation(OpName::Intersect, operands.iter(), &mut dispatched, &trimming).unwrap();
+        assert_eq!(dispatched, bytes, "{} lines", len);
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), expected, "{} lines", len);
+    }
+}
+
+#[test]
+fn conditional_union_takes_the_lines_of_b_the_predicate_accepts() {
+    let a = b"alice\nbob\n".to_vec();
+    let b = b"alice\tadmin\ncarol\tuser\nbob\tuser\nalice\tadmin\nbobby\tuser\n";
+    let first_field_in_a = |line: &TextSlice, a: &UnionSet| a.contains(line.split(|&b| b == b'\t').next().unwrap_or(line));
+    let joined = conditional_union(a.clone(), b, first_field_in_a, &config());
+    assert_eq!(lines(&joined), [&b"alice"[..], b"bob", b"alice\tadmin", b"bob\tuser"]);
+    let has_prefix_in_a = |line: &TextSlice, a: &UnionSet| a.iter().any(|prefix| line.starts_with(prefix));
+    let prefixed = conditional_union(a, b, has_prefix_in_a, &config());
+    assert_eq!(lines(&prefixed), [&b"alice"[..], b"bob", b"alice\tadmin", b"bob\tuser", b"bobby\tuser"]);
+}
+
+#[test]
+fn conditional_union_predicates_see_only_the_lines_of_a() {
+    // Each line of `b` extends the one before it, but only `a`'s line counts
+    let b = b"x1\nx12\nx123\n";
+    let extends_a_line = |line: &TextSlice, a: &UnionSet| a.contains(&line[..line.len() - 1]);
+    let set = conditional_union(b"x".to_vec(), b, extends_a_line, &config());
+    assert_eq!(lines(&set), [&b"x"[..], b"x1"]);
+}
+
+#[test]
+fn into_union_set_keeps_the_intersection_in_order() {
+    let (a, b) = (b"1\n2\n3\n4\n".to_vec(), b"4\n2\n3\n".to_vec());
+    let mut set = IntersectSet::init(&a, &config());
+    set.operate(&b, &config());
+    assert_eq!(set.into_union_set().to_bytes(), intersect_n(&[a, b], &config()));
+}
+
+#[test]
+fn into_union_set_goes_on_with_owned_operations() {
+    let operands = texts(&["1\n2\n3\n4\n", "4\n2\n3\n", "3\n4\n5\n"]);
+    let intersection = || {
+        let mut set = IntersectSet::init(&operands[0], &config());
+        set.operate(&operands[1], &config());
+        set.into_union_set()
+    };
+    // A `UnionSet` operates by union
+    let mut union = intersection();
+    union.operate(&operands[2], &config());
+    assert_eq!(union.to_bytes(), b"2\n3\n4\n5\n");
+    // and a filter by the third operand's lines finishes the intersection
+    let third = UnionSet::init(operands[2].clone(), &config());
+    let mut narrowed = intersection();
+    narrowed.retain_lines(|line| third.contains(line));
+    assert_eq!(narrowed.to_bytes(), intersect_n(&operands, &config()));
+}
+
+// The result of `op` over `operands` for each of `count` shards
+fn shard_results(op: fn(&[TextVec], &Config) -> TextVec, operands: &[TextVec], count: u64, config: &Config) -> Vec<UnionSet> {
+    (0..count)
+        .map(|index| {
+            let sharded = Config { shard: Some(Shard { index, count }), ..config.clone() };
+            // Operands are transformed as they're read, before any set sees them
+            let operands: Vec<TextVec> = operands.iter().map(|text| sharded.transform_text(text).into_owned()).collect();
+            UnionSet::init(op(&operands, &sharded), &sharded)
+        })
+        .collect()
+}
+
+// Whether `shards` are disjoint and together have just the lines of `whole`
+fn partitions(shards: &[UnionSet], whole: &TextVec) -> bool {
+    let whole = UnionSet::init(whole.clone(), &config());
+    let total: usize = shards.iter().map(|shard| shard.len()).sum();
+    let joined: UnionSet = shards.iter().flatten().cloned().collect();
+    total == whole.len() && joined.len() == whole.len() && joined.iter().all(|line| whole.contains(line))
+}
+
+#[test]
+fn shards_partition_the_result() {
+    let a: String = (0..40).map(|n| format!("{}\n", n)).collect();
+    let b: String = (20..70).map(|n| format!("{}\n", n)).collect();
+    let operands = texts(&[&a, &b]);
+    for op in [union_n, intersect_n, diff_n, symdiff_n] {
+        let whole = op(&operands, &config());
+        for count in [1, 2, 3, 7] {
+            assert!(partitions(&shard_results(op, &operands, count, &config()), &whole), "{} shards", count);
+        }
+    }
+}
+
+#[test]
+fn shards_of_hashed_lines_partition_the_hashed_result() {
+    let a: String = (0..40).map(|n| format!("{}\n", n)).collect();
+    let b: String = (20..70).map(|n| format!("{}\n", n)).collect();
+    let hashing = Config { hash_lines: true, ..config() };
+    let operands = texts(&[&a, &b]);
+    let hashed: Vec<TextVec> = operands.iter().map(|text| hashing.transform_text(text).into_owned()).collect();
+    for op in [union_n, intersect_n] {
+        let whole = op(&hashed, &hashing);
+        let shards = shard_results(op, &operands, 2, &hashing);
+        assert!(partitions(&shards, &whole));
+        assert!(shards.iter().all(|shard| !shard.is_empty()));
+    }
+    assert_eq!(union_n(&hashed, &hashing).iter().filter(|&&b| b == b'\n').count(), 70);
+}
+
+#[test]
+fn trim_cr_strips_one_carriage_return_before_a_newline() {
+    let trimming = Config { trim_cr: true, ..config() };
+    let operands = texts(&["a\r\nb \r\nc\r\r\nd\re\n", "a\nb \nc\r\n"]);
+    assert_eq!(union_n(&operands, &trimming), b"a\nb \nc\r\nd\re\nc\n");
+    assert_eq!(intersect_n(&operands, &trimming), b"a\nb \n");
+}
+
+#[test]
+fn trim_cr_leaves_nul_delimited_records_alone() {
+    // As asked for, a carriage return is kept before a NUL as well as inside
+    // a record
+    let trimming = Config { trim_cr: true, delimiter: b'\0', ..config() };
+    let operands = texts(&["a\r\0b\rc\0", "a\0b\rc\0"]);
+    let mut out = TextVec::new();
+    write_calculation(OpName::Union, operands.iter(), &mut out, &trimming).unwrap();
+    assert_eq!(out, b"a\r\0b\rc\0a\0");
+}
+
+#[test]
+fn line_counts_ignore_the_shard() {
+    let text = b"a\nb\nc\na\nd\n";
+    let sharded = Config { shard: Some(Shard { index: 0, count: 2 }), ..config() };
+    assert_eq!(count_lines(text, &sharded), 5);
+    assert_eq!(count_duplicates(text, &sharded), 1);
+}
+
+#[test]
+fn distinct_lines_have_no_duplicates() {
+    let text = b"a\nb\nc\n";
+    assert!(!has_duplicates(text, &config()));
+    assert_eq!(count_duplicates(text, &config()), 0);
+    assert_eq!(find_first_duplicate(text, &config()), None);
+}
+
+#[test]
+fn every_repeat_of_a_line_is_counted() {
+    let text = b"a\nb\na\nc\na\n";
+    assert!(has_duplicates(text, &config()));
+    assert_eq!(count_duplicates(text, &config()), 2);
+}
+
+#[test]
+fn the_first_duplicate_is_the_first_line_to_repeat() {
+    // `a` is repeated too, but `b` repeats first
+    let text = b"a\nb\nb\na\n";
+    assert_eq!(find_first_duplicate(text, &config()), Some(&b"b"[..]));
+    let found = find_first_duplicate(text, &config()).unwrap();
+    assert_eq!(found.as_ptr() as usize - text.as_ptr() as usize, 4);
+}
+
+// The result of `op` as the command line dispatches it
+fn dispatched(op: OpName, operands: &[TextVec]) -> TextVec {
+    let mut out = TextVec::new();
+    write_calculation(op, operands.iter(), &mut out, &config()).unwrap();
+    out
+}
+
+#[test]
+fn the_mathematical_names_match_the_operations() {
+    let operands = texts(&["a\nb\nc\nd\n", "b\ne\n", "c\ne\nf\n"]);
+    assert_eq!(set_minus(operands[0].clone(), &operands[1..], &config()), dispatched(OpName::Complement, &operands));
+    assert_eq!(set_minus(operands[0].clone(), &operands[1..], &config()), b"a\nd\n");
+    assert_eq!(set_union(&operands, &config()), dispatched(OpName::Union, &operands));
+    assert_eq!(set_intersect(&operands, &config()), dispatched(OpName::Intersect, &operands));
+    assert_eq!(set_intersect(&operands[1..], &config()), b"e\n");
+    assert_eq!(set_symdiff(&operands, &config()), dispatched(OpName::Exclusive, &operands));
+    assert_eq!(set_symdiff(&operands, &config()), b"a\nd\nf\n");
+}
+
+#[test]
+fn set_minus_with_nothing_to_subtract_is_the_minuend() {
+    assert_eq!(set_minus(b"b\na\nb\n".to_vec(), &[], &config()), b"b\na\n");
+}
+
+#[test]
+fn folding_intersections_matches_operating_on_the_raw_inputs() {
+    let operands = texts(&["1\n2\n3\n4\n", "2\n3\n4\n5\n", "3\n4\n", "4\n2\n6\n"]);
+    fn intersect(pair: &[TextVec]) -> IntersectSet<'_> {
+        let mut set = IntersectSet::init(&pair[0], &config());
+        set.operate(&pair[1], &config());
+        set
+    }
+    // (A ∩ B) ∪ (C ∩ D) and (A ∩ B) ∩ (C ∩ D)
+    let halves = || vec![intersect(&operands[..2]), intersect(&operands[2..])];
+    let union = fold_into_union(halves());
+    let expected = union_n(&[intersect_n(&operands[..2], &config()), intersect_n(&operands[2..], &config())], &config());
+    assert_eq!(union.to_bytes(), expected);
+    let halves = halves();
+    let mut intersection = fold_into_intersection(&halves);
+    assert_eq!(intersection.to_bytes(), intersect_n(&operands, &config()));
+    // It's an `IntersectSet`, so it can take another operand
+    intersection.operate(b"5\n", &config());
+    assert!(intersection.is_settled());
+}
+
+#[test]
+fn folding_keeps_first_seen_order_and_handles_no_sets() {
+    let sets = vec![UnionSet::from_lines(["c", "a"]), UnionSet::from_lines(["b", "a", "c"])];
+    assert_eq!(lines(&fold_into_union(sets.clone())), [&b"c"[..], b"a", b"b"]);
+    assert_eq!(lines(&fold_into_intersection(&sets)), [&b"c"[..], b"a"]);
+    assert!(fold_into_union(Vec::<UnionSet>::new()).is_empty());
+    assert!(fold_into_intersection::<UnionSet>(&[]).is_empty());
+}
+
+#[test]
+fn folding_sees_the_sets_results_after_filtering() {
+    let mut long = UnionSet::from_lines(["a", "bb", "ccc"]);
+    long.retain_lines(|line| line.len() > 1);
+    let sets = vec![long, UnionSet::from_lines(["a", "ccc"])];
+    assert_eq!(lines(&fold_into_intersection(&sets)), [&b"ccc"[..]]);
+}
+
+#[test]
+fn sets_from_split_lines_match_sets_from_the_text() {
+    let text = b"b\na\n\nb\nc";
+    let union = UnionSet::init_from_lines(split_lines(text, b'\n'));
+    assert_eq!(union, UnionSet::init_from_slice(text, &config()));
+    let mut intersection = IntersectSet::init_from_lines(split_lines(text, b'\n'));
+    assert_eq!(intersection, IntersectSet::init_from_slice(text, &config()));
+    let mut from_text = intersection.clone();
+    intersection.operate_lines(split_lines(b"c\nb\nd\n", b'\n'));
+    from_text.operate(b"c\nb\nd\n", &config());
+    assert_eq!(intersection, from_text);
+    // Lines that come split aren't normalized
+    let trimming = Config { ignore_trailing_whitespace: true, ..config() };
+    let mut union = UnionSet::default();
+    union.insert_all_lines(b"a \n", &trimming);
+    union.insert_lines([&b"a "[..]]);
+    assert_eq!(lines(&union), [&b"a"[..], b"a "]);
+}
+
+#[test]
+fn an_intersect_set_from_lines_borrows_them_from_the_items() {
+    // Rows as a database cursor might hand them out, each its own allocation
+    let rows: Vec<TextVec> = ["x", "y", "x", "z"].iter().map(|row| row.as_bytes().to_vec()).collect();
+    let mut set = IntersectSet::init_from_lines(rows.iter().map(TextVec::as_slice));
+    assert_eq!(set.len(), 3);
+    assert!(set.iter().zip([&rows[0], &rows[1], &rows[3]]).all(|(line, row)| std::ptr::eq(*line, row.as_slice())));
+    {
+        // An operand's lines only need to last as long as `operate_lines`
+        let other: Vec<TextVec> = vec![b"z".to_vec(), b"x".to_vec()];
+        set.operate_lines(other.iter().map(TextVec::as_slice));
+    }
+    assert_eq!(lines(&set), [&b"x"[..], b"z"]);
+    assert!(std::ptr::eq(*set.get_index(0).unwrap(), rows[0].as_slice()));
+}
+
+#[test]
+fn calculating_with_no_operands_is_an_error() {
+    let mut out = Vec::new();
+    let err = write_calculation(OpName::Union, [].iter(), &mut out, &config()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(do_calculation(OpName::Intersect, [].iter(), &config()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert!(out.is_empty());
+}
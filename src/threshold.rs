@@ -0,0 +1,99 @@
+use std::io;
+
+use indexmap::IndexMap;
+
+use crate::{Config, LineSet, SetExpression, SliceSet, TextSlice, TextVec};
+
+// A `ThresholdSet` holds the lines whose operands weigh at least `threshold`
+// between them. Each operand weighs `config.operand_weights[i]`, or 1 past the
+// end of that list, and counts once for a line however often it has it. With
+// every weight 1 that's the lines in at least `threshold` operands: 1 gives
+// the union, and the number of operands the intersection. Lines come out in
+// the order they were first seen.
+//
+pub struct ThresholdSet {
+    threshold: u64,
+    weights: Vec<u64>,
+    operands: usize,
+    totals: IndexMap<TextVec, u64>,
+}
+
+impl ThresholdSet {
+    pub fn init(text: &TextSlice, threshold: u64, config: &Config) -> Self {
+        let mut set =
+            ThresholdSet { threshold, weights: config.operand_weights.clone(), operands: 0, totals: IndexMap::new() };
+        set.operate(text, config);
+        set
+    }
+}
+
+impl<'data> SetExpression<'data> for ThresholdSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        let weight = self.weights.get(self.operands).cloned().unwrap_or(1);
+        self.operands += 1;
+        for line in SliceSet::init_from_slice(text, config) {
+            match self.totals.get_mut(line) {
+                Some(total) => *total = total.saturating_add(weight),
+                None => {
+                    self.totals.insert(line.to_vec(), weight);
+                }
+            }
+        }
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        let threshold = self.threshold;
+        self.totals.retain(|_, total| *total >= threshold);
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.totals.keys().map(|v| v.as_slice()))
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.totals.retain(|line, _| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    fn threshold(texts: &[&TextSlice], threshold: u64, config: &Config) -> ThresholdSet {
+        let mut set = ThresholdSet::init(texts[0], threshold, config);
+        for text in &texts[1..] {
+            set.operate(text, config);
+        }
+        set.finish().unwrap();
+        set
+    }
+
+    #[test]
+    fn with_no_weights_a_line_needs_that_many_operands() {
+        let texts: [&TextSlice; 3] = [b"a\nb\nb\n", b"b\nc\n", b"c\nb\nd\n"];
+        let config = Config::default();
+        let expected: [&[u8]; 2] = [b"b", b"c"];
+        assert_eq!(lines(&threshold(&texts, 2, &config)), expected);
+        assert_eq!(lines(&threshold(&texts, 3, &config)), [b"b"]);
+    }
+
+    #[test]
+    fn one_heavy_operand_can_meet_the_threshold_alone() {
+        let texts: [&TextSlice; 3] = [b"a\nb\n", b"b\nc\n", b"c\n"];
+        let config = Config { operand_weights: vec![3, 1, 1], ..Config::default() };
+        let expected: [&[u8]; 2] = [b"a", b"b"];
+        assert_eq!(lines(&threshold(&texts, 3, &config)), expected);
+    }
+
+    #[test]
+    fn operands_past_the_end_of_the_weights_weigh_1() {
+        let texts: [&TextSlice; 3] = [b"a\nb\n", b"b\nc\n", b"c\nd\n"];
+        let config = Config { operand_weights: vec![2], ..Config::default() };
+        // `a` weighs 2, `b` 3, `c` 2 and `d` 1
+        let expected: [&[u8]; 3] = [b"a", b"b", b"c"];
+        assert_eq!(lines(&threshold(&texts, 2, &config)), expected);
+        assert_eq!(lines(&threshold(&texts, 3, &config)), [b"b"]);
+    }
+}
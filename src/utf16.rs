@@ -0,0 +1,71 @@
+use crate::{TextSlice, TextVec};
+
+// If `text` starts with a UTF-16 byte order mark, `FF FE` for little-endian or
+// `FE FF` for big-endian, the rest of it converted to UTF-8 so it compares
+// equal to the same lines in a UTF-8 file. The mark itself is dropped. A
+// lone surrogate, or an odd byte at the end, becomes U+FFFD. Lines still end
+// where the decoded text has `\n`, and Windows line ends keep their `\r`.
+// Text without a mark is left alone, as `None`.
+pub fn decode_utf16_bom(text: &TextSlice) -> Option<TextVec> {
+    let from_bytes: fn([u8; 2]) -> u16 = match text {
+        [0xff, 0xfe, ..] => u16::from_le_bytes,
+        [0xfe, 0xff, ..] => u16::from_be_bytes,
+        _ => return None,
+    };
+    let units = text[2..].chunks(2).map(|pair| match *pair {
+        [a, b] => from_bytes([a, b]),
+        // Not a surrogate pair's first half followed by its second, so it
+        // decodes as U+FFFD
+        _ => 0xdc00,
+    });
+    let decoded: String = char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect();
+    Some(decoded.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> TextVec {
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        bytes
+    }
+
+    fn utf16be(text: &str) -> TextVec {
+        let mut bytes = vec![0xfe, 0xff];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        bytes
+    }
+
+    #[test]
+    fn either_byte_order_decodes_to_the_same_utf8() {
+        let text = "héllo\r\n𝄞 world\n";
+        assert_eq!(decode_utf16_bom(&utf16le(text)), Some(text.as_bytes().to_vec()));
+        assert_eq!(decode_utf16_bom(&utf16be(text)), Some(text.as_bytes().to_vec()));
+        assert_eq!(decode_utf16_bom(&[0xff, 0xfe]), Some(Vec::new()));
+    }
+
+    #[test]
+    fn an_odd_byte_at_the_end_is_a_replacement_character() {
+        let mut bytes = utf16le("ab");
+        bytes.push(b'c');
+        assert_eq!(decode_utf16_bom(&bytes), Some("ab\u{fffd}".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn an_unpaired_surrogate_is_a_replacement_character() {
+        // A high surrogate followed by `a` rather than a low one, then a low
+        // surrogate on its own
+        let bytes = [0xff, 0xfe, 0x3d, 0xd8, b'a', 0, 0x00, 0xdc, b'\n', 0];
+        assert_eq!(decode_utf16_bom(&bytes), Some("\u{fffd}a\u{fffd}\n".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn text_without_a_mark_is_left_alone() {
+        assert_eq!(decode_utf16_bom(b"hello\n"), None);
+        assert_eq!(decode_utf16_bom(&[0xef, 0xbb, 0xbf, b'a']), None);
+        assert_eq!(decode_utf16_bom(&[0xff]), None);
+        assert_eq!(decode_utf16_bom(b""), None);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{io, str};
+
+use indexmap::IndexMap;
+
+use crate::{buffer::LineBuffer, Config, LineSet, SetExpression, TextSlice, TextVec};
+
+// A `WeightedUnionSet` is a union of lines that each start with a count and
+// `config.weight_delimiter`, like `42\tfoo`. Rather than keeping one copy of
+// each line it adds up the counts of every line with the same remainder, and
+// `finish` writes each remainder after its total and the delimiter. A line
+// with no count has a weight of 1, or with `config.require_weights` is an
+// error. Filters see the remainder, without the total.
+//
+pub struct WeightedUnionSet {
+    delimiter: u8,
+    require_weights: bool,
+    weights: IndexMap<TextVec, u64>,
+    // `insert_line` can't return an error, so a missing weight is kept for `finish`
+    error: Option<io::Error>,
+    weighted: LineBuffer,
+}
+
+impl WeightedUnionSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let mut set = WeightedUnionSet {
+            delimiter: config.weight_delimiter,
+            require_weights: config.require_weights,
+            weights: IndexMap::new(),
+            error: None,
+            weighted: LineBuffer::default(),
+        };
+        set.operate(text, config);
+        set
+    }
+
+    fn write_totals(&mut self) {
+        self.weighted = LineBuffer::default();
+        for (line, total) in &self.weights {
+            self.weighted.push(&[total.to_string().as_bytes(), &[self.delimiter], line]);
+        }
+    }
+}
+
+// The weight and the rest of `line`, if it starts with a number and the
+// delimiter
+fn split_weight(line: &TextSlice, delimiter: u8) -> Option<(u64, &TextSlice)> {
+    let split = memchr::memchr(delimiter, line)?;
+    let weight = str::from_utf8(&line[..split]).ok()?.parse().ok()?;
+    Some((weight, &line[split + 1..]))
+}
+
+impl<'a> LineSet<'a> for WeightedUnionSet {
+    fn insert_line(&mut self, line: &'a TextSlice) {
+        let (weight, rest) = match split_weight(line, self.delimiter) {
+            Some(split) => split,
+            None if self.require_weights => {
+                if self.error.is_none() {
+                    let message = format!("line has no weight: {}", String::from_utf8_lossy(line));
+                    self.error = Some(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+                return;
+            }
+            None => (1, line),
+        };
+        let total = self.weights.entry(rest.to_vec()).or_insert(0);
+        *total = total.saturating_add(weight);
+    }
+}
+
+impl<'data> SetExpression<'data> for WeightedUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.insert_all_lines(text, config);
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.write_totals();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.weighted.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.weights.retain(|line, _| keep(line));
+        // If `finish` has already run, its output needs the same filtering
+        if !self.weighted.is_empty() {
+            self.write_totals();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn weights_are_summed_across_files() {
+        let config = Config::default();
+        let mut set = WeightedUnionSet::init(b"2\tfoo\n3\tbar\n", &config);
+        set.operate(b"5\tfoo\n1\tbaz\n", &config);
+        set.finish().unwrap();
+        let expected: [&[u8]; 3] = [b"7\tfoo", b"3\tbar", b"1\tbaz"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn a_line_with_no_weight_counts_once() {
+        let config = Config::default();
+        let mut set = WeightedUnionSet::init(b"foo\n4\tfoo\nbar\n", &config);
+        set.finish().unwrap();
+        let expected: [&[u8]; 2] = [b"5\tfoo", b"1\tbar"];
+        assert_eq!(lines(&set), expected);
+    }
+
+    #[test]
+    fn a_missing_weight_is_an_error_when_weights_are_required() {
+        let config = Config { require_weights: true, ..Config::default() };
+        let mut set = WeightedUnionSet::init(b"1\tfoo\nbar\n", &config);
+        let err = set.finish().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("bar"));
+    }
+
+    #[test]
+    fn totals_saturate_rather_than_overflow() {
+        let config = Config::default();
+        let max = format!("{}\tfoo\n", u64::MAX);
+        let mut set = WeightedUnionSet::init(max.as_bytes(), &config);
+        set.operate(b"1\tfoo\n", &config);
+        set.finish().unwrap();
+        let expected = format!("{}\tfoo", u64::MAX);
+        assert_eq!(lines(&set), [expected.as_bytes()]);
+    }
+
+    #[test]
+    fn filters_after_finish_see_the_line_without_its_total() {
+        let config = Config::default();
+        let mut set = WeightedUnionSet::init(b"2\tfoo\n3\tquux\n", &config);
+        set.finish().unwrap();
+        set.retain_lines(|line| line.len() > 3);
+        let expected: [&[u8]; 1] = [b"3\tquux"];
+        assert_eq!(lines(&set), expected);
+    }
+}
@@ -0,0 +1,138 @@
+use std::io;
+
+use indexmap::IndexMap;
+
+use crate::{buffer::LineBuffer, Config, LineSet, SetExpression, SliceCounts, TextSlice, TextVec};
+
+// How many times each word occurs, in the order the words were first seen
+pub type WordFreqMap = IndexMap<TextVec, usize>;
+
+fn word_counts<'a>(text: &'a TextSlice, config: &Config) -> SliceCounts<'a> {
+    let mut counts = SliceCounts::default();
+    counts.insert_all_words(text, config);
+    counts
+}
+
+// A `WordFreqSet` counts the words of its operands, split at whitespace as
+// `WordUnionSet` splits them. The union adds up each word's counts across the
+// operands; the intersection keeps only the words in every operand, each with
+// its count in the operand where it's least frequent, like a multiset.
+//
+// `finish` writes each word, a tab and its count on a line, the most frequent
+// words first, and words with the same count in the order they were first
+// seen. Filters see just the word, without its count.
+//
+pub struct WordFreqSet {
+    counts: WordFreqMap,
+    intersect: bool,
+    lines: LineBuffer,
+}
+
+impl WordFreqSet {
+    pub fn init_union(text: &TextSlice, config: &Config) -> Self {
+        let mut set = WordFreqSet { counts: WordFreqMap::new(), intersect: false, lines: LineBuffer::default() };
+        set.operate(text, config);
+        set
+    }
+
+    pub fn init_intersect(text: &TextSlice, config: &Config) -> Self {
+        let counts = word_counts(text, config).into_iter().map(|(word, n)| (word.to_vec(), n)).collect();
+        WordFreqSet { counts, intersect: true, lines: LineBuffer::default() }
+    }
+
+    // The words and their counts, in the order they'll be written once
+    // `finish` has sorted them
+    pub fn counts(&self) -> &WordFreqMap {
+        &self.counts
+    }
+
+    fn write_lines(&mut self) {
+        self.lines = LineBuffer::default();
+        for (word, count) in &self.counts {
+            self.lines.push(&[word, b"\t", count.to_string().as_bytes()]);
+        }
+    }
+}
+
+impl<'data> SetExpression<'data> for WordFreqSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        let other = word_counts(text, config);
+        if self.intersect {
+            self.counts.retain(|word, count| match other.get(word.as_slice()) {
+                Some(&n) => {
+                    *count = (*count).min(n);
+                    true
+                }
+                None => false,
+            });
+            return;
+        }
+        for (word, n) in other {
+            match self.counts.get_mut(word) {
+                Some(count) => *count += n,
+                None => {
+                    self.counts.insert(word.to_vec(), n);
+                }
+            }
+        }
+    }
+    fn is_settled(&self) -> bool {
+        self.intersect && self.counts.is_empty()
+    }
+    // The sort is stable, so ties stay in first-seen order
+    fn finish(&mut self) -> io::Result<()> {
+        self.counts.sort_by(|_, a, _, b| b.cmp(a));
+        self.write_lines();
+        Ok(())
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.lines.iter())
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.counts.retain(|word, _| keep(word));
+        // If `finish` has already run, its output needs the same filtering
+        if !self.lines.is_empty() {
+            self.write_lines();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn union_adds_up_the_counts_most_frequent_first() {
+        let config = Config::default();
+        let mut set = WordFreqSet::init_union(b"the cat  sat\non the mat\n", &config);
+        set.operate(b"the\tdog sat\n", &config);
+        set.finish().unwrap();
+        assert_eq!(set.counts()[&b"the"[..]], 3);
+        assert_eq!(lines(&set), [&b"the\t3"[..], b"sat\t2", b"cat\t1", b"on\t1", b"mat\t1", b"dog\t1"]);
+    }
+
+    #[test]
+    fn intersection_keeps_the_least_count_of_words_in_every_operand() {
+        let config = Config::default();
+        let mut set = WordFreqSet::init_intersect(b"a a a b b c\n", &config);
+        set.operate(b"b b b a c c\n", &config);
+        set.operate(b"a b b\n", &config);
+        set.finish().unwrap();
+        assert_eq!(lines(&set), [&b"b\t2"[..], b"a\t1"]);
+        set.operate(b"d\n", &config);
+        assert!(set.is_settled());
+    }
+
+    #[test]
+    fn filters_see_the_word_without_its_count() {
+        let config = Config::default();
+        let mut set = WordFreqSet::init_union(b"x yy yy\n", &config);
+        set.finish().unwrap();
+        set.retain_lines(|word| word.len() == 1);
+        assert_eq!(lines(&set), [&b"x\t1"[..]]);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{Config, IntersectSet, LineSet, SetExpression, SliceSet, TextSlice, UnionSet};
+
+// Word sets work like `UnionSet` and `IntersectSet`, but their members are
+// the whitespace-separated words of each operand rather than its lines. A word
+// that occurs several times in one file is still just one member.
+//
+#[derive(Default)]
+pub struct WordUnionSet(UnionSet);
+
+impl WordUnionSet {
+    pub fn init(text: &TextSlice, config: &Config) -> Self {
+        let mut set = Self::default();
+        set.operate(text, config);
+        set
+    }
+}
+
+impl<'data> SetExpression<'data> for WordUnionSet {
+    fn operate(&mut self, text: &'data TextSlice, config: &Config) {
+        self.0.insert_all_words(text, config);
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.iter().map(|v| v.as_slice()))
+    }
+    fn result_len(&self) -> usize {
+        self.0.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.retain(|line| keep(line));
+    }
+}
+
+// As with `IntersectSet`, the members are borrowed from the first operand
+#[derive(Default)]
+pub struct WordIntersectSet<'data>(IntersectSet<'data>);
+
+impl<'data> WordIntersectSet<'data> {
+    pub fn init(text: &'data TextSlice, config: &Config) -> Self {
+        let mut set = Self::default();
+        set.0.insert_all_words(text, config);
+        set
+    }
+}
+
+impl<'data, 'other> SetExpression<'other> for WordIntersectSet<'data> {
+    fn operate(&mut self, text: &'other TextSlice, config: &Config) {
+        let mut other = SliceSet::default();
+        other.insert_all_words(text, config);
+        self.0.retain(|x| other.contains(x));
+    }
+    fn is_settled(&self) -> bool {
+        self.0.is_empty()
+    }
+    fn result_lines<'me>(&'me self) -> Box<dyn Iterator<Item = &'me TextSlice> + 'me> {
+        Box::new(self.0.iter().cloned())
+    }
+    fn result_len(&self) -> usize {
+        self.0.len()
+    }
+    fn retain_lines(&mut self, mut keep: impl FnMut(&TextSlice) -> bool) {
+        self.0.retain(|line| keep(line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words<'data>(set: &impl SetExpression<'data>) -> Vec<&TextSlice> {
+        set.result_lines().collect()
+    }
+
+    #[test]
+    fn union_has_every_word_once_in_first_seen_order() {
+        let config = Config::default();
+        let mut set = WordUnionSet::init(b"the cat\tsat\n  the  end", &config);
+        set.operate(b"a cat\n", &config);
+        let expected: [&[u8]; 5] = [b"the", b"cat", b"sat", b"end", b"a"];
+        assert_eq!(words(&set), expected);
+    }
+
+    #[test]
+    fn intersection_has_the_words_in_every_operand() {
+        let config = Config::default();
+        let first = b"one two\tthree\nfour two".to_vec();
+        let mut set = WordIntersectSet::init(&first, &config);
+        set.operate(b"four\n\nthree one\n", &config);
+        set.operate(b"x three four", &config);
+        let expected: [&[u8]; 2] = [b"three", b"four"];
+        assert_eq!(words(&set), expected);
+        set.operate(b"five", &config);
+        assert!(set.is_settled());
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+
+use crate::{Config, LineSet, TextVec, UnionSet};
+
+// A `UnionSetWriter` builds a `UnionSet` from whatever is written to it, so
+// that `io::copy` can feed it from any reader. (`UnionSet` is `IndexSet`'s own
+// type, so it can't implement `Write` itself.) Bytes are held until a whole
+// line or record has arrived, then split and inserted just as
+// `insert_all_lines` would.
+//
+// `flush` inserts whatever is left as a last line, even without its
+// delimiter, and so does `into_set`. Whoever writes should flush only at the
+// end of the input: a line flushed in two parts is two lines.
+//
+pub struct UnionSetWriter {
+    set: UnionSet,
+    pending: TextVec,
+    config: Config,
+}
+
+impl UnionSetWriter {
+    pub fn new(config: &Config) -> Self {
+        UnionSetWriter { set: UnionSet::default(), pending: TextVec::new(), config: config.clone() }
+    }
+
+    pub fn into_set(mut self) -> UnionSet {
+        self.insert_pending();
+        self.set
+    }
+
+    fn insert_pending(&mut self) {
+        if !self.pending.is_empty() {
+            self.set.insert_all_lines(&self.pending, &self.config);
+            self.pending.clear();
+        }
+    }
+
+    // How many of the pending bytes make up whole lines or records
+    fn complete(&self) -> usize {
+        match self.config.record_size {
+            Some(size) => self.pending.len() / size * size,
+            None => memchr::memrchr(self.config.delimiter, &self.pending).map_or(0, |end| end + 1),
+        }
+    }
+}
+
+impl Write for UnionSetWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let complete = self.complete();
+        if complete > 0 {
+            self.set.insert_all_lines(&self.pending[..complete], &self.config);
+            self.pending.drain(..complete);
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.insert_pending();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::UnionSetExt;
+
+    #[test]
+    fn io_copy_feeds_the_set_a_line_at_a_time() {
+        let mut writer = UnionSetWriter::new(&Config::default());
+        io::copy(&mut Cursor::new(&b"b\na\nb\nc"[..]), &mut writer).unwrap();
+        assert_eq!(writer.into_set(), UnionSet::from_lines(["b", "a", "c"]));
+    }
+
+    #[test]
+    fn lines_split_across_writes_are_joined() {
+        let mut writer = UnionSetWriter::new(&Config::default());
+        for piece in [&b"fi"[..], b"rst\nsec", b"ond\n", b"\nthi"] {
+            writer.write_all(piece).unwrap();
+        }
+        assert_eq!(writer.into_set(), UnionSet::from_lines(["first", "second", "", "thi"]));
+    }
+
+    #[test]
+    fn flush_inserts_the_pending_partial_line() {
+        let mut writer = UnionSetWriter::new(&Config::default());
+        writer.write_all(b"one\ntwo").unwrap();
+        assert_eq!(writer.set, UnionSet::from_lines(["one"]));
+        writer.flush().unwrap();
+        assert_eq!(writer.set, UnionSet::from_lines(["one", "two"]));
+        assert!(writer.pending.is_empty());
+        writer.flush().unwrap();
+        assert_eq!(writer.into_set(), UnionSet::from_lines(["one", "two"]));
+    }
+}
@@ -0,0 +1,531 @@
+// Run the `minimal` binary on small files and check what it writes
+
+use std::{
+    fs,
+    process::{Command, Output},
+};
+
+use tempfile::TempDir;
+
+// Write `files` into a fresh directory and run `minimal` there with `args`,
+// so the files can be named as they are
+fn run(files: &[(&str, &str)], args: &[&str]) -> Output {
+    let dir = TempDir::new().expect("can't make a temporary directory");
+    for (name, text) in files {
+        fs::write(dir.path().join(name), text).expect("can't write a test file");
+    }
+    Command::new(env!("CARGO_BIN_EXE_minimal")).args(args).current_dir(dir.path()).output().expect("can't run minimal")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn estimate_union_prints_about_the_number_of_distinct_lines() {
+    let output = run(&[("a", "1\n2\n3\n"), ("b", "3\n4\n")], &["--estimate-union", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "4\n");
+}
+
+#[test]
+fn ignore_trailing_whitespace_matches_trimmed_lines_and_writes_them_trimmed() {
+    let files = [("a", "hello   \nworld\n"), ("b", "hello\t\n")];
+    assert_eq!(stdout(&run(&files, &["intersect", "a", "b"])), "");
+    assert_eq!(stdout(&run(&files, &["-W", "intersect", "a", "b"])), "hello\n");
+    assert_eq!(stdout(&run(&files, &["--ignore-trailing-whitespace", "union", "a", "b"])), "hello\nworld\n");
+}
+
+#[test]
+fn external_union_is_sorted_and_distinct_whatever_the_chunk_size() {
+    let files = [("a", "pear\napple\nfig\napple\n"), ("b", "fig\nkiwi\n")];
+    for chunk_size in ["1", "8", "1000"] {
+        let output = run(&files, &["--external", "--chunk-size", chunk_size, "union", "a", "b"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(stdout(&output), "apple\nfig\nkiwi\npear\n");
+    }
+}
+
+#[test]
+fn check_takes_only_files_and_counts_their_lines() {
+    let output = run(&[("a", "1\n2\n3\n"), ("b", "x\n")], &["--check", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "a: 3 lines\nb: 1 lines\n");
+}
+
+#[test]
+fn check_fails_on_a_file_that_cannot_be_read() {
+    let output = run(&[("a", "1\n")], &["--check", "a", "missing"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "a: 1 lines\n");
+    assert!(stderr(&output).contains("missing"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("1 file could not be read"), "{}", stderr(&output));
+}
+
+#[test]
+fn intersection_stops_reading_files_once_it_is_empty() {
+    let files = [("a", "1\n2\n"), ("b", "3\n")];
+    let output = run(&files, &["intersect", "a", "b", "missing"]);
+    assert_eq!(stdout(&output), "");
+    assert!(!stderr(&output).contains("missing"), "{}", stderr(&output));
+    let output = run(&files, &["multiset-intersect", "a", "b", "missing"]);
+    assert!(!stderr(&output).contains("missing"), "{}", stderr(&output));
+    // While there are lines left, a missing file is still an error
+    let output = run(&files, &["intersect", "a", "a", "missing"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("missing"), "{}", stderr(&output));
+}
+
+#[test]
+fn words_makes_union_and_intersect_work_on_words() {
+    let files = [("a", "red green\nblue red\n"), ("b", "green\tyellow blue\n")];
+    assert_eq!(stdout(&run(&files, &["--words", "intersect", "a", "b"])), "green\nblue\n");
+    assert_eq!(stdout(&run(&files, &["-w", "union", "a", "b"])), "red\ngreen\nblue\nyellow\n");
+    assert!(!run(&files, &["--words", "diff", "a", "b"]).status.success());
+}
+
+#[test]
+fn source_prefixes_each_union_line_with_its_first_file() {
+    let files = [("a", "x\ny\n"), ("b", "y\nz\n")];
+    let output = run(&files, &["--source", "union", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "0\tx\n0\ty\n1\tz\n");
+}
+
+#[test]
+fn min_and_max_length_filter_the_result() {
+    let files = [("a", "a\nbb\nccc\ndddd\n")];
+    assert_eq!(stdout(&run(&files, &["--min-length", "2", "--max-length", "3", "union", "a"])), "bb\nccc\n");
+    assert_eq!(stdout(&run(&files, &["--max-length", "0", "union", "a"])), "");
+}
+
+#[test]
+fn key_width_compares_only_the_start_of_each_line() {
+    let files = [("a", "id1 apple\nid2 pear\n"), ("b", "id1 banana\n")];
+    assert_eq!(stdout(&run(&files, &["--key-width", "3", "intersect", "a", "b"])), "id1 apple\n");
+    assert_eq!(stdout(&run(&files, &["--key-width", "3", "union", "b", "a"])), "id1 banana\nid2 pear\n");
+}
+
+#[test]
+fn to_lower_folds_ascii_letters_and_leaves_accented_ones() {
+    let files = [("a", "Café\nÉté\n"), ("b", "CAFé\nété\n")];
+    assert_eq!(stdout(&run(&files, &["--to-lower", "intersect", "a", "b"])), "café\n");
+    assert_eq!(stdout(&run(&files, &["--to-lower", "--unicode-case", "intersect", "a", "b"])), "café\nété\n");
+}
+
+#[test]
+fn max_line_length_rejects_or_truncates_long_lines() {
+    let files = [("a", "short\nmuch too long\n")];
+    let output = run(&files, &["--max-line-length", "5", "union", "a"]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("a: line 2 is too long (13 bytes)"), "{}", stderr(&output));
+    let output = run(&files, &["--max-line-length", "5", "--on-long-line", "truncate", "union", "a"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "short\nmuch \n");
+    let output = run(&files, &["--max-line-length", "5", "--on-long-line", "skip", "union", "a"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "short\n");
+}
+
+#[test]
+fn long_lines_are_reported_alike_by_every_way_of_reading() {
+    let files = [("a", "head\nok\n"), ("b", "x\nfine\nmuch too long\n")];
+    let expected = "b: line 3 is too long (13 bytes)";
+    for args in [&["union", "a", "b"][..], &["--external", "union", "a", "b"], &["--external", "--chunk-size", "3", "union", "a", "b"]] {
+        let args: Vec<_> = ["--max-line-length", "5"].iter().chain(args).copied().collect();
+        let output = run(&files, &args);
+        assert!(!output.status.success());
+        assert!(stderr(&output).contains(expected), "{:?}: {}", args, stderr(&output));
+    }
+    let output = run(&files, &["--max-line-length", "5", "--header", "union", "a", "b"]);
+    assert!(stderr(&output).contains(expected), "{}", stderr(&output));
+}
+
+#[test]
+fn on_long_line_needs_max_line_length() {
+    let output = run(&[("a", "x\n")], &["--on-long-line", "skip", "union", "a"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("needs --max-line-length"), "{}", stderr(&output));
+}
+
+#[test]
+fn sorted_set_writes_the_union_sorted() {
+    let files = [("a", "c\na\n"), ("b", "b\na\n")];
+    assert_eq!(stdout(&run(&files, &["--sorted-set", "union", "a", "b"])), "a\nb\nc\n");
+    assert!(!run(&files, &["--sorted-set", "intersect", "a", "b"]).status.success());
+}
+
+#[test]
+fn exclusive_union_writes_lines_in_exactly_one_file() {
+    let files = [("a", "1\n2\n"), ("b", "2\n3\n"), ("c", "3\n4\n")];
+    assert_eq!(stdout(&run(&files, &["--exclusive", "union", "a", "b", "c"])), "1\n4\n");
+    assert_eq!(stdout(&run(&files, &["exclusive-union", "a", "b", "c"])), "1\n4\n");
+}
+
+#[test]
+fn complement_writes_universe_lines_in_no_other_file() {
+    let files = [("u", "1\n2\n3\n4\n"), ("a", "2\n"), ("b", "4\n5\n")];
+    assert_eq!(stdout(&run(&files, &["--complement", "u", "a", "b"])), "1\n3\n");
+    assert_eq!(stdout(&run(&files, &["complement", "u", "a", "b"])), "1\n3\n");
+}
+
+#[test]
+fn comm_writes_three_columns_and_suppress_leaves_some_out() {
+    let files = [("a", "only1\nboth\n"), ("b", "both\nonly2\n")];
+    assert_eq!(stdout(&run(&files, &["comm", "a", "b"])), "only1\n\t\tboth\n\tonly2\n");
+    assert_eq!(stdout(&run(&files, &["--suppress", "12", "comm", "a", "b"])), "both\n");
+    assert_eq!(stdout(&run(&files, &["--suppress", "1", "--suppress", "3", "comm", "a", "b"])), "only2\n");
+    assert!(!run(&files, &["--suppress", "4", "comm", "a", "b"]).status.success());
+}
+
+#[test]
+fn concat_of_one_file_reproduces_it() {
+    let text = "z\na\nz\n\n  spaced  \n";
+    assert_eq!(stdout(&run(&[("a", text)], &["concat", "a"])), text);
+    assert_eq!(stdout(&run(&[("a", "1\n"), ("b", "1\n2")], &["concat", "a", "b"])), "1\n1\n2\n");
+}
+
+#[test]
+fn ndjson_writes_each_line_as_a_json_string() {
+    let output = run(&[("a", "x\n\"y\"\n")], &["--ndjson", "union", "a"]);
+    assert_eq!(stdout(&output), "\"x\"\n\"\\\"y\\\"\"\n");
+}
+
+#[test]
+fn invert_match_flips_not_in_all() {
+    let files = [("a", "1\n2\n3\n"), ("b", "1\n2\n"), ("c", "2\n3\n")];
+    assert_eq!(stdout(&run(&files, &["not-in-all", "a", "b", "c"])), "1\n3\n");
+    assert_eq!(stdout(&run(&files, &["-v", "not-in-all", "a", "b", "c"])), "2\n");
+}
+
+#[test]
+fn directory_operates_on_the_files_under_it() {
+    let files = [("a.txt", "1\n2\n"), ("b.txt", "2\n3\n"), ("c.log", "2\n")];
+    assert_eq!(stdout(&run(&files, &["--directory", ".", "--extension", ".txt", "union"])), "1\n2\n3\n");
+}
+
+#[test]
+fn ignore_field_order_matches_lines_with_the_same_fields() {
+    let files = [("a", "x\ty\tz\nq\n"), ("b", "z\tx\ty\n")];
+    assert_eq!(stdout(&run(&files, &["--ignore-field-order", "intersect", "a", "b"])), "x\ty\tz\n");
+    let files = [("a", "x;y\n"), ("b", "y;x\n")];
+    assert_eq!(stdout(&run(&files, &["--ignore-field-order", "--fd", ";", "union", "a", "b"])), "x;y\n");
+}
+
+#[test]
+fn rolling_window_writes_each_windows_union() {
+    let files = [("1", "a\n"), ("2", "b\n"), ("3", "a\nc\n")];
+    let output = run(&files, &["--rolling-window", "2", "union", "1", "2", "3"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "a\nb\n\nb\na\nc\n");
+}
+
+#[test]
+fn header_writes_the_first_files_header_once_and_leaves_headers_out_of_the_sets() {
+    let files = [("a.csv", "id,name\n1,ann\n2,bo\n"), ("b.csv", "ID,NAME\n2,bo\n3,cy\n")];
+    assert_eq!(stdout(&run(&files, &["--header", "union", "a.csv", "b.csv"])), "id,name\n1,ann\n2,bo\n3,cy\n");
+    assert_eq!(stdout(&run(&files, &["--header", "intersect", "a.csv", "b.csv"])), "id,name\n2,bo\n");
+    let files = [("a.csv", "# export\nid\n1\n"), ("b.csv", "# export\nid\n1\n2\n")];
+    assert_eq!(stdout(&run(&files, &["--header-lines", "2", "complement", "b.csv", "a.csv"])), "# export\nid\n2\n");
+}
+
+#[test]
+fn cover_prints_the_chosen_file_names() {
+    let files = [("a", "1\n"), ("b", "1\n2\n3\n"), ("c", "3\n4\n")];
+    let output = run(&files, &["--cover", "a", "b", "c"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "b\nc\n");
+}
+
+#[test]
+fn group_by_field_writes_each_groups_lines_under_its_key() {
+    let files = [("a", "x\t1\ny\t2\n"), ("b", "x\t3\nx\t1\n")];
+    assert_eq!(stdout(&run(&files, &["--group-by-field", "1", "union", "a", "b"])), "x\n\tx\t1\n\tx\t3\ny\n\ty\t2\n");
+}
+
+#[test]
+fn field_intersects_by_the_chosen_fields_with_any_delimiter() {
+    let tabs = [("a", "chr1\t100\tx\nchr2\t200\ty\nchr3\t5\tz\n"), ("b", "chr2\t200\tq\nchr1\t100\tw\nchr3\t6\tz\n")];
+    assert_eq!(stdout(&run(&tabs, &["--field", "1,2", "intersect", "a", "b"])), "chr1\t100\tx\nchr2\t200\ty\n");
+    assert_eq!(stdout(&run(&tabs, &["--fd", "\\t", "--field", "3", "intersect", "a", "b"])), "chr3\t5\tz\n");
+    let spaces = [("a", "a b c\nd e f\n"), ("b", "x b c\nd e g\n")];
+    assert_eq!(stdout(&run(&spaces, &["--fd", " ", "--field", "2", "intersect", "a", "b"])), "a b c\nd e f\n");
+    assert_eq!(stdout(&run(&spaces, &["--fd", " ", "--field", "3", "intersect", "a", "b"])), "a b c\n");
+    let colons = [("a", "root:x:0\nbin:x:1\n"), ("b", "daemon:y:1\n")];
+    assert_eq!(stdout(&run(&colons, &["--field-delimiter", ":", "--field", "3", "intersect", "a", "b"])), "bin:x:1\n");
+}
+
+// The demo's operands and the results the first-seen order gives them, which
+// no change of hasher or set type should alter
+const TXT_A: &str = include_str!("fixtures/txt_a");
+const TXT_B: &str = include_str!("fixtures/txt_b");
+const UNION: &str = include_str!("fixtures/union");
+const INTERSECTION: &str = include_str!("fixtures/intersection");
+
+#[test]
+fn union_and_intersection_keep_first_seen_order() {
+    let files = [("txt_a", TXT_A), ("txt_b", TXT_B)];
+    assert_eq!(stdout(&run(&files, &["union", "txt_a", "txt_b"])), UNION);
+    assert_eq!(stdout(&run(&files, &["intersect", "txt_a", "txt_b"])), INTERSECTION);
+}
+
+#[test]
+fn the_demo_writes_the_fixed_union_and_intersection() {
+    let expected = format!("\nUnion =========================\n{}\nIntersection =========================\n{}", UNION, INTERSECTION);
+    assert_eq!(stdout(&run(&[], &[])), expected);
+}
+
+#[test]
+fn cache_answers_from_the_stored_result_until_a_file_changes() {
+    let dir = TempDir::new().unwrap();
+    let minimal = || Command::new(env!("CARGO_BIN_EXE_minimal")).args(["--cache", "union", "a", "b"]).current_dir(dir.path()).output().unwrap();
+    fs::write(dir.path().join("a"), "x\n").unwrap();
+    fs::write(dir.path().join("b"), "y\n").unwrap();
+    assert_eq!(stdout(&minimal()), "x\ny\n");
+    // A result that the calculation would never give shows the cache was used
+    let cache = dir.path().join(".setop_cache");
+    let entries = fs::read_to_string(&cache).unwrap();
+    assert!(entries.ends_with("x\ny\n"));
+    fs::write(&cache, entries.replace("x\ny\n", "c\nd\n")).unwrap();
+    assert_eq!(stdout(&minimal()), "c\nd\n");
+    fs::write(dir.path().join("b"), "z\n").unwrap();
+    assert_eq!(stdout(&minimal()), "x\nz\n");
+}
+
+#[test]
+fn print_stats_writes_the_counts_to_stderr_after_the_result() {
+    let output = run(&[("a", "a\nb\na\n"), ("b", "b\nc\n")], &["--print-stats", "union", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "a\nb\nc\n");
+    let stats = stderr(&output);
+    assert!(stats.starts_with("input_line_counts\t3 2\ninput_unique_counts\t2 2\nresult_line_count\t3\nbytes_processed\t10\nelapsed_ms\t"), "{}", stats);
+}
+
+#[test]
+fn expr_evaluates_a_nested_expression_over_files() {
+    let files = [("a.txt", "1\n2\n3\n"), ("b.txt", "3\n4\n"), ("c.txt", "2\n4\n5\n")];
+    assert_eq!(stdout(&run(&files, &["--expr", "(a.txt + b.txt) x c.txt"])), "2\n4\n");
+    assert_eq!(stdout(&run(&files, &["--expr", "a.txt + b.txt x c.txt"])), "1\n2\n3\n4\n");
+    let output = run(&files, &["--expr", "(a.txt + b.txt"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("bad expression"), "{}", stderr(&output));
+}
+
+#[test]
+fn print_files_labels_lines_with_the_files_that_have_them() {
+    let files = [("logfile1.txt", "hello\nboth\n"), ("logfile2.txt", "both\nworld\n")];
+    let union = run(&files, &["-F", "union", "logfile1.txt", "logfile2.txt"]);
+    assert_eq!(stdout(&union), "logfile1.txt\thello\nlogfile1.txt\tboth\nlogfile2.txt\tworld\n");
+    let intersection = run(&files, &["--print-files", "intersect", "logfile1.txt", "logfile2.txt"]);
+    assert_eq!(stdout(&intersection), "logfile1.txt,logfile2.txt\tboth\n");
+}
+
+#[test]
+fn cross_pairs_the_lines_of_two_files() {
+    let files = [("a", "x\ny\n"), ("b", "1\n2\n3\n")];
+    let output = run(&files, &["--cross", "a", "b"]);
+    assert_eq!(stdout(&output), "x\t1\nx\t2\nx\t3\ny\t1\ny\t2\ny\t3\n");
+    assert_eq!(stderr(&output), "");
+    assert_eq!(stdout(&run(&files, &["--cross", "--separator", ",", "a", "b"])).lines().count(), 6);
+}
+
+#[test]
+fn cross_warns_about_more_than_a_million_pairs() {
+    let many: String = (0..1001).map(|n| format!("{}\n", n)).collect();
+    let output = run(&[("a", &many), ("b", &many)], &["-q", "--cross", "a", "b"]);
+    assert!(stderr(&output).contains("the cross product has 1002001 lines"), "{}", stderr(&output));
+}
+
+#[test]
+fn map_delimiter_merges_the_values_of_each_key() {
+    let files = [("a", "u1:a\nu2:b\n"), ("b", "u2:c\nu3:d\n")];
+    assert_eq!(stdout(&run(&files, &["--map-delimiter", ":", "union", "a", "b"])), "u1:a\nu2:b\nu3:d\n");
+    assert_eq!(stdout(&run(&files, &["--map-delimiter", ":", "--map-merge", "last", "intersect", "a", "b"])), "u2:c\n");
+    assert_eq!(stdout(&run(&files, &["--map-delimiter", ":", "--map-merge", "append:;", "union", "a", "b"])), "u1:a\nu2:b;c\nu3:d\n");
+}
+
+#[test]
+fn word_frequency_counts_words_across_files() {
+    let files = [("a", "to be or not\n"), ("b", "to be is to do\n")];
+    assert_eq!(stdout(&run(&files, &["--word-frequency", "union", "a", "b"])), "to\t3\nbe\t2\nor\t1\nnot\t1\nis\t1\ndo\t1\n");
+    assert_eq!(stdout(&run(&files, &["--word-frequency", "intersect", "a", "b"])), "to\t1\nbe\t1\n");
+}
+
+#[test]
+fn no_final_newline_leaves_no_byte_after_the_last_line() {
+    let files = [("a", "a\nb\n"), ("b", "b\nc\n")];
+    for args in [&["-n", "union", "a", "b"][..], &["--no-final-newline", "--stream", "union", "a", "b"], &["-n", "--sorted", "union", "a", "b"]] {
+        let output = run(&files, args);
+        assert!(output.status.success(), "{:?}: {}", args, stderr(&output));
+        assert_eq!(stdout(&output), "a\nb\nc", "{:?}", args);
+    }
+}
+
+#[test]
+fn skip_and_limit_write_a_window_of_the_result() {
+    let numbers: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+    let files = [("a", numbers.as_str())];
+    assert_eq!(stdout(&run(&files, &["--skip", "5", "--limit", "3", "union", "a"])), "6\n7\n8\n");
+    assert_eq!(stdout(&run(&files, &["--skip", "18", "union", "a"])), "19\n20\n");
+    assert_eq!(stdout(&run(&files, &["--skip", "25", "union", "a"])), "");
+    assert_eq!(stdout(&run(&files, &["--limit", "2", "union", "a"])), "1\n2\n");
+}
+
+#[test]
+fn hashed_shards_have_every_line_between_them() {
+    let lines: String = (0..40).map(|n| format!("line {}\n", n)).collect();
+    let files = [("a", lines.as_str())];
+    let count = |shard: &str| stdout(&run(&files, &["--hash", "--shard", shard, "union", "a"])).lines().count();
+    assert_eq!(count("0/2") + count("1/2"), 40);
+    assert_eq!(count("0/1"), 40);
+}
+
+#[test]
+fn print_stats_counts_every_line_of_a_sharded_operand() {
+    let output = run(&[("a", "a\nb\nc\na\n")], &["--print-stats", "--shard", "0/2", "union", "a"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).starts_with("input_line_counts\t4\ninput_unique_counts\t3\n"), "{}", stderr(&output));
+}
+
+#[test]
+fn delimiter_takes_a_hex_byte() {
+    let files = [("a", "x\x1ey\x1ex\x1e"), ("b", "y\x1ez\x1e")];
+    assert_eq!(stdout(&run(&files, &["--delimiter", "0x1e", "union", "a", "b"])), "x\x1ey\x1ez\x1e");
+    assert_eq!(stdout(&run(&files, &["--delimiter", "30", "intersect", "a", "b"])), "y\x1e");
+    assert!(!run(&files, &["--delimiter", "0x1e1", "union", "a", "b"]).status.success());
+}
+
+#[test]
+fn master_file_comes_first_wherever_it_is_given() {
+    let files = [("m", "c\na\nb\n"), ("x", "b\nc\n"), ("y", "d\nc\nb\n")];
+    assert_eq!(stdout(&run(&files, &["complement", "x", "m", "y"])), "");
+    for order in [["m", "x", "y"], ["x", "m", "y"], ["x", "y", "m"]] {
+        let args = |op| vec!["--master", "m", op, order[0], order[1], order[2]];
+        assert_eq!(stdout(&run(&files, &args("intersect"))), "c\nb\n");
+        assert_eq!(stdout(&run(&files, &args("complement"))), "a\n");
+        assert_eq!(stdout(&run(&files, &args("union"))), stdout(&run(&files, &["union", "m", "x", "y"])));
+    }
+    assert_eq!(stdout(&run(&files, &["--master", "m", "complement", "x"])), "a\n");
+}
+
+#[test]
+fn print_positions_writes_every_place_each_common_line_occurs() {
+    let files = [("a", "x\ny\nx\nz\n"), ("b", "z\nx\n")];
+    let output = run(&files, &["--print-positions", "intersect", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "x:0:1\nx:0:3\nx:1:2\nz:0:4\nz:1:1\n");
+}
+
+#[test]
+fn check_unique_names_the_first_repeated_line_and_how_many_repeat() {
+    let files = [("a", "x\ny\ny\nx\ny\n"), ("b", "z\n")];
+    let output = run(&files, &["--check-unique", "union", "a", "b"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("a: 3 repeated lines, the first being y"), "{}", stderr(&output));
+    let output = run(&files, &["--check-unique", "union", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "z\n");
+}
+
+#[test]
+fn numeric_sort_orders_by_leading_number() {
+    let files = [("a", "10\nfoo\n-3\n2 apples\n"), ("b", "2\nbar\n")];
+    let output = run(&files, &["--numeric-sort", "union", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "foo\nbar\n-3\n2 apples\n2\n10\n");
+    let output = run(&files, &["--numeric-sort", "--non-numeric-last", "union", "a", "b"]);
+    assert_eq!(stdout(&output), "-3\n2 apples\n2\n10\nfoo\nbar\n");
+}
+
+#[test]
+fn sorted_files_are_merged_a_line_at_a_time() {
+    let files = [("a", "apple\nfig\nfig\nkiwi\npear\n"), ("b", "fig\nkiwi\nplum\n")];
+    for (op, expected) in [("union", "apple\nfig\nkiwi\npear\nplum\n"), ("intersect", "fig\nkiwi\n"), ("complement", "apple\npear\n")] {
+        let output = run(&files, &["--sorted", op, "a", "b"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(stdout(&output), expected, "{}", op);
+    }
+}
+
+#[test]
+fn stream_writes_the_same_union() {
+    let files = [("a", "pear\napple\npear\n"), ("b", "fig\napple\nkiwi")];
+    let output = run(&files, &["--stream", "union", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), stdout(&run(&files, &["union", "a", "b"])));
+    assert_eq!(stdout(&output), "pear\napple\nfig\nkiwi\n");
+}
+
+#[test]
+fn diff3_merges_the_changes_and_marks_conflicts() {
+    let files = [("base", "1\n2\n3\n4\n"), ("a", "0\n1\nx\n3\n4\n"), ("b", "1\ny\n3\n")];
+    let expected = "0\n1\n<<<<<<< A\nx\n||||||| base\n2\n=======\ny\n>>>>>>> B\n3\n";
+    assert_eq!(stdout(&run(&files, &["diff3", "base", "a", "b"])), expected);
+    let output = run(&files, &["diff3", "--b", "b", "--base", "base", "--a", "a"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), expected);
+}
+
+#[test]
+fn skipping_the_whole_result_leaves_it_empty() {
+    let files = [("a", "1\n2\n3\n")];
+    for skip in ["3", "10"] {
+        let output = run(&files, &["--skip", skip, "--print-empty-marker", "(none)", "union", "a"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(stdout(&output), "(none)\n");
+        assert_eq!(run(&files, &["--skip", skip, "--quiet", "union", "a"]).status.code(), Some(1));
+    }
+    assert_eq!(stdout(&run(&files, &["--skip", "2", "--print-empty-marker", "(none)", "union", "a"])), "3\n");
+    assert_eq!(run(&files, &["--skip", "2", "--quiet", "union", "a"]).status.code(), Some(0));
+    assert_eq!(run(&files, &["--limit", "0", "--quiet", "union", "a"]).status.code(), Some(1));
+}
+
+#[cfg(feature = "sampling")]
+#[test]
+fn a_seeded_sample_is_the_same_every_run() {
+    let numbers: String = (1..=100).map(|n| format!("{}\n", n)).collect();
+    let files = [("a", numbers.as_str())];
+    let sample = |args: &[&str]| {
+        let output = run(&files, &[args, &["union", "a"]].concat());
+        assert!(output.status.success(), "{}", stderr(&output));
+        stdout(&output)
+    };
+    let by_count = sample(&["--sample", "10", "--seed", "5"]);
+    assert_eq!(by_count.lines().count(), 10);
+    assert_eq!(by_count, sample(&["--sample", "10", "--seed", "5"]));
+    assert!(by_count.lines().all(|line| numbers.lines().any(|n| n == line)));
+    assert_eq!(sample(&["--sample", "0.25", "--seed", "5"]).lines().count(), 25);
+    // The sample is taken in the result's order, then sorted
+    let shuffled = [("a", "30\n4\n200\n17\n9\n1000\n")];
+    let output = run(&shuffled, &["--sample", "4", "--seed", "5", "--numeric-sort", "union", "a"]);
+    let sorted: Vec<i64> = stdout(&output).lines().map(|line| line.parse().unwrap()).collect();
+    assert_eq!(sorted.len(), 4);
+    assert!(sorted.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", sorted);
+    let output = run(&shuffled, &["--sample", "4", "--seed", "5", "--sorted-set", "union", "a"]);
+    let lines: Vec<String> = stdout(&output).lines().map(String::from).collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", lines);
+}
+
+#[cfg(not(feature = "sampling"))]
+#[test]
+fn sample_needs_the_sampling_feature() {
+    let output = run(&[("a", "1\n")], &["--sample", "1", "union", "a"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("sampling feature"), "{}", stderr(&output));
+}
+
+#[test]
+fn trim_cr_only_strips_carriage_returns_before_newlines_but_not_nuls() {
+    let files = [("a", "x\r\ny \r\n"), ("b", "x\ny \n")];
+    assert_eq!(stdout(&run(&files, &["--trim-cr-only", "intersect", "a", "b"])), "x\ny \n");
+    let files = [("a", "x\r\0y\rz\0"), ("b", "x\0y\rz\0")];
+    let output = run(&files, &["--trim-cr-only", "--delimiter", "\\0", "intersect", "a", "b"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "y\rz\0");
+}